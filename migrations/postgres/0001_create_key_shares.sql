@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS key_shares (
+    node_id BIGINT NOT NULL,
+    session_id TEXT NOT NULL,
+    share BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (node_id, session_id)
+);
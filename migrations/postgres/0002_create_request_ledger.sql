@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS request_ledger (
+    node_id BIGINT NOT NULL,
+    request_id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    request TEXT NOT NULL,
+    status TEXT NOT NULL,
+    result TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (node_id, request_id)
+);
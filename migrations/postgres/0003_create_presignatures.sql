@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS presignatures (
+    node_id BIGINT NOT NULL,
+    session_id TEXT NOT NULL,
+    signers TEXT NOT NULL,
+    presignature_id BIGINT NOT NULL,
+    record BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (node_id, session_id, signers, presignature_id)
+);
@@ -0,0 +1,10 @@
+ALTER TABLE key_shares ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS staged_key_shares (
+    node_id BIGINT NOT NULL,
+    session_id TEXT NOT NULL,
+    version BIGINT NOT NULL,
+    share BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (node_id, session_id)
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS key_shares (
+    node_id INTEGER NOT NULL,
+    session_id TEXT NOT NULL,
+    share BLOB NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (node_id, session_id)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS request_ledger (
+    node_id INTEGER NOT NULL,
+    request_id TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    request TEXT NOT NULL,
+    status TEXT NOT NULL,
+    result TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (node_id, request_id)
+);
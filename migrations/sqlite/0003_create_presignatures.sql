@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS presignatures (
+    node_id INTEGER NOT NULL,
+    session_id TEXT NOT NULL,
+    signers TEXT NOT NULL,
+    presignature_id INTEGER NOT NULL,
+    record BLOB NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (node_id, session_id, signers, presignature_id)
+);
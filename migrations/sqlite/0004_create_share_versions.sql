@@ -0,0 +1,10 @@
+ALTER TABLE key_shares ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS staged_key_shares (
+    node_id INTEGER NOT NULL,
+    session_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    share BLOB NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (node_id, session_id)
+);
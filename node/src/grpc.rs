@@ -0,0 +1,620 @@
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use std::pin::Pin;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::Stream;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::{error, warn};
+
+use dkg_tcp::control::{
+    self, ControlRequest, ControlResponse, DkgStartRequest, ErrorCode, MessageFormat, RequestId,
+};
+use dkg_tcp::decode::{Account, MessageSummary};
+use dkg_tcp::policy::{PolicyRule, PolicyViolation};
+use dkg_tcp::progress::ProgressEvent;
+use dkg_tcp::store::RequestStatus;
+
+use crate::handlers::{Handlers, SessionEvent, Submission, outcome};
+use crate::node::encode_public_key;
+
+pub mod pb {
+    tonic::include_proto!("idmap.v1");
+}
+
+use pb::coordinator_server::{Coordinator, CoordinatorServer};
+use pb::result::Outcome;
+use pb::session_event::Event;
+
+/// Events buffered per `WatchSession` stream before the session waits on a slow client.
+const WATCH_BUFFER: usize = 16;
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<pb::SessionEvent, Status>> + Send>>;
+
+/// Serves the gRPC API on `listener` until the server fails.
+///
+/// # Arguments
+/// * `listener` - Bound gRPC listener
+/// * `handlers` - Request handlers shared with the Redis control plane and the HTTP API
+pub async fn serve(listener: TcpListener, handlers: Handlers) {
+    let server = Server::builder()
+        .add_service(CoordinatorServer::new(CoordinatorService { handlers }))
+        .serve_with_incoming(TcpListenerStream::new(listener));
+
+    if let Err(e) = server.await {
+        error!("[GRPC] Server failed: {:?}", e);
+    }
+}
+
+struct CoordinatorService {
+    handlers: Handlers,
+}
+
+#[tonic::async_trait]
+impl Coordinator for CoordinatorService {
+    type WatchSessionStream = EventStream;
+
+    async fn start_keygen(
+        &self,
+        request: Request<pb::StartKeygenRequest>,
+    ) -> Result<Response<pb::Operation>, Status> {
+        let request = request.into_inner();
+        let id = parse_request_id(&request.request_id)?;
+        let session = self.handlers.session_or_default(request.session.as_deref());
+        let request = DkgStartRequest {
+            id: id.clone(),
+            session: request.session,
+            rotate: request.rotate,
+        };
+
+        let submitted = self.handlers.submit_dkg(request, None, |_| async {}).await;
+        submission_operation(id, session, submitted).map(Response::new)
+    }
+
+    async fn refresh(
+        &self,
+        request: Request<pb::RefreshRequest>,
+    ) -> Result<Response<pb::Operation>, Status> {
+        let request = request.into_inner();
+        let id = parse_request_id(&request.request_id)?;
+        let session = self.handlers.session_or_default(request.session.as_deref());
+        let request = control::RefreshRequest {
+            id: id.clone(),
+            session: request.session,
+        };
+
+        let submitted = self
+            .handlers
+            .submit_refresh(request, None, |_| async {})
+            .await;
+        submission_operation(id, session, submitted).map(Response::new)
+    }
+
+    async fn sign(
+        &self,
+        request: Request<pb::SignRequest>,
+    ) -> Result<Response<pb::Operation>, Status> {
+        let request = request.into_inner();
+        let id = parse_request_id(&request.request_id)?;
+        let session = self.handlers.session_or_default(request.session.as_deref());
+        let format = message_format(request.format());
+        let request = control::SignRequest {
+            id: id.clone(),
+            session: request.session,
+            message: BASE64.encode(&request.message),
+            signers: signers(&request.signers)?,
+            format,
+            presigned: request.presigned,
+        };
+
+        let submitted = self
+            .handlers
+            .submit_sign(ControlRequest::Sign(request), None, |_| async {})
+            .await;
+        submission_operation(id, session, submitted).map(Response::new)
+    }
+
+    async fn presign(
+        &self,
+        request: Request<pb::PresignRequest>,
+    ) -> Result<Response<pb::Operation>, Status> {
+        let request = request.into_inner();
+        let id = parse_request_id(&request.request_id)?;
+        let session = self.handlers.session_or_default(request.session.as_deref());
+        let request = control::PresignRequest {
+            id: id.clone(),
+            session: request.session,
+            count: request.count as usize,
+            signers: signers(&request.signers)?,
+        };
+
+        let submitted = self
+            .handlers
+            .submit_sign(ControlRequest::Presign(request), None, |_| async {})
+            .await;
+        submission_operation(id, session, submitted).map(Response::new)
+    }
+
+    async fn sign_batch(
+        &self,
+        request: Request<pb::SignBatchRequest>,
+    ) -> Result<Response<pb::Operation>, Status> {
+        let request = request.into_inner();
+        let id = parse_request_id(&request.request_id)?;
+        let session = self.handlers.session_or_default(request.session.as_deref());
+        let format = message_format(request.format());
+        let request = control::SignBatchRequest {
+            id: id.clone(),
+            session: request.session,
+            messages: request.messages.iter().map(|m| BASE64.encode(m)).collect(),
+            signers: signers(&request.signers)?,
+            format,
+        };
+
+        let submitted = self
+            .handlers
+            .submit_sign(ControlRequest::SignBatch(request), None, |_| async {})
+            .await;
+        submission_operation(id, session, submitted).map(Response::new)
+    }
+
+    async fn get_public_key(
+        &self,
+        request: Request<pb::GetPublicKeyRequest>,
+    ) -> Result<Response<pb::PublicKey>, Status> {
+        let session = request.into_inner().session;
+        match self.handlers.key_share(&session).await {
+            Ok(Some(share)) => Ok(Response::new(pb::PublicKey {
+                public_key: encode_public_key(&share),
+                threshold: share.min_signers().into(),
+                n: share.n().into(),
+                session,
+            })),
+            Ok(None) => Err(Status::not_found(format!("No key for session {}", session))),
+            Err(e) => {
+                error!(
+                    "[GRPC] Failed to load share for session {}: {:?}",
+                    session, e
+                );
+                Err(Status::internal(format!("Failed to load share: {}", e)))
+            }
+        }
+    }
+
+    async fn watch_session(
+        &self,
+        request: Request<pb::WatchSessionRequest>,
+    ) -> Result<Response<Self::WatchSessionStream>, Status> {
+        let id = parse_request_id(&request.into_inner().request_id)?;
+
+        // A running session streams its events; anything else is answered from the ledger
+        let Some(events) = self.handlers.watch(&id) else {
+            let finished = recorded_result(&self.handlers, &id).await?;
+            let stream = tokio_stream::once(Ok(pb::SessionEvent {
+                event: Some(Event::Finished(finished)),
+            }));
+            return Ok(Response::new(Box::pin(stream)));
+        };
+
+        let (tx, rx) = mpsc::channel(WATCH_BUFFER);
+        tokio::spawn(forward_events(self.handlers.clone(), id, events, tx));
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+/// Relays a running session's events to one `WatchSession` stream, ending with its result.
+async fn forward_events(
+    handlers: Handlers,
+    id: RequestId,
+    mut events: broadcast::Receiver<SessionEvent>,
+    tx: mpsc::Sender<Result<pb::SessionEvent, Status>>,
+) {
+    loop {
+        let event = match events.recv().await {
+            Ok(SessionEvent::Progress(event)) => progress_event(event),
+            Ok(SessionEvent::Finished(response)) => Event::Finished(result(&response)),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!(
+                    "[GRPC] Watcher of request {} fell behind; skipped {} events",
+                    id, skipped
+                );
+                continue;
+            }
+            // Gone without a result, e.g. rejected by the ledger; it is recorded there instead
+            Err(broadcast::error::RecvError::Closed) => {
+                let last = recorded_result(&handlers, &id)
+                    .await
+                    .map(|finished| pb::SessionEvent {
+                        event: Some(Event::Finished(finished)),
+                    });
+                let _ = tx.send(last).await;
+                return;
+            }
+        };
+
+        let finished = matches!(event, Event::Finished(_));
+        let event = pb::SessionEvent { event: Some(event) };
+        if tx.send(Ok(event)).await.is_err() || finished {
+            // The client hung up, or the stream is complete
+            return;
+        }
+    }
+}
+
+/// Result of a request that is no longer running, from the ledger.
+async fn recorded_result(handlers: &Handlers, id: &RequestId) -> Result<pb::Result, Status> {
+    let entry = match handlers.operation(id).await {
+        Ok(Some(entry)) => entry,
+        Ok(None) => return Err(Status::not_found(format!("Unknown request {}", id))),
+        Err(e) => {
+            error!("[GRPC] Failed to look up request {}: {:?}", id, e);
+            return Err(Status::internal(format!(
+                "Failed to read request ledger: {}",
+                e
+            )));
+        }
+    };
+
+    match entry.result.as_deref().map(ControlResponse::from_json) {
+        Some(Ok(response)) => Ok(result(&response)),
+        Some(Err(e)) => {
+            warn!(
+                "[GRPC] Recorded result of request {} is unreadable: {}",
+                id, e
+            );
+            Err(Status::internal(format!(
+                "Recorded result of request {} is unreadable",
+                id
+            )))
+        }
+        // Recorded as running but not running here, e.g. cut short by a restart
+        None => Err(Status::unavailable(format!(
+            "Request {} is not running on this node; submit it again to resume",
+            id
+        ))),
+    }
+}
+
+/// Reads a request id the way the JSON control plane would: all digits is a number.
+fn parse_request_id(id: &str) -> Result<RequestId, Status> {
+    if id.is_empty() {
+        return Err(Status::invalid_argument("request_id is required"));
+    }
+    Ok(match id.parse::<u64>() {
+        Ok(number) => RequestId::Number(number),
+        Err(_) => RequestId::Text(id.to_string()),
+    })
+}
+
+/// Signer set of a sign request; empty means the default signer set.
+fn signers(signers: &[u32]) -> Result<Option<Vec<u16>>, Status> {
+    if signers.is_empty() {
+        return Ok(None);
+    }
+    signers
+        .iter()
+        .map(|&s| u16::try_from(s))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+        .map_err(|_| Status::invalid_argument("signer index out of range"))
+}
+
+fn message_format(format: pb::MessageFormat) -> MessageFormat {
+    match format {
+        pb::MessageFormat::Unspecified | pb::MessageFormat::Raw => MessageFormat::Raw,
+        pb::MessageFormat::Solana => MessageFormat::Solana,
+        pb::MessageFormat::Offchain => MessageFormat::Offchain,
+        pb::MessageFormat::SignIn => MessageFormat::SignIn,
+    }
+}
+
+fn submission_operation(
+    id: RequestId,
+    session: String,
+    submitted: Submission,
+) -> Result<pb::Operation, Status> {
+    let (status, result) = match submitted {
+        Submission::Started | Submission::InProgress => (RequestStatus::Running, None),
+        Submission::Answered(response) => (outcome(&response), Some(result(&response))),
+        Submission::NotSigner(signers) => {
+            return Err(Status::failed_precondition(format!(
+                "This node is not among signers {:?}",
+                signers
+            )));
+        }
+    };
+
+    Ok(pb::Operation {
+        request_id: id.to_string(),
+        session,
+        status: operation_status(status).into(),
+        result,
+    })
+}
+
+fn result(response: &ControlResponse) -> pb::Result {
+    let summary = match response {
+        ControlResponse::Sign(r) => r.summary.as_deref(),
+        ControlResponse::SignError(e) => e.summary.as_deref(),
+        ControlResponse::Dkg(_)
+        | ControlResponse::DkgError(_)
+        | ControlResponse::RefreshError(_)
+        | ControlResponse::SignBatch(_)
+        | ControlResponse::Presign(_)
+        | ControlResponse::Refresh(_) => None,
+    };
+    let (server_id, outcome) = match response {
+        ControlResponse::Dkg(r) => (r.server_id, Outcome::PublicKey(r.data.clone())),
+        ControlResponse::Refresh(r) => (
+            r.server_id,
+            Outcome::RefreshedKey(pb::RefreshedKey {
+                public_key: r.data.clone(),
+                version: r.version,
+            }),
+        ),
+        ControlResponse::Sign(r) => (r.server_id, Outcome::Signature(r.data.clone())),
+        ControlResponse::SignBatch(r) => (
+            r.server_id,
+            Outcome::Signatures(pb::Signatures {
+                signatures: r
+                    .data
+                    .iter()
+                    .enumerate()
+                    .map(|(i, signature)| pb::SignedMessage {
+                        signature: signature.clone(),
+                        summary: r
+                            .summaries
+                            .get(i)
+                            .and_then(Option::as_ref)
+                            .map(message_summary),
+                    })
+                    .collect(),
+            }),
+        ),
+        ControlResponse::Presign(r) => (
+            r.server_id,
+            Outcome::Presignatures(pb::Presignatures {
+                count: r.count as u32,
+                available: r.available as u32,
+            }),
+        ),
+        ControlResponse::DkgError(e)
+        | ControlResponse::RefreshError(e)
+        | ControlResponse::SignError(e) => (
+            e.server_id,
+            Outcome::Error(pb::Error {
+                code: error_code(e.code).into(),
+                message: e.error.clone(),
+                violation: e.violation.as_ref().map(policy_violation),
+                index: e.index.map(|index| index as u32),
+            }),
+        ),
+    };
+
+    pb::Result {
+        server_id,
+        outcome: Some(outcome),
+        summary: summary.map(|summary| Box::new(message_summary(summary))),
+    }
+}
+
+fn message_summary(summary: &MessageSummary) -> pb::MessageSummary {
+    let strings = |accounts: &[Account]| accounts.iter().map(Account::to_string).collect();
+
+    pb::MessageSummary {
+        version: summary.version.clone(),
+        fee_payer: summary.fee_payer.to_string(),
+        signers: strings(&summary.signers),
+        recent_blockhash: summary.recent_blockhash.clone(),
+        lookup_tables: strings(&summary.lookup_tables),
+        instructions: summary
+            .instructions
+            .iter()
+            .map(|instruction| pb::InstructionSummary {
+                program_id: instruction.program_id.to_string(),
+                program: instruction.program.clone(),
+                accounts: strings(&instruction.accounts),
+                decoded: instruction.decoded.as_ref().map(ToString::to_string),
+            })
+            .collect(),
+    }
+}
+
+fn policy_violation(violation: &PolicyViolation) -> pb::PolicyViolation {
+    let rule = match violation.rule {
+        PolicyRule::RawMessage => pb::PolicyRule::RawMessage,
+        PolicyRule::ProgramNotAllowed => pb::PolicyRule::ProgramNotAllowed,
+        PolicyRule::TransferLimit => pb::PolicyRule::TransferLimit,
+        PolicyRule::DailyLimit => pb::PolicyRule::DailyLimit,
+        PolicyRule::DestinationNotAllowed => pb::PolicyRule::DestinationNotAllowed,
+        PolicyRule::DestinationDenied => pb::PolicyRule::DestinationDenied,
+        PolicyRule::UnresolvedDestination => pb::PolicyRule::UnresolvedDestination,
+        PolicyRule::UncheckedTransfer => pb::PolicyRule::UncheckedTransfer,
+        PolicyRule::MemoRequired => pb::PolicyRule::MemoRequired,
+        PolicyRule::OffchainMessage => pb::PolicyRule::OffchainMessage,
+    };
+
+    pb::PolicyViolation {
+        rule: rule.into(),
+        subject: violation.subject.clone(),
+        amount: violation.amount,
+        limit: violation.limit,
+    }
+}
+
+fn progress_event(event: ProgressEvent) -> Event {
+    match event {
+        ProgressEvent::PeerConnected { party } => Event::PeerConnected(party.into()),
+        ProgressEvent::PeerDisconnected { party } => Event::PeerDisconnected(party.into()),
+        ProgressEvent::RoundStarted { round } => Event::RoundStarted(round.into()),
+    }
+}
+
+fn operation_status(status: RequestStatus) -> pb::OperationStatus {
+    match status {
+        RequestStatus::Running => pb::OperationStatus::Running,
+        RequestStatus::Succeeded => pb::OperationStatus::Succeeded,
+        RequestStatus::Rejected => pb::OperationStatus::Rejected,
+        RequestStatus::Failed => pb::OperationStatus::Failed,
+    }
+}
+
+fn error_code(code: ErrorCode) -> pb::ErrorCode {
+    match code {
+        ErrorCode::MalformedRequest => pb::ErrorCode::MalformedRequest,
+        ErrorCode::InvalidMessage => pb::ErrorCode::InvalidMessage,
+        ErrorCode::ShareNotFound => pb::ErrorCode::ShareNotFound,
+        ErrorCode::InvalidSigners => pb::ErrorCode::InvalidSigners,
+        ErrorCode::StoreError => pb::ErrorCode::StoreError,
+        ErrorCode::SessionInProgress => pb::ErrorCode::SessionInProgress,
+        ErrorCode::Busy => pb::ErrorCode::Busy,
+        ErrorCode::PeerUnavailable => pb::ErrorCode::PeerUnavailable,
+        ErrorCode::ProtocolFailed => pb::ErrorCode::ProtocolFailed,
+        ErrorCode::Timeout => pb::ErrorCode::Timeout,
+        ErrorCode::KeyExists => pb::ErrorCode::KeyExists,
+        ErrorCode::RequestIdReused => pb::ErrorCode::RequestIdReused,
+        ErrorCode::InvalidSignature => pb::ErrorCode::InvalidSignature,
+        ErrorCode::PolicyViolation => pb::ErrorCode::PolicyViolation,
+        ErrorCode::PresignatureUnavailable => pb::ErrorCode::PresignatureUnavailable,
+        ErrorCode::ShareVersionMismatch => pb::ErrorCode::ShareVersionMismatch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::cluster;
+    use dkg_tcp::scheduler::SessionScheduler;
+    use tokio_stream::StreamExt;
+
+    async fn services(n: u16, threshold: u16) -> Vec<CoordinatorService> {
+        cluster(n, threshold)
+            .await
+            .into_iter()
+            .map(|ctx| CoordinatorService {
+                handlers: Handlers::new(
+                    ctx,
+                    SessionScheduler::new(4, 4).unwrap(),
+                    "session-001".into(),
+                ),
+            })
+            .collect()
+    }
+
+    fn start_keygen(request_id: &str) -> Request<pb::StartKeygenRequest> {
+        Request::new(pb::StartKeygenRequest {
+            request_id: request_id.into(),
+            session: Some("session-001".into()),
+            rotate: false,
+        })
+    }
+
+    fn watch(request_id: &str) -> Request<pb::WatchSessionRequest> {
+        Request::new(pb::WatchSessionRequest {
+            request_id: request_id.into(),
+        })
+    }
+
+    #[tokio::test]
+    async fn watch_session_streams_a_keygen_until_its_result() {
+        let nodes = services(2, 2).await;
+
+        let mut watches = Vec::new();
+        for node in &nodes {
+            let operation = node.start_keygen(start_keygen("1")).await.unwrap();
+            let operation = operation.into_inner();
+            assert_eq!(operation.request_id, "1");
+            assert_eq!(operation.status(), pb::OperationStatus::Running);
+            watches.push(node.watch_session(watch("1")).await.unwrap().into_inner());
+        }
+
+        let mut keys = Vec::new();
+        for (party, events) in watches.into_iter().enumerate() {
+            let events: Vec<Event> = events
+                .map(|event| event.unwrap().event.unwrap())
+                .collect()
+                .await;
+            let peer = 1 - party as u32;
+            assert!(events.contains(&Event::PeerConnected(peer)));
+            assert!(events.contains(&Event::RoundStarted(1)));
+            let Some(Event::Finished(result)) = events.last() else {
+                panic!("the stream ends with the result: {:?}", events);
+            };
+            assert_eq!(result.server_id, party as u64);
+            let Some(Outcome::PublicKey(key)) = &result.outcome else {
+                panic!("expected a public key: {:?}", result);
+            };
+            keys.push(key.clone());
+        }
+        assert_eq!(keys[0], keys[1]);
+
+        let public_key = nodes[1]
+            .get_public_key(Request::new(pb::GetPublicKeyRequest {
+                session: "session-001".into(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(public_key.public_key, keys[0]);
+        assert_eq!((public_key.threshold, public_key.n), (2, 2));
+
+        // A finished request yields its recorded result alone, and a replay is answered with it
+        let events: Vec<_> = nodes[0]
+            .watch_session(watch("1"))
+            .await
+            .unwrap()
+            .into_inner()
+            .collect()
+            .await;
+        assert!(matches!(
+            events.as_slice(),
+            [Ok(pb::SessionEvent {
+                event: Some(Event::Finished(_))
+            })]
+        ));
+        let replayed = nodes[0]
+            .start_keygen(start_keygen("1"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(replayed.status(), pb::OperationStatus::Succeeded);
+        assert!(matches!(
+            replayed.result.and_then(|result| result.outcome),
+            Some(Outcome::PublicKey(key)) if key == keys[0]
+        ));
+    }
+
+    #[tokio::test]
+    async fn unknown_requests_and_sessions_are_not_found() {
+        let nodes = services(2, 2).await;
+
+        let status = nodes[0]
+            .watch_session(watch("missing"))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        let status = nodes[0]
+            .get_public_key(Request::new(pb::GetPublicKeyRequest {
+                session: "session-001".into(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        let status = nodes[0].start_keygen(start_keygen("")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn request_ids_of_only_digits_match_json_numbers() {
+        assert_eq!(parse_request_id("42").unwrap(), RequestId::Number(42));
+        assert_eq!(
+            parse_request_id("a42").unwrap(),
+            RequestId::Text("a42".into())
+        );
+        assert_eq!(signers(&[]).unwrap(), None);
+        assert_eq!(signers(&[0, 2]).unwrap(), Some(vec![0, 2]));
+        assert!(signers(&[70_000]).is_err());
+    }
+}
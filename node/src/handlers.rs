@@ -0,0 +1,554 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
+
+use dkg_tcp::control::{
+    ControlRequest, ControlResponse, DkgStartRequest, ErrorCode, ErrorResult, RefreshRequest,
+    RequestId,
+};
+use dkg_tcp::progress::{Progress, ProgressEvent};
+use dkg_tcp::scheduler::SessionScheduler;
+use dkg_tcp::store::{KeyShare, LedgerEntry, RequestStatus};
+
+use crate::node::{
+    SessionContext, run_dkg_session, run_presign_session, run_refresh_session, run_sign_session,
+};
+
+/// What became of a submitted request.
+pub enum Submission {
+    /// Scheduled; its result goes to the completion callback once the session finishes
+    Started,
+    /// The same request is already running on this node; its session also answers this
+    /// delivery if it is a new one
+    InProgress,
+    /// Answered without running: a recorded result, or an error
+    Answered(ControlResponse),
+    /// This node is not among the request's signers
+    NotSigner(Vec<u16>),
+}
+
+/// Something a watcher of a running request is told.
+#[derive(Debug, Clone)]
+pub enum SessionEvent {
+    Progress(ProgressEvent),
+    /// The request finished with this result; no events follow
+    Finished(ControlResponse),
+}
+
+/// Events buffered per watcher; a watcher that falls further behind skips the oldest.
+const SESSION_EVENT_CAPACITY: usize = 64;
+
+/// Request handling shared by the Redis control plane, the HTTP API and the gRPC API.
+#[derive(Clone)]
+pub struct Handlers {
+    ctx: SessionContext,
+    scheduler: SessionScheduler,
+    in_flight: InFlight,
+    default_session: String,
+}
+
+impl Handlers {
+    pub fn new(ctx: SessionContext, scheduler: SessionScheduler, default_session: String) -> Self {
+        Self {
+            ctx,
+            scheduler,
+            in_flight: InFlight::default(),
+            default_session,
+        }
+    }
+
+    /// Party index of this node, as reported in `server_id`.
+    pub fn node_id(&self) -> u64 {
+        self.ctx.id as u64
+    }
+
+    /// Starts a keygen session for `request` unless the ledger or the scheduler answers it first.
+    ///
+    /// # Arguments
+    /// * `request` - Keygen request as received
+    /// * `delivery` - What the request arrived in, e.g. its stream entry; see [`Submission`]
+    /// * `on_done` - Receives the result once the session finishes; only called for `Started`,
+    ///   or for `InProgress` with a new `delivery`
+    pub async fn submit_dkg<F, Fut>(
+        &self,
+        request: DkgStartRequest,
+        delivery: Option<&str>,
+        on_done: F,
+    ) -> Submission
+    where
+        F: FnOnce(ControlResponse) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        // The same request delivered again while it runs; the running session answers it
+        let Some((guard, on_done)) = self.in_flight.claim(&request.id, delivery, on_done) else {
+            debug!("[DKG] Request {} is already running", request.id);
+            return Submission::InProgress;
+        };
+
+        let session = self.session_or_default(request.session.as_deref());
+        let control = ControlRequest::StartDkg(request.clone());
+        let admitted = match self
+            .admit(&control, &session, guard, ControlResponse::DkgError)
+            .await
+        {
+            Admission::Run(admitted) => admitted,
+            Admission::Answer(response) => return Submission::Answered(response),
+        };
+
+        let label = format!("keygen {}", session);
+        let progress = admitted.guard.progress();
+        let run = run_dkg_session(self.ctx.clone(), session, request, progress);
+        self.schedule(&label, admitted, ControlResponse::DkgError, run, on_done)
+            .await
+    }
+
+    /// Starts a refresh session for `request` unless the ledger or the scheduler answers it
+    /// first.
+    ///
+    /// # Arguments
+    /// * `request` - Refresh request as received
+    /// * `delivery` - What the request arrived in, e.g. its stream entry; see [`Submission`]
+    /// * `on_done` - Receives the result once the session finishes; only called for `Started`,
+    ///   or for `InProgress` with a new `delivery`
+    pub async fn submit_refresh<F, Fut>(
+        &self,
+        request: RefreshRequest,
+        delivery: Option<&str>,
+        on_done: F,
+    ) -> Submission
+    where
+        F: FnOnce(ControlResponse) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        // The same request delivered again while it runs; the running session answers it
+        let Some((guard, on_done)) = self.in_flight.claim(&request.id, delivery, on_done) else {
+            debug!("[REFRESH] Request {} is already running", request.id);
+            return Submission::InProgress;
+        };
+
+        let session = self.session_or_default(request.session.as_deref());
+        let control = ControlRequest::Refresh(request.clone());
+        let admitted = match self
+            .admit(&control, &session, guard, ControlResponse::RefreshError)
+            .await
+        {
+            Admission::Run(admitted) => admitted,
+            Admission::Answer(response) => return Submission::Answered(response),
+        };
+
+        let label = format!("refresh {}", session);
+        let progress = admitted.guard.progress();
+        let run = run_refresh_session(self.ctx.clone(), session, request, progress);
+        self.schedule(
+            &label,
+            admitted,
+            ControlResponse::RefreshError,
+            run,
+            on_done,
+        )
+        .await
+    }
+
+    /// Starts a signing session for `request` unless this node is not a signer or the ledger
+    /// or the scheduler answers it first.
+    ///
+    /// # Arguments
+    /// * `request` - Any request served on `sign-start`, as received
+    /// * `delivery` - What the request arrived in, e.g. its stream entry; see [`Submission`]
+    /// * `on_done` - Receives the result once the session finishes; only called for `Started`,
+    ///   or for `InProgress` with a new `delivery`
+    pub async fn submit_sign<F, Fut>(
+        &self,
+        request: ControlRequest,
+        delivery: Option<&str>,
+        on_done: F,
+    ) -> Submission
+    where
+        F: FnOnce(ControlResponse) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        debug_assert!(request.is_sign(), "keygen request submitted for signing");
+        let session = self.session_or_default(request.session());
+
+        // Without an explicit set, the lowest `threshold` parties co-sign
+        let requested = request
+            .signers()
+            .map(<[u16]>::to_vec)
+            .unwrap_or_else(|| (0..self.ctx.keygen_config.threshold).collect());
+        if !requested.contains(&self.ctx.id) {
+            debug!(
+                "[SIGN] Not among signers {:?} for session {}",
+                requested, session
+            );
+            return Submission::NotSigner(requested);
+        }
+
+        // The same request delivered again while it runs; the running session answers it
+        let Some((guard, on_done)) = self.in_flight.claim(request.id(), delivery, on_done) else {
+            debug!("[SIGN] Request {} is already running", request.id());
+            return Submission::InProgress;
+        };
+
+        let admitted = match self
+            .admit(&request, &session, guard, ControlResponse::SignError)
+            .await
+        {
+            Admission::Run(admitted) => admitted,
+            Admission::Answer(response) => return Submission::Answered(response),
+        };
+
+        let progress = admitted.guard.progress();
+        if let ControlRequest::Presign(request) = request {
+            let label = format!("presign {}", session);
+            let run = run_presign_session(self.ctx.clone(), session, request, requested, progress);
+            return self
+                .schedule(&label, admitted, ControlResponse::SignError, run, on_done)
+                .await;
+        }
+
+        let label = format!("sign {}", session);
+        let run = run_sign_session(self.ctx.clone(), session, request, requested, progress);
+        self.schedule(&label, admitted, ControlResponse::SignError, run, on_done)
+            .await
+    }
+
+    /// Subscribes to the events of a request while its session runs on this node. Returns
+    /// `None` once it has finished, or if it never started here; the ledger has the rest.
+    pub fn watch(&self, request_id: &RequestId) -> Option<broadcast::Receiver<SessionEvent>> {
+        self.in_flight.watch(request_id)
+    }
+
+    /// Ledger entry of a request this node has seen.
+    pub async fn operation(&self, request_id: &RequestId) -> Result<Option<LedgerEntry>> {
+        self.ctx
+            .store
+            .get_request(self.node_id(), &request_id.to_json())
+            .await
+    }
+
+    /// This node's share of a key session, if it holds one.
+    pub async fn key_share(&self, session: &str) -> Result<Option<KeyShare>> {
+        self.ctx.store.get(self.node_id(), session).await
+    }
+
+    /// Key session a request runs for, `DEFAULT_SESSION_ID` when it names none.
+    pub fn session_or_default(&self, session: Option<&str>) -> String {
+        session.unwrap_or(&self.default_session).to_string()
+    }
+
+    /// Queues `run` on the scheduler; its result is recorded in the ledger and then passed to
+    /// `on_done`. A full queue answers with `busy` right away.
+    async fn schedule<S, F, Fut>(
+        &self,
+        label: &str,
+        admitted: Admitted,
+        error: fn(ErrorResult) -> ControlResponse,
+        run: S,
+        on_done: F,
+    ) -> Submission
+    where
+        S: Future<Output = ControlResponse> + Send + 'static,
+        F: FnOnce(ControlResponse) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let Admitted {
+            request_id,
+            entry,
+            guard,
+        } = admitted;
+        let handlers = self.clone();
+        let session_entry = entry.clone();
+        // Shared with the session, so a full queue still finishes the claim below
+        let claim = Arc::new(Mutex::new(Some(guard)));
+        let session_claim = claim.clone();
+        let spawned = self.scheduler.spawn(label, async move {
+            let response = run.await;
+            // Held until the result is recorded, so a replay in between is not started again
+            handlers.record_result(session_entry, &response).await;
+            let guard = session_claim.lock().unwrap().take();
+            if let Some(guard) = guard {
+                guard.finish(&response);
+            }
+            on_done(response).await;
+        });
+
+        match spawned {
+            Ok(_) => Submission::Started,
+            Err(e) => {
+                warn!("[NODE] {:?}", e);
+                let reply = ErrorResult::new(
+                    Some(request_id),
+                    self.node_id(),
+                    ErrorCode::Busy,
+                    e.to_string(),
+                );
+                let response = error(reply);
+                self.record_result(entry, &response).await;
+                let guard = claim.lock().unwrap().take();
+                if let Some(guard) = guard {
+                    guard.finish(&response);
+                }
+                Submission::Answered(response)
+            }
+        }
+    }
+
+    /// Looks a request up in the ledger before it runs. A request that already finished for good
+    /// is answered with its recorded result; anything else is recorded as running.
+    ///
+    /// # Arguments
+    /// * `request` - The request as received
+    /// * `session` - Key session the request resolved to
+    /// * `guard` - This node's claim on the request id
+    /// * `error` - Wraps an error in the reply type of the request's protocol
+    async fn admit(
+        &self,
+        request: &ControlRequest,
+        session: &str,
+        guard: InFlightGuard,
+        error: fn(ErrorResult) -> ControlResponse,
+    ) -> Admission {
+        match self.check_ledger(request, session, &guard.id, error).await {
+            Ok(entry) => Admission::Run(Admitted {
+                request_id: guard.id.clone(),
+                entry,
+                guard,
+            }),
+            Err(response) => {
+                guard.finish(&response);
+                Admission::Answer(*response)
+            }
+        }
+    }
+
+    /// The ledger entry recording a request as running, or the response that answers it
+    /// without running, as described for [`Self::admit`].
+    async fn check_ledger(
+        &self,
+        request: &ControlRequest,
+        session: &str,
+        request_id: &RequestId,
+        error: fn(ErrorResult) -> ControlResponse,
+    ) -> Result<LedgerEntry, Box<ControlResponse>> {
+        let id = self.node_id();
+        let key = request_id.to_json();
+        let request_json = request.to_json();
+        let reject = |code: ErrorCode, message: String| {
+            Err(Box::new(error(ErrorResult::new(
+                Some(request_id.clone()),
+                id,
+                code,
+                message,
+            ))))
+        };
+
+        match self.ctx.store.get_request(id, &key).await {
+            Ok(Some(entry)) if entry.request != request_json => {
+                warn!(
+                    "[LEDGER] Request id {} reused for a different request",
+                    request_id
+                );
+                return reject(
+                    ErrorCode::RequestIdReused,
+                    format!(
+                        "Request id {} was already used for a different request",
+                        request_id
+                    ),
+                );
+            }
+            Ok(Some(entry)) if entry.status.is_final() => {
+                match entry.result.as_deref().map(ControlResponse::from_json) {
+                    Some(Ok(response)) => {
+                        info!(
+                            "[LEDGER] Replaying recorded result of request {}",
+                            request_id
+                        );
+                        return Err(Box::new(response));
+                    }
+                    _ => warn!(
+                        "[LEDGER] Recorded result of request {} is unreadable; running it again",
+                        request_id
+                    ),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("[LEDGER] Failed to look up request {}: {:?}", request_id, e);
+                return reject(
+                    ErrorCode::StoreError,
+                    format!("Failed to read request ledger: {}", e),
+                );
+            }
+        }
+
+        let entry = LedgerEntry {
+            request_id: key,
+            session: session.to_string(),
+            request: request_json,
+            status: RequestStatus::Running,
+            result: None,
+        };
+        if let Err(e) = self.ctx.store.put_request(id, &entry).await {
+            error!("[LEDGER] Failed to record request {}: {:?}", request_id, e);
+            return reject(
+                ErrorCode::StoreError,
+                format!("Failed to write request ledger: {}", e),
+            );
+        }
+        Ok(entry)
+    }
+
+    /// Records how a request finished, so replays are answered according to [`outcome`].
+    async fn record_result(&self, mut entry: LedgerEntry, response: &ControlResponse) {
+        entry.status = outcome(response);
+        entry.result = Some(response.to_json());
+
+        if let Err(e) = self.ctx.store.put_request(self.node_id(), &entry).await {
+            error!(
+                "[LEDGER] Failed to record result of request {}: {:?}",
+                entry.request_id, e
+            );
+        }
+    }
+}
+
+/// Ledger status of a finished request. Successes and permanent errors are final and answer
+/// every replay; transient errors let a replay run the request again.
+pub fn outcome(response: &ControlResponse) -> RequestStatus {
+    match response.error_code() {
+        None => RequestStatus::Succeeded,
+        Some(code) if code.is_transient() => RequestStatus::Failed,
+        Some(_) => RequestStatus::Rejected,
+    }
+}
+
+/// Outcome of checking a request against the ledger.
+enum Admission {
+    /// Recorded as running and cleared to start
+    Run(Admitted),
+    /// Answered without running: a recorded result, or an error
+    Answer(ControlResponse),
+}
+
+/// A request recorded as running in the ledger and claimed against redelivery.
+struct Admitted {
+    request_id: RequestId,
+    entry: LedgerEntry,
+    guard: InFlightGuard,
+}
+
+/// Completion callback of a later delivery of a running request.
+type Follower = Box<dyn FnOnce(ControlResponse) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+/// A request with a session running on this node.
+struct Running {
+    /// Channel the request's watchers subscribe to
+    events: broadcast::Sender<SessionEvent>,
+    /// Deliveries of the request so far, so a repeat of one is not answered twice
+    deliveries: Vec<String>,
+    /// Called with the session's result, next to the `on_done` of the first delivery
+    followers: Vec<Follower>,
+}
+
+type RunningMap = Arc<Mutex<HashMap<RequestId, Running>>>;
+
+/// Request ids with a session currently running, so a redelivered request is not started twice.
+#[derive(Clone, Default)]
+struct InFlight(RunningMap);
+
+impl InFlight {
+    /// Marks `id` as running and hands `on_done` back with the claim. If it already runs,
+    /// returns `None` and keeps `on_done` to call with its result when `delivery` is new.
+    fn claim<F, Fut>(
+        &self,
+        id: &RequestId,
+        delivery: Option<&str>,
+        on_done: F,
+    ) -> Option<(InFlightGuard, F)>
+    where
+        F: FnOnce(ControlResponse) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut running = self.0.lock().unwrap();
+        if let Some(request) = running.get_mut(id) {
+            if let Some(delivery) = delivery
+                && !request.deliveries.iter().any(|seen| seen == delivery)
+            {
+                request.deliveries.push(delivery.to_string());
+                request
+                    .followers
+                    .push(Box::new(move |response| Box::pin(on_done(response))));
+            }
+            return None;
+        }
+
+        let (events, _) = broadcast::channel(SESSION_EVENT_CAPACITY);
+        running.insert(
+            id.clone(),
+            Running {
+                events: events.clone(),
+                deliveries: delivery.into_iter().map(str::to_string).collect(),
+                followers: Vec::new(),
+            },
+        );
+        let guard = InFlightGuard {
+            running: self.0.clone(),
+            id: id.clone(),
+            events,
+            finished: false,
+        };
+        Some((guard, on_done))
+    }
+
+    fn watch(&self, id: &RequestId) -> Option<broadcast::Receiver<SessionEvent>> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|request| request.events.subscribe())
+    }
+}
+
+struct InFlightGuard {
+    running: RunningMap,
+    id: RequestId,
+    events: broadcast::Sender<SessionEvent>,
+    finished: bool,
+}
+
+impl InFlightGuard {
+    /// Forwards the session's progress to the request's watchers.
+    fn progress(&self) -> Progress {
+        let events = self.events.clone();
+        Progress::new(move |event| {
+            // No watchers is not an error
+            let _ = events.send(SessionEvent::Progress(event));
+        })
+    }
+
+    /// Releases the request id once its result is recorded, tells the request's watchers how
+    /// it finished and answers every later delivery with the same result.
+    fn finish(mut self, response: &ControlResponse) {
+        self.finished = true;
+        let followers = match self.running.lock().unwrap().remove(&self.id) {
+            Some(request) => request.followers,
+            None => Vec::new(),
+        };
+        let _ = self.events.send(SessionEvent::Finished(response.clone()));
+        for follower in followers {
+            tokio::spawn(follower(response.clone()));
+        }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.running.lock().unwrap().remove(&self.id);
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use axum::extract::{Path, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tracing::{error, warn};
+
+use dkg_tcp::control::{
+    ControlRequest, ControlResponse, CreateTokenAccountRequest, DkgStartRequest, PresignRequest,
+    RefreshRequest, RequestId, SignBatchRequest, SignRequest, TokenTransferRequest,
+};
+use dkg_tcp::store::{LedgerEntry, RequestStatus};
+
+use crate::handlers::{Handlers, Submission, outcome};
+use crate::node::encode_public_key;
+
+/// A request as seen by the HTTP API, identified by its request id.
+#[derive(Serialize)]
+struct Operation {
+    id: RequestId,
+    session: String,
+    /// `running`, `succeeded`, `rejected` or `failed`
+    status: &'static str,
+    /// The control-plane response once the request has finished
+    result: Option<ControlResponse>,
+}
+
+/// This node's view of a key session.
+#[derive(Serialize)]
+struct KeyInfo {
+    session: String,
+    /// Base58-encoded shared public key
+    public_key: String,
+    threshold: u16,
+    n: u16,
+}
+
+#[derive(Serialize)]
+struct ApiError {
+    error: String,
+}
+
+/// Serves the HTTP API on `listener` until the server fails.
+///
+/// # Arguments
+/// * `listener` - Bound HTTP listener
+/// * `handlers` - Request handlers shared with the Redis control plane
+pub async fn serve(listener: TcpListener, handlers: Handlers) {
+    let app = Router::new()
+        .route("/keygen", post(keygen))
+        .route("/refresh", post(refresh))
+        .route("/sign", post(sign))
+        .route("/sign/batch", post(sign_batch))
+        .route("/presign", post(presign))
+        .route("/sign/token-transfer", post(sign_token_transfer))
+        .route(
+            "/sign/create-token-account",
+            post(sign_create_token_account),
+        )
+        .route("/keys/{session}", get(key))
+        .route("/sessions/{id}", get(operation))
+        .with_state(handlers);
+
+    if let Err(e) = axum::serve(listener, app).await {
+        error!("[HTTP] Server failed: {:?}", e);
+    }
+}
+
+/// `POST /keygen` — starts a keygen request; every node must receive it under the same id.
+async fn keygen(
+    State(handlers): State<Handlers>,
+    Json(request): Json<DkgStartRequest>,
+) -> Response {
+    let id = request.id.clone();
+    let session = handlers.session_or_default(request.session.as_deref());
+    let submitted = handlers.submit_dkg(request, None, |_| async {}).await;
+    submission_response(id, session, submitted)
+}
+
+/// `POST /refresh` — refreshes every party's share of a session's key; every node must receive
+/// it under the same id.
+async fn refresh(
+    State(handlers): State<Handlers>,
+    Json(request): Json<RefreshRequest>,
+) -> Response {
+    let id = request.id.clone();
+    let session = handlers.session_or_default(request.session.as_deref());
+    let submitted = handlers.submit_refresh(request, None, |_| async {}).await;
+    submission_response(id, session, submitted)
+}
+
+/// `POST /sign` — starts a signing request; every signer must receive it under the same id.
+async fn sign(State(handlers): State<Handlers>, Json(request): Json<SignRequest>) -> Response {
+    submit_sign(handlers, ControlRequest::Sign(request)).await
+}
+
+/// `POST /sign/batch` — signs a list of messages in one session; the result lists their
+/// signatures in order.
+async fn sign_batch(
+    State(handlers): State<Handlers>,
+    Json(request): Json<SignBatchRequest>,
+) -> Response {
+    submit_sign(handlers, ControlRequest::SignBatch(request)).await
+}
+
+/// `POST /presign` — generates nonces for later `/sign` requests with `presigned` set.
+async fn presign(
+    State(handlers): State<Handlers>,
+    Json(request): Json<PresignRequest>,
+) -> Response {
+    submit_sign(handlers, ControlRequest::Presign(request)).await
+}
+
+/// `POST /sign/token-transfer` — signs a token transfer out of the session key's token account.
+async fn sign_token_transfer(
+    State(handlers): State<Handlers>,
+    Json(request): Json<TokenTransferRequest>,
+) -> Response {
+    submit_sign(handlers, ControlRequest::SignTokenTransfer(request)).await
+}
+
+/// `POST /sign/create-token-account` — signs the creation of an associated token account.
+async fn sign_create_token_account(
+    State(handlers): State<Handlers>,
+    Json(request): Json<CreateTokenAccountRequest>,
+) -> Response {
+    submit_sign(handlers, ControlRequest::SignCreateTokenAccount(request)).await
+}
+
+async fn submit_sign(handlers: Handlers, request: ControlRequest) -> Response {
+    let id = request.id().clone();
+    let session = handlers.session_or_default(request.session());
+    let submitted = handlers.submit_sign(request, None, |_| async {}).await;
+    submission_response(id, session, submitted)
+}
+
+/// `GET /keys/{session}` — public key of a session this node holds a share of.
+async fn key(State(handlers): State<Handlers>, Path(session): Path<String>) -> Response {
+    match handlers.key_share(&session).await {
+        Ok(Some(share)) => Json(KeyInfo {
+            public_key: encode_public_key(&share),
+            threshold: share.min_signers(),
+            n: share.n(),
+            session,
+        })
+        .into_response(),
+        Ok(None) => api_error(
+            StatusCode::NOT_FOUND,
+            format!("No key for session {}", session),
+        ),
+        Err(e) => {
+            error!(
+                "[HTTP] Failed to load share for session {}: {:?}",
+                session, e
+            );
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to load share: {}", e),
+            )
+        }
+    }
+}
+
+/// `GET /sessions/{id}` — status and result of a request, polled with its request id.
+async fn operation(State(handlers): State<Handlers>, Path(id): Path<String>) -> Response {
+    // A numeric path could be either kind of id; numbers are what the gateway sends
+    let mut candidates = Vec::new();
+    if let Ok(number) = id.parse::<u64>() {
+        candidates.push(RequestId::Number(number));
+    }
+    candidates.push(RequestId::Text(id.clone()));
+
+    for request_id in candidates {
+        match handlers.operation(&request_id).await {
+            Ok(Some(entry)) => return Json(from_entry(request_id, entry)).into_response(),
+            Ok(None) => {}
+            Err(e) => {
+                error!("[HTTP] Failed to look up request {}: {:?}", id, e);
+                return api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to read request ledger: {}", e),
+                );
+            }
+        }
+    }
+    api_error(StatusCode::NOT_FOUND, format!("Unknown request {}", id))
+}
+
+/// Running requests answer `202 Accepted` with the URL to poll; anything answered right away
+/// answers `200 OK` with its result.
+fn submission_response(id: RequestId, session: String, submitted: Submission) -> Response {
+    match submitted {
+        Submission::Started | Submission::InProgress => {
+            let location = format!("/sessions/{}", id);
+            let operation = Operation {
+                id,
+                session,
+                status: RequestStatus::Running.as_str(),
+                result: None,
+            };
+            (
+                StatusCode::ACCEPTED,
+                [(header::LOCATION, location)],
+                Json(operation),
+            )
+                .into_response()
+        }
+        Submission::Answered(response) => Json(Operation {
+            id,
+            session,
+            status: outcome(&response).as_str(),
+            result: Some(response),
+        })
+        .into_response(),
+        Submission::NotSigner(signers) => api_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("This node is not among signers {:?}", signers),
+        ),
+    }
+}
+
+fn from_entry(id: RequestId, entry: LedgerEntry) -> Operation {
+    let result = entry
+        .result
+        .as_deref()
+        .and_then(|json| match ControlResponse::from_json(json) {
+            Ok(response) => Some(response),
+            Err(e) => {
+                warn!(
+                    "[HTTP] Recorded result of request {} is unreadable: {}",
+                    id, e
+                );
+                None
+            }
+        });
+
+    Operation {
+        id,
+        session: entry.session,
+        status: entry.status.as_str(),
+        result,
+    }
+}
+
+fn api_error(status: StatusCode, error: String) -> Response {
+    (status, Json(ApiError { error })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::cluster;
+    use dkg_tcp::scheduler::SessionScheduler;
+    use serde_json::Value;
+    use tokio::time::{Duration, sleep};
+
+    async fn nodes(n: u16, threshold: u16) -> Vec<Handlers> {
+        cluster(n, threshold)
+            .await
+            .into_iter()
+            .map(|ctx| {
+                Handlers::new(
+                    ctx,
+                    SessionScheduler::new(4, 4).unwrap(),
+                    "session-001".into(),
+                )
+            })
+            .collect()
+    }
+
+    fn start_keygen(id: u64) -> Json<DkgStartRequest> {
+        Json(DkgStartRequest {
+            id: RequestId::Number(id),
+            session: None,
+            rotate: false,
+        })
+    }
+
+    async fn body(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    /// Polls `GET /sessions/{id}` until the request is no longer running.
+    async fn finished(handlers: &Handlers, id: &str) -> Value {
+        loop {
+            let response = operation(State(handlers.clone()), Path(id.to_string())).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let operation = body(response).await;
+            if operation["status"] != "running" {
+                return operation;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn keygen_is_accepted_and_polled_until_its_key_is_served() {
+        let nodes = nodes(2, 2).await;
+
+        for handlers in &nodes {
+            let response = keygen(State(handlers.clone()), start_keygen(1)).await;
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            assert_eq!(response.headers()[header::LOCATION], "/sessions/1");
+            let operation = body(response).await;
+            assert_eq!(operation["status"], "running");
+            assert_eq!(operation["session"], "session-001");
+        }
+
+        let mut keys = Vec::new();
+        for handlers in &nodes {
+            let operation = finished(handlers, "1").await;
+            assert_eq!(operation["status"], "succeeded");
+
+            let response = key(State(handlers.clone()), Path("session-001".into())).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let info = body(response).await;
+            assert_eq!(
+                (info["threshold"].as_u64(), info["n"].as_u64()),
+                (Some(2), Some(2))
+            );
+            keys.push(info["public_key"].clone());
+        }
+        assert_eq!(keys[0], keys[1]);
+
+        // A replay of the finished request is answered right away with its result
+        let response = keygen(State(nodes[0].clone()), start_keygen(1)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await["status"], "succeeded");
+    }
+
+    #[tokio::test]
+    async fn unknown_sessions_and_requests_are_not_found() {
+        let handlers = nodes(2, 2).await.remove(0);
+
+        let response = key(State(handlers.clone()), Path("session-001".into())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(body(response).await["error"].is_string());
+
+        let response = operation(State(handlers), Path("7".into())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn signing_requests_for_other_signers_are_unprocessable() {
+        let handlers = nodes(3, 2).await.remove(0);
+
+        let request = SignRequest {
+            id: RequestId::Number(2),
+            session: None,
+            message: "aGVsbG8=".into(),
+            signers: Some(vec![1, 2]),
+            format: Default::default(),
+            presigned: false,
+        };
+        let response = sign(State(handlers), Json(request)).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Key sessions with a protocol running on this node. Keygen and refresh replace a session's
+/// share, so they run alone; signing and presigning only read it and may run side by side.
+/// Nothing waits for a lock: a request that cannot take one fails right away, so two nodes
+/// holding locks in a different order never wait on each other.
+#[derive(Clone, Default)]
+pub struct SessionLocks(Arc<Mutex<HashMap<String, Holders>>>);
+
+enum Holders {
+    Exclusive,
+    Shared(usize),
+}
+
+impl SessionLocks {
+    /// Locks `session` for a protocol that writes its share, or returns `None` if anything runs
+    /// on it.
+    pub fn exclusive(&self, session: &str) -> Option<SessionLock> {
+        let mut held = self.0.lock().unwrap();
+        if held.contains_key(session) {
+            return None;
+        }
+        held.insert(session.to_string(), Holders::Exclusive);
+        Some(self.guard(session))
+    }
+
+    /// Locks `session` for a protocol that reads its share, or returns `None` if a keygen or
+    /// refresh runs on it.
+    pub fn shared(&self, session: &str) -> Option<SessionLock> {
+        let mut held = self.0.lock().unwrap();
+        match held
+            .entry(session.to_string())
+            .or_insert(Holders::Shared(0))
+        {
+            Holders::Exclusive => return None,
+            Holders::Shared(count) => *count += 1,
+        }
+        Some(self.guard(session))
+    }
+
+    fn guard(&self, session: &str) -> SessionLock {
+        SessionLock {
+            locks: self.0.clone(),
+            session: session.to_string(),
+        }
+    }
+}
+
+/// A lock on one key session, released when dropped.
+pub struct SessionLock {
+    locks: Arc<Mutex<HashMap<String, Holders>>>,
+    session: String,
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        let mut held = self.locks.lock().unwrap();
+        match held.get_mut(&self.session) {
+            Some(Holders::Shared(count)) if *count > 1 => *count -= 1,
+            _ => {
+                held.remove(&self.session);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readers_share_a_session_and_writers_run_alone() {
+        let locks = SessionLocks::default();
+        let first = locks.shared("session-001").unwrap();
+        let second = locks.shared("session-001").unwrap();
+        assert!(locks.exclusive("session-001").is_none());
+        assert!(locks.exclusive("session-002").is_some());
+
+        drop(first);
+        assert!(locks.exclusive("session-001").is_none());
+        drop(second);
+
+        let writer = locks.exclusive("session-001").unwrap();
+        assert!(locks.shared("session-001").is_none());
+        assert!(locks.exclusive("session-001").is_none());
+        drop(writer);
+        assert!(locks.shared("session-001").is_some());
+    }
+}
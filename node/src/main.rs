@@ -1,10 +1,17 @@
-mod server;
+mod grpc;
+mod handlers;
+mod http;
+mod locks;
+mod node;
+mod streams;
+#[cfg(test)]
+mod testing;
 
 use anyhow::Result;
 use dkg_tcp::env_loader::init_env;
 use tracing_subscriber::fmt;
 
-/// Entry point (only calls run_server)
+/// Entry point (only calls run_node)
 #[tokio::main]
 async fn main() -> Result<()> {
     fmt()
@@ -17,5 +24,5 @@ async fn main() -> Result<()> {
     init_env(env!("CARGO_MANIFEST_DIR"));
 
     // start the process
-    server::run_server().await
+    node::run_node().await
 }
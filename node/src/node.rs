@@ -0,0 +1,1553 @@
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, timeout};
+use tracing::{error, info, warn};
+
+use dkg_tcp::control::{
+    ControlRequest, ControlResponse, DKG_START_STREAM, DkgResult, DkgStartRequest, ErrorCode,
+    ErrorResult, MAX_BATCH_MESSAGES, MAX_PRESIGNATURES, MessageFormat, PresignRequest,
+    PresignResult, RefreshRequest, RefreshResult, RequestId, SIGN_START_STREAM, SignBatchResult,
+    SignResult,
+};
+use dkg_tcp::decode::{self, MessageSummary};
+use dkg_tcp::keygen::KeygenConfig;
+use dkg_tcp::offchain;
+use dkg_tcp::policy::PolicyEngine;
+use dkg_tcp::presign::{self, PresignatureMismatch};
+use dkg_tcp::progress::Progress;
+use dkg_tcp::refresh::{self, ShareVersionMismatch};
+use dkg_tcp::scheduler::SessionScheduler;
+use dkg_tcp::seal;
+use dkg_tcp::sign::InvalidSignature;
+use dkg_tcp::siws::SignInMessage;
+use dkg_tcp::store::{self, KeyShare, Store};
+use dkg_tcp::token::{self, TokenTransfer};
+use dkg_tcp::transport::BoxedStream;
+use dkg_tcp::transport::peers::PeerTable;
+use dkg_tcp::transport::session::{Handshake, ProtocolKind, SessionListener, open_links};
+use dkg_tcp::transport::tls::{self, NodeTls};
+use dkg_tcp::{keygen, sign};
+use redis::Client;
+use solana_hash::Hash;
+use solana_pubkey::Pubkey;
+use std::env;
+
+use crate::grpc;
+use crate::handlers::Handlers;
+use crate::http;
+use crate::locks::{SessionLock, SessionLocks};
+use crate::streams::{self, Responder, StreamConsumer};
+
+/// How long a session may take to connect to every other party.
+const LINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the keygen rounds may take once every party is connected.
+const DKG_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long the signing rounds of a single message may take.
+const SIGN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long the refresh rounds may take; every one of the `n` parties takes part, so the
+/// slowest of them sets the pace.
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Extra signing time allowed for every message of a batch after the first.
+const BATCH_MESSAGE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Structured environment configuration for an MPC node.
+#[derive(Debug, Clone)]
+struct EnvConfig {
+    n: u16,
+    threshold: u16,
+    node_id: u16,
+    peers: PeerTable,
+    listen_addr: String,
+    redis_url: Option<String>,
+    http_addr: Option<String>,
+    grpc_addr: Option<String>,
+    default_session: String,
+    database_url: String,
+    kek_file: Option<String>,
+    kek_passphrase: Option<String>,
+    kek_salt: Option<String>,
+    kek_version: u32,
+    tls_cert: Option<String>,
+    tls_key: Option<String>,
+    tls_ca: Option<String>,
+    max_concurrent_sessions: usize,
+    max_queued_sessions: usize,
+    policy_file: Option<String>,
+}
+
+impl EnvConfig {
+    /// Load all env variables and apply safe defaults.
+    fn load() -> Result<Self> {
+        let n = env::var("N")
+            .unwrap_or_else(|_| "2".into())
+            .parse::<u16>()
+            .context("N must be numeric")?;
+
+        // Defaults to n-of-n when no explicit threshold is configured
+        let threshold = match env::var("THRESHOLD") {
+            Ok(t) => t.parse::<u16>().context("THRESHOLD must be numeric")?,
+            Err(_) => n,
+        };
+
+        let node_id = env::var("NODE_ID")
+            .unwrap_or_else(|_| "0".into())
+            .parse::<u16>()
+            .context("NODE_ID must be numeric")?;
+        if node_id >= n {
+            bail!("NODE_ID {} is out of range for N={}", node_id, n);
+        }
+
+        // Every party's MPC listener, this node included
+        let peers = PeerTable::parse(
+            &env::var("PEERS").unwrap_or_else(|_| "0=127.0.0.1:7001,1=127.0.0.1:7002".into()),
+        )?;
+        peers.validate(n)?;
+
+        // Listens on the port from this node's own peer entry unless told otherwise
+        let listen_addr = match env::var("LISTEN_ADDR") {
+            Ok(addr) => addr,
+            Err(_) => {
+                let own = peers.addr(node_id)?;
+                let port = own.rsplit(':').next().unwrap_or(own);
+                format!("0.0.0.0:{}", port)
+            }
+        };
+
+        // `none` turns the Redis control plane off, e.g. for nodes driven over HTTP only
+        let redis_url = match env::var("REDIS_URL") {
+            Ok(url) if url == "none" => None,
+            Ok(url) => Some(url),
+            Err(_) => Some("redis://127.0.0.1:6379".into()),
+        };
+        if redis_url.is_none() && env::var("HTTP_ADDR").is_err() && env::var("GRPC_ADDR").is_err() {
+            bail!(
+                "REDIS_URL=none needs HTTP_ADDR or GRPC_ADDR, or the node cannot receive requests"
+            );
+        }
+
+        Ok(Self {
+            n,
+            threshold,
+            node_id,
+            peers,
+            listen_addr,
+
+            redis_url,
+
+            http_addr: env::var("HTTP_ADDR").ok(),
+
+            grpc_addr: env::var("GRPC_ADDR").ok(),
+
+            default_session: env::var("DEFAULT_SESSION_ID")
+                .unwrap_or_else(|_| "session-001".into()),
+
+            database_url: env::var("DATABASE_URL").unwrap_or_else(|_| "memory".into()),
+
+            kek_file: env::var("KEK_FILE").ok(),
+
+            kek_passphrase: env::var("KEK_PASSPHRASE").ok(),
+
+            kek_salt: env::var("KEK_SALT").ok(),
+
+            kek_version: env::var("KEK_VERSION")
+                .unwrap_or_else(|_| "1".into())
+                .parse::<u32>()
+                .context("KEK_VERSION must be numeric")?,
+
+            tls_cert: env::var("TLS_CERT").ok(),
+
+            tls_key: env::var("TLS_KEY").ok(),
+
+            tls_ca: env::var("TLS_CA").ok(),
+
+            max_concurrent_sessions: env::var("MAX_CONCURRENT_SESSIONS")
+                .unwrap_or_else(|_| "8".into())
+                .parse::<usize>()
+                .context("MAX_CONCURRENT_SESSIONS must be numeric")?,
+
+            max_queued_sessions: env::var("MAX_QUEUED_SESSIONS")
+                .unwrap_or_else(|_| "64".into())
+                .parse::<usize>()
+                .context("MAX_QUEUED_SESSIONS must be numeric")?,
+
+            policy_file: env::var("POLICY_FILE").ok(),
+        })
+    }
+}
+
+/// State shared by every session the node spawns.
+#[derive(Clone)]
+pub struct SessionContext {
+    pub store: Arc<dyn Store>,
+    pub listener: Arc<SessionListener>,
+    pub node_tls: Option<NodeTls>,
+    pub peers: Arc<PeerTable>,
+    pub keygen_config: KeygenConfig,
+    pub id: u16,
+    /// Checked before this node co-signs; without one it signs every valid request
+    pub policy: Option<Arc<PolicyEngine>>,
+    /// Key sessions with a protocol running, so a share is never replaced while in use
+    pub sessions: SessionLocks,
+}
+
+/// Starts the control planes of one node: the Redis loops and the HTTP API, as configured.
+pub async fn run_node() -> Result<()> {
+    let env_config = EnvConfig::load()?;
+
+    info!(
+        "Starting node [node_id={} of n={}] on {} with Redis={} HTTP={}",
+        env_config.node_id,
+        env_config.n,
+        env_config.listen_addr,
+        env_config.redis_url.as_deref().unwrap_or("off"),
+        env_config.http_addr.as_deref().unwrap_or("off")
+    );
+
+    // Persistent store for DKG shares
+    let keyring = seal::load_keyring(
+        env_config.kek_file.as_deref(),
+        env_config.kek_passphrase.as_deref(),
+        env_config.kek_salt.as_deref(),
+        env_config.kek_version,
+    )?;
+    let sealed = keyring.is_some();
+    let store = store::open_store(&env_config.database_url, keyring).await?;
+    if sealed {
+        // Picks up shares sealed under a rotated-out key or written before sealing was enabled
+        let rewrapped = store.rewrap().await?;
+        info!(
+            "Re-wrapped {} stored shares under the current KEK",
+            rewrapped
+        );
+    }
+
+    // Mutual TLS for the MPC links, if configured
+    let node_tls = tls::load_optional(
+        env_config.tls_cert.as_deref(),
+        env_config.tls_key.as_deref(),
+        env_config.tls_ca.as_deref(),
+    )?;
+    if node_tls.is_none() {
+        warn!("TLS_CERT/TLS_KEY/TLS_CA not set; MPC traffic will be plaintext and unauthenticated");
+    }
+
+    // One listener serves keygen and signing; handshakes say which session a link is for
+    let listener =
+        Arc::new(SessionListener::bind(&env_config.listen_addr, node_tls.clone()).await?);
+
+    // One cap shared by DKG and SIGN sessions on this node
+    let scheduler = SessionScheduler::new(
+        env_config.max_concurrent_sessions,
+        env_config.max_queued_sessions,
+    )?;
+
+    // Signing policy checked before every co-signature, if configured
+    let policy = match &env_config.policy_file {
+        Some(path) => {
+            info!("[POLICY] Loading signing policy from {}", path);
+            Some(Arc::new(PolicyEngine::load(path)?))
+        }
+        None => {
+            warn!("POLICY_FILE not set; this node will co-sign any valid request");
+            None
+        }
+    };
+
+    let ctx = SessionContext {
+        store,
+        listener,
+        node_tls,
+        peers: Arc::new(env_config.peers.clone()),
+        keygen_config: KeygenConfig::new(env_config.n, env_config.threshold)?,
+        id: env_config.node_id,
+        policy,
+        sessions: SessionLocks::default(),
+    };
+    let handlers = Handlers::new(ctx, scheduler, env_config.default_session.clone());
+
+    let mut tasks = JoinSet::new();
+
+    if let Some(redis_url) = &env_config.redis_url {
+        let redis_client = Client::open(redis_url.clone())?;
+
+        // Each node reads every request through its own consumer group
+        let group = format!("idmap-node-{}", env_config.node_id);
+        let consumer = format!("node-{}", env_config.node_id);
+        let responder = Responder::new(
+            redis_client.get_multiplexed_async_connection().await?,
+            &group,
+        );
+
+        let requests =
+            StreamConsumer::join(&redis_client, DKG_START_STREAM, &group, &consumer).await?;
+        tasks.spawn(streams::run_dkg_loop(
+            requests,
+            responder.clone(),
+            handlers.clone(),
+        ));
+
+        let requests =
+            StreamConsumer::join(&redis_client, SIGN_START_STREAM, &group, &consumer).await?;
+        tasks.spawn(streams::run_sign_loop(
+            requests,
+            responder.clone(),
+            handlers.clone(),
+        ));
+        info!("[NODE] Running DKG + SIGN loops concurrently...");
+    }
+
+    if let Some(http_addr) = &env_config.http_addr {
+        let http_listener = TcpListener::bind(http_addr).await?;
+        info!("[HTTP] Listening on {}", http_addr);
+        tasks.spawn(http::serve(http_listener, handlers.clone()));
+    }
+
+    if let Some(grpc_addr) = &env_config.grpc_addr {
+        let grpc_listener = TcpListener::bind(grpc_addr).await?;
+        info!("[GRPC] Listening on {}", grpc_addr);
+        tasks.spawn(grpc::serve(grpc_listener, handlers.clone()));
+    }
+
+    while tasks.join_next().await.is_some() {}
+    Ok(())
+}
+
+/// Runs one keygen session among all `n` parties and returns the resulting public key.
+/// Peer links and protocol rounds are reported to `progress` as they happen.
+pub async fn run_dkg_session(
+    ctx: SessionContext,
+    session: String,
+    request: DkgStartRequest,
+    progress: Progress,
+) -> ControlResponse {
+    let session = session.as_str();
+    let request_id = request.id;
+    let id = ctx.id as u64;
+    let fail = |code: ErrorCode, error: String| {
+        ControlResponse::DkgError(ErrorResult::new(Some(request_id.clone()), id, code, error))
+    };
+    info!("[DKG] Starting keygen session {}", session);
+
+    let handshake = Handshake::new(
+        ProtocolKind::Keygen,
+        session,
+        &request_id.to_string(),
+        ctx.id,
+    );
+    let parties: Vec<u16> = (0..ctx.keygen_config.n).collect();
+
+    // Only connections whose handshake names this session and request are accepted
+    let mut pending = match ctx
+        .listener
+        .register(handshake.kind, session, &handshake.request_id)
+    {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("[DKG] {:?}", e);
+            return fail(ErrorCode::SessionInProgress, e.to_string());
+        }
+    };
+
+    // Held until the share is stored, so no other request checks for or replaces it meanwhile
+    let _lock = match lock_session(&ctx, session, true) {
+        Ok(lock) => lock,
+        Err(e) => return fail(ErrorCode::SessionInProgress, e.to_string()),
+    };
+    match ctx.store.get(id, session).await {
+        Ok(Some(_)) if !request.rotate => {
+            warn!("[DKG] Session {} already has a key", session);
+            return fail(
+                ErrorCode::KeyExists,
+                format!(
+                    "Session {} already has a key; set `rotate` to replace it",
+                    session
+                ),
+            );
+        }
+        Ok(Some(_)) => warn!("[DKG] Rotating the key of session {}", session),
+        Ok(None) => {}
+        Err(e) => {
+            error!(
+                "[DKG] Failed to check for an existing share of session {}: {:?}",
+                session, e
+            );
+            return fail(
+                ErrorCode::StoreError,
+                format!("Failed to load share: {}", e),
+            );
+        }
+    }
+
+    // ✅ Bounded wait for every other party (prevents hanging if one never shows up)
+    let links = match open_links(
+        &mut pending,
+        ctx.node_tls.as_ref(),
+        &ctx.peers,
+        &handshake,
+        &parties,
+        LINK_TIMEOUT,
+        &progress,
+    )
+    .await
+    {
+        Ok(links) => links,
+        Err(e) => {
+            warn!("[DKG] {:?}", e);
+            return fail(ErrorCode::PeerUnavailable, format!("{:#}", e));
+        }
+    };
+    info!("[DKG] Connected to {} peers", links.len());
+
+    // ✅ Timeout for DKG computation (prevents indefinite wait)
+    let shares = match timeout(
+        DKG_TIMEOUT,
+        keygen::generate_private_share(links, id, ctx.keygen_config, session.as_bytes(), &progress),
+    )
+    .await
+    {
+        Ok(Ok(s)) => s,
+        Ok(Err(e)) => {
+            error!("[DKG] Key generation failed: {:?}", e);
+            return fail(
+                ErrorCode::ProtocolFailed,
+                format!("Key generation failed: {}", e),
+            );
+        }
+        Err(_) => {
+            error!("[DKG] DKG phase timed out for session {}", session);
+            return fail(ErrorCode::Timeout, "DKG phase timed out".into());
+        }
+    };
+
+    let pubkey = encode_public_key(&shares);
+
+    if let Err(e) = ctx.store.put(id, session, &shares).await {
+        error!(
+            "[DKG] Failed to store share for session {}: {:?}",
+            session, e
+        );
+        return fail(
+            ErrorCode::StoreError,
+            format!("Failed to store share: {}", e),
+        );
+    }
+
+    info!("[DKG] Stored share for session {}", session);
+
+    ControlResponse::Dkg(DkgResult {
+        id: request_id.clone(),
+        data: pubkey,
+        server_id: id,
+    })
+}
+
+/// Runs one refresh session among all `n` parties, which re-randomizes every party's share of
+/// the session's key and keeps its public key. Peer links and protocol rounds are reported to
+/// `progress` as they happen.
+pub async fn run_refresh_session(
+    ctx: SessionContext,
+    session: String,
+    request: RefreshRequest,
+    progress: Progress,
+) -> ControlResponse {
+    let session = session.as_str();
+    let request_id = request.id;
+    let id = ctx.id as u64;
+    let fail = |code: ErrorCode, error: String| {
+        ControlResponse::RefreshError(ErrorResult::new(Some(request_id.clone()), id, code, error))
+    };
+    info!("[REFRESH] Starting refresh of session {}", session);
+
+    let _lock = match lock_session(&ctx, session, true) {
+        Ok(lock) => lock,
+        Err(e) => return fail(ErrorCode::SessionInProgress, e.to_string()),
+    };
+    let n = match ctx.store.get(id, session).await {
+        Ok(Some(share)) => share.n(),
+        Ok(None) => {
+            warn!(
+                "[REFRESH] No share found for node {} session {}",
+                id, session
+            );
+            return fail(
+                ErrorCode::ShareNotFound,
+                format!("No share found for node {} session {}", id, session),
+            );
+        }
+        Err(e) => {
+            error!(
+                "[REFRESH] Failed to load share for session {}: {:?}",
+                session, e
+            );
+            return fail(
+                ErrorCode::StoreError,
+                format!("Failed to load share: {}", e),
+            );
+        }
+    };
+
+    let parties: Vec<u16> = (0..n).collect();
+    let links = match connect_signers(
+        &ctx,
+        ProtocolKind::Refresh,
+        session,
+        &request_id,
+        &parties,
+        &progress,
+        fail,
+    )
+    .await
+    {
+        Ok(links) => links,
+        Err(response) => return response,
+    };
+    info!("[REFRESH] Connected to {} peers", links.len());
+
+    // ✅ Timeout for the refresh rounds; a party that stops before committing keeps its old share
+    let refreshed = match timeout(
+        REFRESH_TIMEOUT,
+        refresh::run_refresh_phase(id, session, &*ctx.store, links, &progress),
+    )
+    .await
+    {
+        Ok(Ok(refreshed)) => refreshed,
+        Ok(Err(e)) => {
+            error!("[REFRESH] Refresh failed: {:?}", e);
+            return fail(ErrorCode::ProtocolFailed, format!("Refresh failed: {}", e));
+        }
+        Err(_) => {
+            error!("[REFRESH] Refresh phase timed out for session {}", session);
+            return fail(ErrorCode::Timeout, "Refresh phase timed out".into());
+        }
+    };
+    info!(
+        "[REFRESH] Committed share version {} for session {}",
+        refreshed.version, session
+    );
+
+    ControlResponse::Refresh(RefreshResult {
+        id: request_id.clone(),
+        data: encode_public_key(&refreshed.key_share),
+        version: refreshed.version,
+        server_id: id,
+    })
+}
+
+/// Runs one signing session among the `requested` signers, which include this node, and
+/// returns the signature or an error result. `request` is any request served on `sign-start`.
+/// Peer links and protocol rounds are reported to `progress` as they happen.
+pub async fn run_sign_session(
+    ctx: SessionContext,
+    session: String,
+    request: ControlRequest,
+    requested: Vec<u16>,
+    progress: Progress,
+) -> ControlResponse {
+    let session = session.as_str();
+    let id = ctx.id as u64;
+    let request_id = request.id().clone();
+    let fail = |code: ErrorCode, error: String| {
+        ControlResponse::SignError(ErrorResult::new(Some(request_id.clone()), id, code, error))
+    };
+
+    info!("[SIGN] Starting signing for session {}", session);
+
+    let _lock = match lock_session(&ctx, session, false) {
+        Ok(lock) => lock,
+        Err(e) => return fail(ErrorCode::SessionInProgress, e.to_string()),
+    };
+    let (valid_share, signers) = match load_signer_share(&ctx, session, &requested, fail).await {
+        Ok(loaded) => loaded,
+        Err(response) => return response,
+    };
+
+    let messages = match messages_to_sign(&request, &valid_share) {
+        Ok(messages) => messages,
+        Err(e) => {
+            error!("[SIGN] Failed to build message: {:?}", e);
+            return ControlResponse::SignError(ErrorResult {
+                index: e.downcast_ref::<BatchEntry>().map(|entry| entry.0),
+                ..ErrorResult::new(
+                    Some(request_id.clone()),
+                    id,
+                    ErrorCode::InvalidMessage,
+                    format!("{:#}", e),
+                )
+            });
+        }
+    };
+    let batch = matches!(request, ControlRequest::SignBatch(_));
+
+    // Raw requests are decoded too, so they are logged and checked like Solana ones
+    let offchain = request.format().is_some_and(MessageFormat::is_offchain);
+    let mut summaries: Vec<Option<Box<MessageSummary>>> = Vec::with_capacity(messages.len());
+    for (index, bytes) in messages.iter().enumerate() {
+        let summary = if offchain {
+            None
+        } else {
+            decode::summarize_bytes(bytes).ok().map(Box::new)
+        };
+        let label = match batch {
+            true => format!("{} message {}", request_id, index),
+            false => request_id.to_string(),
+        };
+        match &summary {
+            Some(summary) => info!("[SIGN] Request {} asks to sign {}", label, summary),
+            None if offchain => info!(
+                "[SIGN] Request {} asks to sign an off-chain or sign-in message of {} bytes",
+                label,
+                bytes.len()
+            ),
+            None => info!(
+                "[SIGN] Request {} asks to sign {} raw bytes",
+                label,
+                bytes.len()
+            ),
+        }
+        summaries.push(summary);
+    }
+
+    // ✅ Refused before connecting, so the other signers never get a share of the signature
+    let mut approvals = Vec::new();
+    if let Some(policy) = &ctx.policy {
+        let owner = match share_pubkey(&valid_share) {
+            Ok(owner) => Pubkey::from(owner),
+            Err(e) => return fail(ErrorCode::StoreError, format!("Invalid share: {}", e)),
+        };
+        // Approvals of earlier messages stay reserved, so a batch counts against the daily
+        // limit as a whole
+        for (index, summary) in summaries.iter().enumerate() {
+            let checked = if offchain {
+                policy.check_offchain(&owner)
+            } else {
+                policy.check(&owner, summary.as_deref())
+            };
+            match checked {
+                Ok(approval) => approvals.push(approval),
+                Err(violation) => {
+                    warn!("[POLICY] Refused to sign {}: {}", request_id, violation);
+                    return ControlResponse::SignError(ErrorResult {
+                        index: batch.then_some(index),
+                        ..ErrorResult::policy_violation(
+                            request_id.clone(),
+                            id,
+                            violation,
+                            summary.clone(),
+                        )
+                    });
+                }
+            }
+        }
+    }
+
+    // Checked before connecting, so the other signers keep theirs when this node has none
+    let presigned = matches!(&request, ControlRequest::Sign(r) if r.presigned);
+    if presigned {
+        match ctx.store.presignature_stock(id, session, &signers).await {
+            Ok(stock) if stock.available > 0 => {}
+            Ok(_) => return no_presignature(session, &signers, fail),
+            Err(e) => {
+                error!("[SIGN] Failed to count presignatures: {:?}", e);
+                return fail(
+                    ErrorCode::StoreError,
+                    format!("Failed to load presignatures: {}", e),
+                );
+            }
+        }
+    }
+
+    let mut links = match connect_signers(
+        &ctx,
+        ProtocolKind::Sign,
+        session,
+        &request_id,
+        &signers,
+        &progress,
+        fail,
+    )
+    .await
+    {
+        Ok(links) => links,
+        Err(response) => return response,
+    };
+    info!("[SIGN] Connected to signers {:?}", signers);
+    if let Err(response) = check_signer_versions(&ctx, session, &mut links, fail).await {
+        return response;
+    }
+
+    // ✅ Deleted from the store before its nonces are used, so it can never sign twice
+    let presignature = if presigned {
+        match ctx.store.take_presignature(id, session, &signers).await {
+            Ok(Some(presignature)) => {
+                info!(
+                    "[SIGN] Signing with presignature {} of session {}",
+                    presignature.id, session
+                );
+                Some(presignature)
+            }
+            Ok(None) => return no_presignature(session, &signers, fail),
+            Err(e) => {
+                error!("[SIGN] Failed to take a presignature: {:?}", e);
+                return fail(
+                    ErrorCode::StoreError,
+                    format!("Failed to load presignatures: {}", e),
+                );
+            }
+        }
+    } else {
+        None
+    };
+
+    // ✅ Timeout for signing phase itself, with more time for every extra message of a batch
+    let signing_timeout = SIGN_TIMEOUT + BATCH_MESSAGE_TIMEOUT * (messages.len() as u32 - 1);
+    let signing = async {
+        if let Some(presignature) = presignature {
+            let message = messages.into_iter().next().unwrap_or_default();
+            presign::run_presigned_signing_phase(
+                id,
+                &valid_share,
+                links,
+                presignature,
+                &message,
+                &progress,
+            )
+            .await
+            .map(|signature| vec![signature])
+        } else if batch {
+            sign::run_batch_signing_phase(id, valid_share, &signers, links, messages, &progress)
+                .await
+        } else {
+            let message = messages.into_iter().next().unwrap_or_default();
+            sign::run_signing_phase(id, valid_share, &signers, links, message, &progress)
+                .await
+                .map(|signature| vec![signature])
+        }
+    };
+    match timeout(signing_timeout, signing).await {
+        Ok(Ok(signatures)) => {
+            info!(
+                "[SIGN] Signed {} messages for session {}",
+                signatures.len(),
+                session
+            );
+            // Failed sessions drop the approvals, which gives back their daily allowance
+            for approval in approvals {
+                approval.commit();
+            }
+            if batch {
+                ControlResponse::SignBatch(SignBatchResult {
+                    id: request_id.clone(),
+                    data: signatures.iter().map(ToString::to_string).collect(),
+                    server_id: id,
+                    summaries: match summaries.iter().any(Option::is_some) {
+                        true => summaries.into_iter().map(|s| s.map(|s| *s)).collect(),
+                        false => Vec::new(),
+                    },
+                })
+            } else {
+                ControlResponse::Sign(SignResult {
+                    id: request_id.clone(),
+                    data: signatures[0].to_string(),
+                    server_id: id,
+                    summary: summaries.pop().flatten(),
+                })
+            }
+        }
+        Ok(Err(e)) if e.is::<InvalidSignature>() => {
+            error!("[SIGN] {}", e);
+            fail(ErrorCode::InvalidSignature, e.to_string())
+        }
+        Ok(Err(e)) if e.is::<PresignatureMismatch>() => {
+            // Every signer discards up to the same id, so the retry picks the same one
+            let highest = e
+                .downcast_ref::<PresignatureMismatch>()
+                .map_or(0, |m| m.highest);
+            match ctx
+                .store
+                .discard_presignatures(id, session, &signers, highest)
+                .await
+            {
+                Ok(discarded) => warn!("[SIGN] {}; discarded {} stale presignatures", e, discarded),
+                Err(store_error) => error!(
+                    "[SIGN] {}; failed to discard stale presignatures: {:?}",
+                    e, store_error
+                ),
+            }
+            fail(ErrorCode::ProtocolFailed, format!("Signing failed: {}", e))
+        }
+        Ok(Err(e)) => {
+            error!("[SIGN] Signing failed: {:?}", e);
+            fail(ErrorCode::ProtocolFailed, format!("Signing failed: {}", e))
+        }
+        Err(_) => {
+            error!("[SIGN] Signing phase timed out for session {}", session);
+            fail(ErrorCode::Timeout, "Signing phase timed out".into())
+        }
+    }
+}
+
+/// Runs one presign session among the `requested` signers, which include this node, and
+/// stores the presignatures it generates for later single-round signing. Peer links and
+/// protocol rounds are reported to `progress` as they happen.
+pub async fn run_presign_session(
+    ctx: SessionContext,
+    session: String,
+    request: PresignRequest,
+    requested: Vec<u16>,
+    progress: Progress,
+) -> ControlResponse {
+    let session = session.as_str();
+    let id = ctx.id as u64;
+    let request_id = request.id.clone();
+    let fail = |code: ErrorCode, error: String| {
+        ControlResponse::SignError(ErrorResult::new(Some(request_id.clone()), id, code, error))
+    };
+
+    info!(
+        "[PRESIGN] Generating {} presignatures for session {}",
+        request.count, session
+    );
+    if request.count == 0 || request.count > MAX_PRESIGNATURES {
+        return fail(
+            ErrorCode::MalformedRequest,
+            format!(
+                "`count` must be between 1 and {}, not {}",
+                MAX_PRESIGNATURES, request.count
+            ),
+        );
+    }
+
+    let _lock = match lock_session(&ctx, session, false) {
+        Ok(lock) => lock,
+        Err(e) => return fail(ErrorCode::SessionInProgress, e.to_string()),
+    };
+    let (valid_share, signers) = match load_signer_share(&ctx, session, &requested, fail).await {
+        Ok(loaded) => loaded,
+        Err(response) => return response,
+    };
+
+    let first_id = match ctx.store.presignature_stock(id, session, &signers).await {
+        Ok(stock) => stock.next_id,
+        Err(e) => {
+            error!("[PRESIGN] Failed to count presignatures: {:?}", e);
+            return fail(
+                ErrorCode::StoreError,
+                format!("Failed to load presignatures: {}", e),
+            );
+        }
+    };
+
+    let mut links = match connect_signers(
+        &ctx,
+        ProtocolKind::Presign,
+        session,
+        &request_id,
+        &signers,
+        &progress,
+        fail,
+    )
+    .await
+    {
+        Ok(links) => links,
+        Err(response) => return response,
+    };
+    info!("[PRESIGN] Connected to signers {:?}", signers);
+    if let Err(response) = check_signer_versions(&ctx, session, &mut links, fail).await {
+        return response;
+    }
+
+    let presignatures = match timeout(
+        SIGN_TIMEOUT,
+        presign::run_presign_phase(
+            id,
+            &valid_share,
+            &signers,
+            links,
+            request.count,
+            first_id,
+            &progress,
+        ),
+    )
+    .await
+    {
+        Ok(Ok(presignatures)) => presignatures,
+        Ok(Err(e)) => {
+            error!("[PRESIGN] Presigning failed: {:?}", e);
+            return fail(
+                ErrorCode::ProtocolFailed,
+                format!("Presigning failed: {}", e),
+            );
+        }
+        Err(_) => {
+            error!("[PRESIGN] Presign phase timed out for session {}", session);
+            return fail(ErrorCode::Timeout, "Presign phase timed out".into());
+        }
+    };
+
+    let count = presignatures.len();
+    let stored = ctx
+        .store
+        .put_presignatures(id, session, presignatures)
+        .await;
+    let stock = match stored {
+        Ok(()) => ctx.store.presignature_stock(id, session, &signers).await,
+        Err(e) => Err(e),
+    };
+    let available = match stock {
+        Ok(stock) => stock.available,
+        Err(e) => {
+            error!("[PRESIGN] Failed to store presignatures: {:?}", e);
+            return fail(
+                ErrorCode::StoreError,
+                format!("Failed to store presignatures: {}", e),
+            );
+        }
+    };
+    info!(
+        "[PRESIGN] Stored {} presignatures for session {}; {} available",
+        count, session, available
+    );
+
+    ControlResponse::Presign(PresignResult {
+        id: request_id.clone(),
+        count,
+        available,
+        server_id: id,
+    })
+}
+
+/// Takes this node's lock on `session`, exclusive for protocols that replace its share. Fails
+/// when another request holds it; answer such a request with `session_in_progress`.
+///
+/// # Arguments
+/// * `ctx` - Node context holding the session locks
+/// * `session` - Key session the request runs for
+/// * `exclusive` - Whether the request writes the session's share
+fn lock_session(ctx: &SessionContext, session: &str, exclusive: bool) -> Result<SessionLock> {
+    let lock = match exclusive {
+        true => ctx.sessions.exclusive(session),
+        false => ctx.sessions.shared(session),
+    };
+    lock.ok_or_else(|| {
+        warn!("[NODE] Another request is running on session {}", session);
+        anyhow!("Another request is running on session {}", session)
+    })
+}
+
+/// This node's share of `session` and the canonical signer set of a sign-channel request,
+/// or the error result to answer with.
+///
+/// # Arguments
+/// * `ctx` - Node context holding the store
+/// * `session` - Key session the request runs for
+/// * `requested` - Signer set named by the request, or the default one
+/// * `fail` - Builds the error result for the request
+async fn load_signer_share(
+    ctx: &SessionContext,
+    session: &str,
+    requested: &[u16],
+    fail: impl Fn(ErrorCode, String) -> ControlResponse,
+) -> Result<(KeyShare, Vec<u16>), ControlResponse> {
+    let id = ctx.id as u64;
+    let share = match ctx.store.get(id, session).await {
+        Ok(Some(share)) => share,
+        Ok(None) => {
+            warn!("[SIGN] No share found for node {} session {}", id, session);
+            return Err(fail(
+                ErrorCode::ShareNotFound,
+                format!("No share found for node {} session {}", id, session),
+            ));
+        }
+        Err(e) => {
+            error!(
+                "[SIGN] Failed to load share for session {}: {:?}",
+                session, e
+            );
+            return Err(fail(
+                ErrorCode::StoreError,
+                format!("Failed to load share: {}", e),
+            ));
+        }
+    };
+
+    match sign::signer_set(&share, requested) {
+        Ok(signers) => Ok((share, signers)),
+        Err(e) => {
+            warn!("[SIGN] {:?}", e);
+            Err(fail(ErrorCode::InvalidSigners, e.to_string()))
+        }
+    }
+}
+
+/// Opens a link to every other signer for one request, or returns the error result to answer
+/// with.
+///
+/// # Arguments
+/// * `ctx` - Node context holding the listener and peer table
+/// * `kind` - Protocol the links are for
+/// * `session` - Key session the request runs for
+/// * `request_id` - The request, so concurrent requests on one session get separate links
+/// * `signers` - Every signer, this node included
+/// * `progress` - Receives each link as it opens
+/// * `fail` - Builds the error result for the request
+async fn connect_signers(
+    ctx: &SessionContext,
+    kind: ProtocolKind,
+    session: &str,
+    request_id: &RequestId,
+    signers: &[u16],
+    progress: &Progress,
+    fail: impl Fn(ErrorCode, String) -> ControlResponse,
+) -> Result<Vec<(u16, BoxedStream)>, ControlResponse> {
+    let handshake = Handshake::new(kind, session, &request_id.to_string(), ctx.id);
+
+    // Only connections whose handshake names this session and request are accepted
+    let mut pending = match ctx
+        .listener
+        .register(handshake.kind, session, &handshake.request_id)
+    {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("[NODE] {:?}", e);
+            return Err(fail(ErrorCode::SessionInProgress, e.to_string()));
+        }
+    };
+
+    // ✅ Bounded wait for the other signers (prevents hanging if one never shows up)
+    open_links(
+        &mut pending,
+        ctx.node_tls.as_ref(),
+        &ctx.peers,
+        &handshake,
+        signers,
+        LINK_TIMEOUT,
+        progress,
+    )
+    .await
+    .map_err(|e| {
+        warn!("[NODE] {:?}", e);
+        fail(ErrorCode::PeerUnavailable, format!("{:#}", e))
+    })
+}
+
+/// Checks that every other signer holds the same version of the session's share as this node,
+/// so signers left out of step by a partial refresh fail with `share_version_mismatch` before
+/// any signing round, or returns the error result to answer with.
+///
+/// # Arguments
+/// * `ctx` - Node context holding the store
+/// * `session` - Key session the request runs for
+/// * `links` - Open links to every other signer
+/// * `fail` - Builds the error result for the request
+async fn check_signer_versions(
+    ctx: &SessionContext,
+    session: &str,
+    links: &mut [(u16, BoxedStream)],
+    fail: impl Fn(ErrorCode, String) -> ControlResponse,
+) -> Result<(), ControlResponse> {
+    let version = match ctx.store.share_version(ctx.id as u64, session).await {
+        Ok(version) => version.unwrap_or(0),
+        Err(e) => {
+            error!(
+                "[SIGN] Failed to load the share version of session {}: {:?}",
+                session, e
+            );
+            return Err(fail(
+                ErrorCode::StoreError,
+                format!("Failed to load share version: {}", e),
+            ));
+        }
+    };
+
+    match timeout(LINK_TIMEOUT, refresh::check_share_versions(version, links)).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) if e.is::<ShareVersionMismatch>() => {
+            warn!("[SIGN] Session {}: {}", session, e);
+            Err(fail(ErrorCode::ShareVersionMismatch, e.to_string()))
+        }
+        Ok(Err(e)) => {
+            warn!("[NODE] {:?}", e);
+            Err(fail(ErrorCode::PeerUnavailable, format!("{:#}", e)))
+        }
+        Err(_) => {
+            warn!(
+                "[NODE] Signers of session {} did not send their share versions in time",
+                session
+            );
+            Err(fail(
+                ErrorCode::PeerUnavailable,
+                "Signers did not send their share versions in time".into(),
+            ))
+        }
+    }
+}
+
+/// Error result of a presigned sign request when this node has no presignature left.
+fn no_presignature(
+    session: &str,
+    signers: &[u16],
+    fail: impl Fn(ErrorCode, String) -> ControlResponse,
+) -> ControlResponse {
+    warn!(
+        "[SIGN] No presignature left for session {} and signers {:?}",
+        session, signers
+    );
+    fail(
+        ErrorCode::PresignatureUnavailable,
+        format!(
+            "No presignature left for session {} and signers {:?}",
+            session, signers
+        ),
+    )
+}
+
+/// Bytes a sign-channel request asks the session key to sign: the decoded `message`s, or a
+/// message built from the request with the session key as fee payer.
+///
+/// # Arguments
+/// * `request` - Any request served on `sign-start`
+/// * `share` - This node's share of the session key
+fn messages_to_sign(request: &ControlRequest, share: &KeyShare) -> Result<Vec<Vec<u8>>> {
+    let owner = Pubkey::from(share_pubkey(share)?);
+    let message = match request {
+        ControlRequest::Sign(r) => return Ok(vec![decode_message(&r.message, r.format, &owner)?]),
+        ControlRequest::SignBatch(r) => {
+            ensure!(!r.messages.is_empty(), "Batch has no messages");
+            ensure!(
+                r.messages.len() <= MAX_BATCH_MESSAGES,
+                "Batch has {} messages, more than the {} allowed",
+                r.messages.len(),
+                MAX_BATCH_MESSAGES
+            );
+            return r
+                .messages
+                .iter()
+                .enumerate()
+                .map(|(index, message)| {
+                    decode_message(message, r.format, &owner).context(BatchEntry(index))
+                })
+                .collect();
+        }
+        ControlRequest::SignTokenTransfer(r) => {
+            let transfer = TokenTransfer {
+                owner,
+                mint: parse_pubkey("mint", &r.mint)?,
+                recipient: parse_pubkey("recipient", &r.recipient)?,
+                amount: r.amount,
+                decimals: r.decimals,
+                program: r.program,
+                create_recipient_account: r.create_recipient_account,
+                memo: r.memo.clone(),
+            };
+            token::token_transfer_message(&transfer, parse_blockhash(&r.recent_blockhash)?)
+        }
+        ControlRequest::SignCreateTokenAccount(r) => token::create_token_account_message(
+            &owner,
+            &parse_pubkey("wallet", &r.wallet)?,
+            &parse_pubkey("mint", &r.mint)?,
+            r.program,
+            parse_blockhash(&r.recent_blockhash)?,
+        ),
+        ControlRequest::StartDkg(_) | ControlRequest::Refresh(_) => {
+            bail!("Keygen and refresh requests are not signed")
+        }
+        ControlRequest::Presign(_) => bail!("Presign requests sign no message"),
+    };
+    Ok(vec![message.serialize()])
+}
+
+/// Decodes a base64 `message` and checks it is what `format` says it is.
+///
+/// # Arguments
+/// * `message` - Base64 message from a sign or sign-batch request
+/// * `format` - How the message is signed
+/// * `owner` - The session key
+fn decode_message(message: &str, format: MessageFormat, owner: &Pubkey) -> Result<Vec<u8>> {
+    let bytes = BASE64
+        .decode(message)
+        .context("Message is not valid base64")?;
+    match format {
+        MessageFormat::Raw => {}
+        MessageFormat::Solana => check_solana_message(&bytes, owner)?,
+        MessageFormat::Offchain => return offchain::offchain_message(&bytes),
+        MessageFormat::SignIn => check_sign_in(&bytes, owner)?,
+    }
+    Ok(bytes)
+}
+
+/// Context naming the entry of a sign-batch request that failed to decode.
+#[derive(Debug)]
+struct BatchEntry(usize);
+
+impl std::fmt::Display for BatchEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Message {}", self.0)
+    }
+}
+
+/// Checks that `bytes` are a legacy or v0 message the session key `owner` signs.
+fn check_solana_message(bytes: &[u8], owner: &Pubkey) -> Result<()> {
+    let message = sign::parse_message(bytes).context("Message is not a Solana message")?;
+    let required = usize::from(message.header().num_required_signatures);
+    if !message.static_account_keys()[..required].contains(owner) {
+        bail!("Message does not require a signature from {}", owner);
+    }
+    Ok(())
+}
+
+/// Checks that `bytes` are the text of a sign-in message for the session key `owner`.
+fn check_sign_in(bytes: &[u8], owner: &Pubkey) -> Result<()> {
+    let text = std::str::from_utf8(bytes).context("Sign-in message is not UTF-8")?;
+    let message = SignInMessage::parse(text)?;
+    ensure!(
+        message.address == *owner,
+        "Sign-in message is for {}, not the session key {}",
+        message.address,
+        owner
+    );
+    // Never the case for a well-formed sign-in, but a login must not double as a transaction
+    ensure!(
+        sign::parse_message(bytes).is_err(),
+        "Sign-in message is also a Solana message"
+    );
+    Ok(())
+}
+
+fn parse_pubkey(field: &str, value: &str) -> Result<Pubkey> {
+    Pubkey::from_str(value).with_context(|| format!("`{}` is not a base58 address", field))
+}
+
+fn parse_blockhash(value: &str) -> Result<Hash> {
+    Hash::from_str(value).context("`recent_blockhash` is not a base58 blockhash")
+}
+
+/// The session key as a Solana address.
+fn share_pubkey(share: &KeyShare) -> Result<[u8; 32]> {
+    Ok(share
+        .shared_public_key()
+        .to_bytes(true)
+        .as_ref()
+        .try_into()?)
+}
+
+/// Base58 form of a key session's shared public key, as reported in keygen results.
+pub fn encode_public_key(share: &KeyShare) -> String {
+    bs58::encode(share.shared_public_key().to_bytes(true)).into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::cluster;
+    use dkg_tcp::control::{SignBatchRequest, SignRequest};
+    use dkg_tcp::policy::{Policy, PolicyRule};
+    use dkg_tcp::token::TokenProgram;
+    use solana_signature::Signature;
+
+    fn keygen(id: u64, rotate: bool) -> DkgStartRequest {
+        DkgStartRequest {
+            id: RequestId::Number(id),
+            session: Some("session-001".into()),
+            rotate,
+        }
+    }
+
+    /// Runs `request` on every node at once.
+    async fn run_keygen(
+        nodes: &[SessionContext],
+        request: DkgStartRequest,
+    ) -> Vec<ControlResponse> {
+        let runs: Vec<_> = nodes
+            .iter()
+            .map(|ctx| {
+                tokio::spawn(run_dkg_session(
+                    ctx.clone(),
+                    "session-001".into(),
+                    request.clone(),
+                    Progress::default(),
+                ))
+            })
+            .collect();
+        let mut responses = Vec::new();
+        for run in runs {
+            responses.push(run.await.unwrap());
+        }
+        responses
+    }
+
+    fn public_key(response: &ControlResponse) -> &str {
+        match response {
+            ControlResponse::Dkg(result) => &result.data,
+            other => panic!("expected a keygen result, got {}", other.to_json()),
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_keygen_requests_for_one_session_never_replace_its_key() {
+        let nodes = cluster(2, 2).await;
+
+        let first: Vec<_> = nodes
+            .iter()
+            .map(|ctx| {
+                tokio::spawn(run_dkg_session(
+                    ctx.clone(),
+                    "session-001".into(),
+                    keygen(1, false),
+                    Progress::default(),
+                ))
+            })
+            .collect();
+        // Wait until both nodes run the first request
+        while nodes
+            .iter()
+            .any(|ctx| ctx.sessions.exclusive("session-001").is_some())
+        {
+            tokio::task::yield_now().await;
+        }
+
+        // A second keygen, or anything else, for the same session is turned away meanwhile
+        for response in run_keygen(&nodes, keygen(2, false)).await {
+            assert_eq!(response.error_code(), Some(ErrorCode::SessionInProgress));
+        }
+        let sign = ControlRequest::Sign(SignRequest {
+            id: RequestId::Number(3),
+            session: Some("session-001".into()),
+            message: "aGVsbG8=".into(),
+            signers: None,
+            format: MessageFormat::Raw,
+            presigned: false,
+        });
+        let refused = run_sign_session(
+            nodes[0].clone(),
+            "session-001".into(),
+            sign,
+            vec![0, 1],
+            Progress::default(),
+        )
+        .await;
+        assert_eq!(refused.error_code(), Some(ErrorCode::SessionInProgress));
+
+        let mut keys = Vec::new();
+        for run in first {
+            keys.push(public_key(&run.await.unwrap()).to_string());
+        }
+        assert_eq!(keys[0], keys[1]);
+
+        // Once it has a key, only a rotation replaces it
+        for response in run_keygen(&nodes, keygen(2, false)).await {
+            assert_eq!(response.error_code(), Some(ErrorCode::KeyExists));
+        }
+        let rotated = run_keygen(&nodes, keygen(4, true)).await;
+        assert_eq!(public_key(&rotated[0]), public_key(&rotated[1]));
+        assert_ne!(public_key(&rotated[0]), keys[0]);
+    }
+
+    #[test]
+    fn solana_messages_must_require_the_session_key_signature() {
+        let owner = Pubkey::new_unique();
+        let transfer = TokenTransfer {
+            owner,
+            mint: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            amount: 5,
+            decimals: 6,
+            program: TokenProgram::Token,
+            create_recipient_account: true,
+            memo: None,
+        };
+        let instructions = token::token_transfer_instructions(&transfer);
+        let v0 = sign::v0_message(&owner, &instructions, &[], Hash::default()).unwrap();
+        let legacy = token::token_transfer_message(&transfer, Hash::default());
+
+        for bytes in [v0.serialize(), legacy.serialize()] {
+            check_solana_message(&bytes, &owner).unwrap();
+            assert!(check_solana_message(&bytes, &Pubkey::new_unique()).is_err());
+        }
+        assert!(check_solana_message(b"hello", &owner).is_err());
+    }
+
+    fn sign_batch(id: u64, messages: &[&[u8]]) -> ControlRequest {
+        ControlRequest::SignBatch(SignBatchRequest {
+            id: RequestId::Number(id),
+            session: Some("session-001".into()),
+            messages: messages.iter().map(|m| BASE64.encode(m)).collect(),
+            signers: None,
+            format: MessageFormat::Raw,
+        })
+    }
+
+    /// Runs `request` on every node at once, each signing with all of them.
+    async fn run_sign(nodes: &[SessionContext], request: ControlRequest) -> Vec<ControlResponse> {
+        let signers: Vec<u16> = (0..nodes.len() as u16).collect();
+        let runs: Vec<_> = nodes
+            .iter()
+            .map(|ctx| {
+                tokio::spawn(run_sign_session(
+                    ctx.clone(),
+                    "session-001".into(),
+                    request.clone(),
+                    signers.clone(),
+                    Progress::default(),
+                ))
+            })
+            .collect();
+        let mut responses = Vec::new();
+        for run in runs {
+            responses.push(run.await.unwrap());
+        }
+        responses
+    }
+
+    fn error(response: &ControlResponse) -> &ErrorResult {
+        match response {
+            ControlResponse::SignError(error) => error,
+            other => panic!("expected a sign error, got {}", other.to_json()),
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_signatures_follow_the_order_of_the_messages() {
+        let nodes = cluster(2, 2).await;
+        let keys = run_keygen(&nodes, keygen(1, false)).await;
+        let owner = Pubkey::from_str(public_key(&keys[0])).unwrap();
+        let messages: [&[u8]; 3] = [b"first", b"second", b"third"];
+
+        let responses = run_sign(&nodes, sign_batch(2, &messages)).await;
+
+        for response in &responses {
+            let ControlResponse::SignBatch(result) = response else {
+                panic!("expected a batch result, got {}", response.to_json());
+            };
+            assert_eq!(result.data.len(), messages.len());
+            for (signature, message) in result.data.iter().zip(messages) {
+                let signature = Signature::from_str(signature).unwrap();
+                assert!(signature.verify(owner.as_ref(), message));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_errors_name_the_message_they_failed_on() {
+        let mut nodes = cluster(2, 2).await;
+        let keys = run_keygen(&nodes, keygen(1, false)).await;
+        let owner = Pubkey::from_str(public_key(&keys[0])).unwrap();
+
+        let mut request = sign_batch(2, &[b"first", b"second"]);
+        if let ControlRequest::SignBatch(batch) = &mut request {
+            batch.messages.insert(1, "not base64!".into());
+        }
+        let response = run_sign_session(
+            nodes[0].clone(),
+            "session-001".into(),
+            request,
+            vec![0, 1],
+            Progress::default(),
+        )
+        .await;
+        assert_eq!(response.error_code(), Some(ErrorCode::InvalidMessage));
+        assert_eq!(error(&response).index, Some(1));
+
+        // A policy that signs only Solana messages refuses the raw bytes after the transfer
+        nodes[0].policy = Some(Arc::new(PolicyEngine::new(Policy::default())));
+        let transfer = sign::transfer_message(
+            &owner.to_string(),
+            "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin",
+            1,
+        )
+        .unwrap();
+        let transfer = transfer.serialize();
+        let response = run_sign_session(
+            nodes[0].clone(),
+            "session-001".into(),
+            sign_batch(3, &[&transfer, b"raw bytes"]),
+            vec![0, 1],
+            Progress::default(),
+        )
+        .await;
+        assert_eq!(response.error_code(), Some(ErrorCode::PolicyViolation));
+        let error = error(&response);
+        assert_eq!(error.index, Some(1));
+        assert_eq!(
+            error.violation.as_ref().unwrap().rule,
+            PolicyRule::RawMessage
+        );
+    }
+
+    /// Runs a refresh of `session-001` on every node at once.
+    async fn run_refresh(nodes: &[SessionContext], id: u64) -> Vec<ControlResponse> {
+        let request = RefreshRequest {
+            id: RequestId::Number(id),
+            session: Some("session-001".into()),
+        };
+        let runs: Vec<_> = nodes
+            .iter()
+            .map(|ctx| {
+                tokio::spawn(run_refresh_session(
+                    ctx.clone(),
+                    "session-001".into(),
+                    request.clone(),
+                    Progress::default(),
+                ))
+            })
+            .collect();
+        let mut responses = Vec::new();
+        for run in runs {
+            responses.push(run.await.unwrap());
+        }
+        responses
+    }
+
+    #[tokio::test]
+    async fn refresh_keeps_the_public_key_and_signers_sign_with_the_new_shares() {
+        let nodes = cluster(3, 2).await;
+        let keys = run_keygen(&nodes, keygen(1, false)).await;
+        let owner = Pubkey::from_str(public_key(&keys[0])).unwrap();
+
+        for response in run_refresh(&nodes, 2).await {
+            let ControlResponse::Refresh(result) = response else {
+                panic!("expected a refresh result, got {}", response.to_json());
+            };
+            assert_eq!(result.data, owner.to_string());
+            assert_eq!(result.version, 1);
+        }
+
+        for response in run_sign(&nodes[..2], sign_batch(3, &[b"hello"])).await {
+            let ControlResponse::SignBatch(result) = &response else {
+                panic!("expected a batch result, got {}", response.to_json());
+            };
+            let signature = Signature::from_str(&result.data[0]).unwrap();
+            assert!(signature.verify(owner.as_ref(), b"hello"));
+        }
+    }
+
+    #[tokio::test]
+    async fn signers_on_different_share_versions_answer_share_version_mismatch() {
+        let nodes = cluster(2, 2).await;
+        run_keygen(&nodes, keygen(1, false)).await;
+
+        // Node 0 committed a refresh that node 1 never heard of
+        let share = nodes[0].store.get(0, "session-001").await.unwrap().unwrap();
+        nodes[0]
+            .store
+            .stage_share(0, "session-001", 1, &share)
+            .await
+            .unwrap();
+        nodes[0]
+            .store
+            .commit_share(0, "session-001", 1)
+            .await
+            .unwrap();
+
+        for response in run_sign(&nodes, sign_batch(2, &[b"hello"])).await {
+            assert_eq!(response.error_code(), Some(ErrorCode::ShareVersionMismatch));
+        }
+    }
+}
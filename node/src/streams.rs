@@ -0,0 +1,463 @@
+use anyhow::Result;
+use redis::aio::MultiplexedConnection;
+use redis::streams::{StreamMaxlen, StreamReadOptions, StreamReadReply};
+use redis::{AsyncCommands, Client, RedisResult};
+use tokio::time::{Duration, sleep};
+use tracing::{debug, error, info, warn};
+
+use dkg_tcp::control::{
+    self, ControlRequest, ControlResponse, DKG_START_STREAM, ErrorCode, ErrorResult, Incoming,
+    SIGN_START_STREAM,
+};
+
+use crate::handlers::{Handlers, Submission};
+
+/// Field every control-plane stream entry carries its JSON message in.
+pub const PAYLOAD_FIELD: &str = "payload";
+
+/// Approximate number of entries kept on each result stream.
+const RESULT_STREAM_MAXLEN: usize = 10_000;
+
+/// How long one read blocks waiting for new requests.
+const BLOCK_MS: usize = 5_000;
+
+/// Entries fetched per read.
+const READ_COUNT: usize = 16;
+
+/// Pause before reconnecting after a Redis error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// A request entry read from a stream.
+pub struct Delivery {
+    pub entry_id: String,
+    /// JSON request, `None` if the entry has no payload or was trimmed while pending
+    pub payload: Option<String>,
+}
+
+/// Reads one request stream through this node's consumer group. Entries stay pending until
+/// acknowledged, and pending entries are replayed first after startup or a reconnect.
+pub struct StreamConsumer {
+    client: Client,
+    // Dedicated connection: a blocking read would otherwise stall every other command on it
+    conn: MultiplexedConnection,
+    stream: &'static str,
+    group: String,
+    consumer: String,
+    /// Position in this consumer's pending entries while they are replayed; `None` once caught up
+    pending_cursor: Option<String>,
+}
+
+impl StreamConsumer {
+    /// Connects and creates the consumer group if it does not exist yet.
+    ///
+    /// # Arguments
+    /// * `client` - Redis client
+    /// * `stream` - Request stream to read
+    /// * `group` - Consumer group of this node; every node reads every request through its own group
+    /// * `consumer` - Consumer name within the group
+    pub async fn join(
+        client: &Client,
+        stream: &'static str,
+        group: &str,
+        consumer: &str,
+    ) -> Result<Self> {
+        let mut conn = client.get_multiplexed_async_connection().await?;
+        create_group(&mut conn, stream, group).await?;
+        info!("Reading stream `{}` as {}/{}", stream, group, consumer);
+
+        Ok(Self {
+            client: client.clone(),
+            conn,
+            stream,
+            group: group.to_string(),
+            consumer: consumer.to_string(),
+            pending_cursor: Some("0".into()),
+        })
+    }
+
+    /// Waits for the next entries. Redis errors are retried after reconnecting, so this only
+    /// returns once there is something to handle.
+    pub async fn next(&mut self) -> Vec<Delivery> {
+        loop {
+            match self.read().await {
+                Ok(batch) if batch.is_empty() => continue,
+                Ok(batch) => return batch,
+                Err(e) => {
+                    warn!("Reading stream `{}` failed: {:?}", self.stream, e);
+                    sleep(RECONNECT_DELAY).await;
+                    self.reconnect().await;
+                }
+            }
+        }
+    }
+
+    async fn read(&mut self) -> RedisResult<Vec<Delivery>> {
+        let options = StreamReadOptions::default()
+            .group(&self.group, &self.consumer)
+            .count(READ_COUNT);
+        let start = read_start(&self.pending_cursor).to_string();
+        let options = match self.pending_cursor {
+            Some(_) => options,
+            None => options.block(BLOCK_MS),
+        };
+
+        // A blocking read that times out replies with nil
+        let reply: Option<StreamReadReply> = self
+            .conn
+            .xread_options(&[self.stream], &[start], &options)
+            .await?;
+        let entries: Vec<_> = reply
+            .into_iter()
+            .flat_map(|reply| reply.keys)
+            .flat_map(|key| key.ids)
+            .collect();
+
+        if self.pending_cursor.is_some() {
+            if entries.is_empty() {
+                debug!("No more pending entries on `{}`", self.stream);
+            } else {
+                debug!(
+                    "Replaying {} pending entries from `{}`",
+                    entries.len(),
+                    self.stream
+                );
+            }
+            advance_cursor(
+                &mut self.pending_cursor,
+                entries.last().map(|entry| entry.id.as_str()),
+            );
+        }
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| Delivery {
+                payload: entry.get(PAYLOAD_FIELD),
+                entry_id: entry.id,
+            })
+            .collect())
+    }
+
+    async fn reconnect(&mut self) {
+        let mut conn = match self.client.get_multiplexed_async_connection().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Reconnecting to Redis failed: {:?}", e);
+                return;
+            }
+        };
+        // The stream may have been recreated while the connection was down
+        if let Err(e) = create_group(&mut conn, self.stream, &self.group).await {
+            warn!("Recreating group {} failed: {:?}", self.group, e);
+            return;
+        }
+
+        self.conn = conn;
+        // Anything delivered before the failure but never acknowledged is read again
+        self.pending_cursor = Some("0".into());
+        info!("Reconnected to stream `{}`", self.stream);
+    }
+}
+
+/// Id to read from: just after the last pending entry replayed, or `>` for new entries once the
+/// replay is done.
+fn read_start(pending_cursor: &Option<String>) -> &str {
+    pending_cursor.as_deref().unwrap_or(">")
+}
+
+/// Moves the replay past the last entry of a read of pending entries; a read that returned none
+/// ends the replay.
+fn advance_cursor(pending_cursor: &mut Option<String>, last: Option<&str>) {
+    *pending_cursor = last.map(str::to_string);
+}
+
+/// Creates `group` on `stream` starting at new entries, creating the stream if needed.
+async fn create_group(
+    conn: &mut MultiplexedConnection,
+    stream: &str,
+    group: &str,
+) -> RedisResult<()> {
+    match conn
+        .xgroup_create_mkstream::<_, _, _, ()>(stream, group, "$")
+        .await
+    {
+        Ok(()) => Ok(()),
+        Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Adds results to the result streams and acknowledges the requests they answer.
+#[derive(Clone)]
+pub struct Responder {
+    conn: MultiplexedConnection,
+    group: String,
+}
+
+impl Responder {
+    pub fn new(conn: MultiplexedConnection, group: &str) -> Self {
+        Self {
+            conn,
+            group: group.to_string(),
+        }
+    }
+
+    /// Adds `response` to its result stream, then acknowledges `entry_id`. A request whose result
+    /// could not be added stays pending, so it is replayed after a restart.
+    pub async fn reply(
+        &mut self,
+        request_stream: &str,
+        entry_id: &str,
+        response: &ControlResponse,
+    ) -> bool {
+        let added = self
+            .conn
+            .xadd_maxlen::<_, _, _, _, Option<String>>(
+                response.stream(),
+                StreamMaxlen::Approx(RESULT_STREAM_MAXLEN),
+                "*",
+                &[(PAYLOAD_FIELD, response.to_json())],
+            )
+            .await;
+        if let Err(e) = added {
+            error!("Failed to add result to {}: {:?}", response.stream(), e);
+            return false;
+        }
+
+        self.ack(request_stream, entry_id).await;
+        true
+    }
+
+    /// Acknowledges `entry_id` without replying, for entries this node has nothing to say about.
+    pub async fn ack(&mut self, request_stream: &str, entry_id: &str) {
+        if let Err(e) = self
+            .conn
+            .xack::<_, _, _, ()>(request_stream, &self.group, &[entry_id])
+            .await
+        {
+            warn!(
+                "Failed to acknowledge {} on {}: {:?}",
+                entry_id, request_stream, e
+            );
+        }
+    }
+}
+
+/// ✅ Handles keygen and refresh requests from `dkg-start`, one session task per request.
+pub async fn run_dkg_loop(
+    mut requests: StreamConsumer,
+    mut responder: Responder,
+    handlers: Handlers,
+) {
+    loop {
+        for delivery in requests.next().await {
+            let entry_id = delivery.entry_id;
+            let payload = delivery.payload.unwrap_or_default();
+            debug!("[DKG] Stream entry {}: {}", entry_id, payload);
+
+            let mut session_responder = responder.clone();
+            let session_entry = entry_id.clone();
+            let on_done = move |response: ControlResponse| async move {
+                session_responder
+                    .reply(DKG_START_STREAM, &session_entry, &response)
+                    .await;
+            };
+
+            let submitted = match control::parse_request(&payload) {
+                Incoming::Request(ControlRequest::StartDkg(request)) => {
+                    handlers.submit_dkg(request, Some(&entry_id), on_done).await
+                }
+                Incoming::Request(ControlRequest::Refresh(request)) => {
+                    handlers
+                        .submit_refresh(request, Some(&entry_id), on_done)
+                        .await
+                }
+                Incoming::Request(_) | Incoming::Unsupported(_) => {
+                    debug!("[DKG] Ignored unrelated message");
+                    responder.ack(DKG_START_STREAM, &entry_id).await;
+                    continue;
+                }
+                Incoming::Malformed { id, error } => {
+                    warn!("[DKG] Malformed request: {}", error);
+                    let reply = ErrorResult::new(
+                        id,
+                        handlers.node_id(),
+                        ErrorCode::MalformedRequest,
+                        error,
+                    );
+                    let response = ControlResponse::DkgError(reply);
+                    responder
+                        .reply(DKG_START_STREAM, &entry_id, &response)
+                        .await;
+                    continue;
+                }
+            };
+            settle(&mut responder, DKG_START_STREAM, &entry_id, submitted).await;
+        }
+    }
+}
+
+/// ✅ Handles signing requests from `sign-start`, one session task per request.
+pub async fn run_sign_loop(
+    mut requests: StreamConsumer,
+    mut responder: Responder,
+    handlers: Handlers,
+) {
+    loop {
+        for delivery in requests.next().await {
+            let entry_id = delivery.entry_id;
+            let payload = delivery.payload.unwrap_or_default();
+            debug!("[SIGN] Stream entry {}: {}", entry_id, payload);
+
+            let request = match control::parse_request(&payload) {
+                Incoming::Request(request) if request.is_sign() => request,
+                Incoming::Request(_) | Incoming::Unsupported(_) => {
+                    debug!("[SIGN] Ignored unrelated message");
+                    responder.ack(SIGN_START_STREAM, &entry_id).await;
+                    continue;
+                }
+                Incoming::Malformed { id, error } => {
+                    warn!("[SIGN] Malformed request: {}", error);
+                    let reply = ErrorResult::new(
+                        id,
+                        handlers.node_id(),
+                        ErrorCode::MalformedRequest,
+                        error,
+                    );
+                    let response = ControlResponse::SignError(reply);
+                    responder
+                        .reply(SIGN_START_STREAM, &entry_id, &response)
+                        .await;
+                    continue;
+                }
+            };
+
+            let mut session_responder = responder.clone();
+            let session_entry = entry_id.clone();
+            let submitted = handlers
+                .submit_sign(request, Some(&entry_id), move |response| async move {
+                    session_responder
+                        .reply(SIGN_START_STREAM, &session_entry, &response)
+                        .await;
+                })
+                .await;
+            settle(&mut responder, SIGN_START_STREAM, &entry_id, submitted).await;
+        }
+    }
+}
+
+/// What to do with a request entry once it has been submitted.
+#[derive(Debug)]
+enum Settlement {
+    /// Keep it pending; a session acknowledges it once its result is added
+    Pending,
+    Ack,
+    Reply(ControlResponse),
+}
+
+/// Entries of a session that started or is still running stay pending until its result is
+/// added, so a failed reply leaves them to be replayed. A running request's session answers
+/// every entry it was submitted in, so none of them is acknowledged here.
+fn settlement(submitted: Submission) -> Settlement {
+    match submitted {
+        Submission::Started | Submission::InProgress => Settlement::Pending,
+        Submission::NotSigner(_) => Settlement::Ack,
+        Submission::Answered(response) => Settlement::Reply(response),
+    }
+}
+
+/// Answers or acknowledges a request entry that did not start a session.
+async fn settle(
+    responder: &mut Responder,
+    request_stream: &str,
+    entry_id: &str,
+    submitted: Submission,
+) {
+    match settlement(submitted) {
+        Settlement::Pending => {
+            debug!("Entry {} on {} stays pending", entry_id, request_stream)
+        }
+        Settlement::Ack => responder.ack(request_stream, entry_id).await,
+        Settlement::Reply(response) => {
+            responder.reply(request_stream, entry_id, &response).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dkg_tcp::control::{DkgStartRequest, RequestId};
+    use dkg_tcp::scheduler::SessionScheduler;
+
+    #[test]
+    fn pending_entries_are_replayed_before_new_ones() {
+        let mut cursor = Some("0".to_string());
+        assert_eq!(read_start(&cursor), "0");
+
+        advance_cursor(&mut cursor, Some("1700000000000-3"));
+        assert_eq!(read_start(&cursor), "1700000000000-3");
+
+        // An empty read of pending entries ends the replay
+        advance_cursor(&mut cursor, None);
+        assert_eq!(read_start(&cursor), ">");
+    }
+
+    #[tokio::test]
+    async fn every_entry_of_a_running_request_gets_its_result() {
+        // Party 1 never shows up, so the keygen ends once the link times out
+        let node = crate::testing::cluster(2, 2).await.remove(0);
+        let scheduler = SessionScheduler::new(1, 1).unwrap();
+        let handlers = Handlers::new(node, scheduler, "default".to_string());
+
+        let (replies, mut replied) = tokio::sync::mpsc::unbounded_channel();
+        let submit = |entry_id: &'static str| {
+            let request = DkgStartRequest {
+                id: RequestId::Number(1),
+                session: None,
+                rotate: false,
+            };
+            let replies = replies.clone();
+            let handlers = handlers.clone();
+            async move {
+                handlers
+                    .submit_dkg(request, Some(entry_id), move |response| async move {
+                        replies.send((entry_id, response)).unwrap();
+                    })
+                    .await
+            }
+        };
+
+        assert!(matches!(submit("1-0").await, Submission::Started));
+        assert!(matches!(submit("2-0").await, Submission::InProgress));
+        // A replay of an entry already submitted is answered only once
+        assert!(matches!(submit("1-0").await, Submission::InProgress));
+        drop(replies);
+
+        let mut answered = Vec::new();
+        while let Some((entry_id, response)) = replied.recv().await {
+            assert!(matches!(response, ControlResponse::DkgError(_)));
+            answered.push(entry_id);
+        }
+        answered.sort();
+        assert_eq!(answered, ["1-0", "2-0"]);
+    }
+
+    #[test]
+    fn requests_that_do_not_run_are_settled_right_away() {
+        assert!(matches!(
+            settlement(Submission::NotSigner(vec![0, 1])),
+            Settlement::Ack
+        ));
+
+        let answer = ControlResponse::SignError(ErrorResult::new(
+            Some(RequestId::Number(7)),
+            2,
+            ErrorCode::Busy,
+            "session queue is full",
+        ));
+        assert!(matches!(
+            settlement(Submission::Answered(answer)),
+            Settlement::Reply(ControlResponse::SignError(_))
+        ));
+    }
+}
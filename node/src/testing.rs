@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use dkg_tcp::keygen::KeygenConfig;
+use dkg_tcp::store;
+use dkg_tcp::transport::peers::PeerTable;
+use dkg_tcp::transport::session::SessionListener;
+
+use crate::locks::SessionLocks;
+use crate::node::SessionContext;
+
+/// Session contexts of an in-process `threshold`-of-`n` deployment on memory stores, one per
+/// party, listening on free local ports.
+pub async fn cluster(n: u16, threshold: u16) -> Vec<SessionContext> {
+    let ports: Vec<u16> = (0..n)
+        .map(|_| {
+            let socket = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            socket.local_addr().unwrap().port()
+        })
+        .collect();
+    let spec: Vec<String> = ports
+        .iter()
+        .enumerate()
+        .map(|(party, port)| format!("{}=127.0.0.1:{}", party, port))
+        .collect();
+    let peers = Arc::new(PeerTable::parse(&spec.join(",")).unwrap());
+
+    let mut nodes = Vec::new();
+    for (party, port) in ports.into_iter().enumerate() {
+        nodes.push(SessionContext {
+            store: store::open_store("memory", None).await.unwrap(),
+            listener: Arc::new(
+                SessionListener::bind(("127.0.0.1", port), None)
+                    .await
+                    .unwrap(),
+            ),
+            node_tls: None,
+            peers: peers.clone(),
+            keygen_config: KeygenConfig::new(n, threshold).unwrap(),
+            id: party as u16,
+            policy: None,
+            sessions: SessionLocks::default(),
+        });
+    }
+    nodes
+}
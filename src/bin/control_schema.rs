@@ -0,0 +1,23 @@
+//! Writes the control-plane JSON Schemas consumed by the gateway.
+//!
+//! Run with `cargo run --bin control_schema [out_dir]`; defaults to `schema/`.
+
+use anyhow::Result;
+use dkg_tcp::control;
+use std::path::PathBuf;
+
+fn main() -> Result<()> {
+    let out_dir = PathBuf::from(std::env::args().nth(1).unwrap_or_else(|| "schema".into()));
+    std::fs::create_dir_all(&out_dir)?;
+
+    for (name, schema) in [
+        ("control-request.schema.json", control::request_schema()),
+        ("control-response.schema.json", control::response_schema()),
+    ] {
+        let path = out_dir.join(name);
+        std::fs::write(&path, serde_json::to_string_pretty(&schema)? + "\n")?;
+        println!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
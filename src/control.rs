@@ -0,0 +1,674 @@
+use schemars::{JsonSchema, Schema, schema_for};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::decode::MessageSummary;
+use crate::policy::PolicyViolation;
+use crate::token::TokenProgram;
+
+/// Redis stream the gateway adds keygen and refresh requests to.
+pub const DKG_START_STREAM: &str = "dkg-start";
+/// Redis stream the gateway adds signing requests to.
+pub const SIGN_START_STREAM: &str = "sign-start";
+/// Redis stream nodes add keygen and refresh results and errors to.
+pub const DKG_RESULT_STREAM: &str = "dkg-result";
+/// Redis stream nodes add signing results and errors to.
+pub const SIGN_RESULT_STREAM: &str = "sign-result";
+
+/// Gateway-assigned request id, echoed back on every reply.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum RequestId {
+    Number(u64),
+    Text(String),
+}
+
+impl RequestId {
+    /// JSON form of the id, which keeps `1` and `"1"` apart.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("request ids always serialize")
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestId::Number(n) => write!(f, "{}", n),
+            RequestId::Text(s) => f.write_str(s),
+        }
+    }
+}
+
+/// Request added by the gateway to `dkg-start` or `sign-start`, tagged by `action`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(tag = "action")]
+pub enum ControlRequest {
+    #[serde(rename = "startdkg")]
+    StartDkg(DkgStartRequest),
+    #[serde(rename = "sign")]
+    Sign(SignRequest),
+    #[serde(rename = "sign-batch")]
+    SignBatch(SignBatchRequest),
+    #[serde(rename = "sign-token-transfer")]
+    SignTokenTransfer(TokenTransferRequest),
+    #[serde(rename = "sign-create-token-account")]
+    SignCreateTokenAccount(CreateTokenAccountRequest),
+    #[serde(rename = "presign")]
+    Presign(PresignRequest),
+    #[serde(rename = "refresh")]
+    Refresh(RefreshRequest),
+}
+
+/// Every `action` a node serves.
+const ACTIONS: &[&str] = &[
+    "startdkg",
+    "sign",
+    "sign-batch",
+    "sign-token-transfer",
+    "sign-create-token-account",
+    "presign",
+    "refresh",
+];
+
+impl ControlRequest {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("control requests always serialize")
+    }
+
+    pub fn id(&self) -> &RequestId {
+        match self {
+            ControlRequest::StartDkg(r) => &r.id,
+            ControlRequest::Sign(r) => &r.id,
+            ControlRequest::SignBatch(r) => &r.id,
+            ControlRequest::SignTokenTransfer(r) => &r.id,
+            ControlRequest::SignCreateTokenAccount(r) => &r.id,
+            ControlRequest::Presign(r) => &r.id,
+            ControlRequest::Refresh(r) => &r.id,
+        }
+    }
+
+    /// Key session the request names, if any.
+    pub fn session(&self) -> Option<&str> {
+        match self {
+            ControlRequest::StartDkg(r) => r.session.as_deref(),
+            ControlRequest::Sign(r) => r.session.as_deref(),
+            ControlRequest::SignBatch(r) => r.session.as_deref(),
+            ControlRequest::SignTokenTransfer(r) => r.session.as_deref(),
+            ControlRequest::SignCreateTokenAccount(r) => r.session.as_deref(),
+            ControlRequest::Presign(r) => r.session.as_deref(),
+            ControlRequest::Refresh(r) => r.session.as_deref(),
+        }
+    }
+
+    /// Requested co-signers of a signing request; `None` for keygen, refresh or the default set.
+    pub fn signers(&self) -> Option<&[u16]> {
+        match self {
+            ControlRequest::StartDkg(_) | ControlRequest::Refresh(_) => None,
+            ControlRequest::Sign(r) => r.signers.as_deref(),
+            ControlRequest::SignBatch(r) => r.signers.as_deref(),
+            ControlRequest::SignTokenTransfer(r) => r.signers.as_deref(),
+            ControlRequest::SignCreateTokenAccount(r) => r.signers.as_deref(),
+            ControlRequest::Presign(r) => r.signers.as_deref(),
+        }
+    }
+
+    /// Whether the request is served on `sign-start`; the others are served on `dkg-start`.
+    pub fn is_sign(&self) -> bool {
+        !matches!(
+            self,
+            ControlRequest::StartDkg(_) | ControlRequest::Refresh(_)
+        )
+    }
+
+    /// What the bytes a signing request signs are; messages built from a request's fields
+    /// are Solana messages.
+    pub fn format(&self) -> Option<MessageFormat> {
+        match self {
+            ControlRequest::StartDkg(_)
+            | ControlRequest::Presign(_)
+            | ControlRequest::Refresh(_) => None,
+            ControlRequest::Sign(r) => Some(r.format),
+            ControlRequest::SignBatch(r) => Some(r.format),
+            ControlRequest::SignTokenTransfer(_) | ControlRequest::SignCreateTokenAccount(_) => {
+                Some(MessageFormat::Solana)
+            }
+        }
+    }
+}
+
+/// Starts a keygen session.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct DkgStartRequest {
+    pub id: RequestId,
+    /// Key session to generate; nodes fall back to `DEFAULT_SESSION_ID` when omitted
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Replace the key of a session that already has one; without it such a request fails
+    /// with `key_exists`
+    #[serde(default)]
+    pub rotate: bool,
+}
+
+/// Refreshes every party's share of an existing session's key, keeping its public key. Like
+/// keygen, it runs on `dkg-start` and needs all `n` parties.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct RefreshRequest {
+    pub id: RequestId,
+    #[serde(default)]
+    pub session: Option<String>,
+}
+
+/// Signs a message with the key of an existing session.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct SignRequest {
+    pub id: RequestId,
+    /// Key session whose share signs; nodes fall back to `DEFAULT_SESSION_ID` when omitted
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Base64-encoded message bytes
+    pub message: String,
+    /// Party indices that co-sign, exactly `THRESHOLD` of them; defaults to parties `0..THRESHOLD`
+    #[serde(default)]
+    pub signers: Option<Vec<u16>>,
+    /// How `message` is interpreted before signing
+    #[serde(default)]
+    pub format: MessageFormat,
+    /// Sign in a single round with a presignature from an earlier `presign` request for the
+    /// same signers; fails with `presignature_unavailable` when this node has none left
+    #[serde(default)]
+    pub presigned: bool,
+}
+
+/// Most messages a [`SignBatchRequest`] may carry.
+pub const MAX_BATCH_MESSAGES: usize = 1000;
+
+/// Signs several messages with the key of an existing session, all in one signing session
+/// over one connection per co-signer.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct SignBatchRequest {
+    pub id: RequestId,
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Base64-encoded message bytes, at most `MAX_BATCH_MESSAGES` of them; the signatures come
+    /// back in the same order
+    pub messages: Vec<String>,
+    #[serde(default)]
+    pub signers: Option<Vec<u16>>,
+    /// How every message is interpreted before signing
+    #[serde(default)]
+    pub format: MessageFormat,
+}
+
+/// Most presignatures one [`PresignRequest`] may generate.
+pub const MAX_PRESIGNATURES: usize = 1000;
+
+/// Generates presignatures for a session's key and signer set, so later sign requests with
+/// `presigned` set need only one round. No message is signed.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct PresignRequest {
+    pub id: RequestId,
+    #[serde(default)]
+    pub session: Option<String>,
+    /// Number of presignatures to generate, at most `MAX_PRESIGNATURES`
+    pub count: usize,
+    /// Signer set the presignatures are for; sign requests must name the same set to use them
+    #[serde(default)]
+    pub signers: Option<Vec<u16>>,
+}
+
+/// What the bytes of a [`SignRequest`] or [`SignBatchRequest`] are.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum MessageFormat {
+    /// Arbitrary bytes, signed as given
+    #[default]
+    Raw,
+    /// A serialized legacy or v0 Solana transaction message, which must be well formed and
+    /// list the session key among its signers
+    Solana,
+    /// A UTF-8 payload, signed wrapped in the Solana off-chain message format
+    Offchain,
+    /// The text of a Sign-In With Solana message for the session key, signed as given
+    SignIn,
+}
+
+impl MessageFormat {
+    /// Whether the signed bytes can never be a transaction message.
+    pub fn is_offchain(self) -> bool {
+        matches!(self, MessageFormat::Offchain | MessageFormat::SignIn)
+    }
+}
+
+/// Signs an SPL Token or Token-2022 `TransferChecked` out of the session key's associated
+/// token account. The session key is the fee payer and transfer authority.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct TokenTransferRequest {
+    pub id: RequestId,
+    #[serde(default)]
+    pub session: Option<String>,
+    #[serde(default)]
+    pub signers: Option<Vec<u16>>,
+    /// Base58 mint address
+    pub mint: String,
+    /// Base58 wallet address; its associated token account is credited
+    pub recipient: String,
+    /// Amount in the mint's base units
+    pub amount: u64,
+    /// The mint's decimals, checked on chain
+    pub decimals: u8,
+    /// Token program that owns the mint
+    #[serde(default)]
+    pub program: TokenProgram,
+    /// Create the recipient's associated token account first if it is missing
+    #[serde(default = "default_true")]
+    pub create_recipient_account: bool,
+    /// Memo attached to the transfer, e.g. for a policy that requires one
+    #[serde(default)]
+    pub memo: Option<String>,
+    /// Base58 blockhash the transaction is valid under; every signer builds the same message
+    pub recent_blockhash: String,
+}
+
+/// Signs the creation of a wallet's associated token account, paid for by the session key.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct CreateTokenAccountRequest {
+    pub id: RequestId,
+    #[serde(default)]
+    pub session: Option<String>,
+    #[serde(default)]
+    pub signers: Option<Vec<u16>>,
+    /// Base58 wallet address that will own the account
+    pub wallet: String,
+    /// Base58 mint address
+    pub mint: String,
+    #[serde(default)]
+    pub program: TokenProgram,
+    /// Base58 blockhash the transaction is valid under
+    pub recent_blockhash: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Reply added by a node to `dkg-result` or `sign-result`, tagged by `result_type`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(tag = "result_type")]
+pub enum ControlResponse {
+    #[serde(rename = "dkg-result")]
+    Dkg(DkgResult),
+    #[serde(rename = "dkg-error")]
+    DkgError(ErrorResult),
+    #[serde(rename = "sign-result")]
+    Sign(SignResult),
+    #[serde(rename = "sign-batch-result")]
+    SignBatch(SignBatchResult),
+    #[serde(rename = "presign-result")]
+    Presign(PresignResult),
+    #[serde(rename = "refresh-result")]
+    Refresh(RefreshResult),
+    #[serde(rename = "refresh-error")]
+    RefreshError(ErrorResult),
+    #[serde(rename = "sign-error")]
+    SignError(ErrorResult),
+}
+
+impl ControlResponse {
+    /// Stream the response is added to.
+    pub fn stream(&self) -> &'static str {
+        match self {
+            ControlResponse::Dkg(_)
+            | ControlResponse::Refresh(_)
+            | ControlResponse::DkgError(_)
+            | ControlResponse::RefreshError(_) => DKG_RESULT_STREAM,
+            ControlResponse::Sign(_)
+            | ControlResponse::SignBatch(_)
+            | ControlResponse::Presign(_)
+            | ControlResponse::SignError(_) => SIGN_RESULT_STREAM,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("control responses always serialize")
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Error code of a failed request, `None` on success.
+    pub fn error_code(&self) -> Option<ErrorCode> {
+        match self {
+            ControlResponse::Dkg(_)
+            | ControlResponse::Sign(_)
+            | ControlResponse::SignBatch(_)
+            | ControlResponse::Presign(_)
+            | ControlResponse::Refresh(_) => None,
+            ControlResponse::DkgError(e)
+            | ControlResponse::RefreshError(e)
+            | ControlResponse::SignError(e) => Some(e.code),
+        }
+    }
+}
+
+/// Shared public key produced by a keygen session.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct DkgResult {
+    pub id: RequestId,
+    /// Base58-encoded compressed Ed25519 public key
+    pub data: String,
+    pub server_id: u64,
+}
+
+/// Signature produced by a signing session.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct SignResult {
+    pub id: RequestId,
+    /// Base58-encoded 64-byte Solana signature
+    pub data: String,
+    pub server_id: u64,
+    /// What was signed, when the bytes are a Solana message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Box<MessageSummary>>,
+}
+
+/// Signatures produced by a batch signing session.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct SignBatchResult {
+    pub id: RequestId,
+    /// Base58-encoded 64-byte Solana signatures, in the order of the request's messages
+    pub data: Vec<String>,
+    pub server_id: u64,
+    /// What each message is, in the same order and `null` for bytes that are not a Solana
+    /// message; empty when none is
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub summaries: Vec<Option<MessageSummary>>,
+}
+
+/// Presignatures generated by a presign session.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct PresignResult {
+    pub id: RequestId,
+    /// Presignatures this request added
+    pub count: usize,
+    /// Presignatures this node now holds for the session and signer set
+    pub available: usize,
+    pub server_id: u64,
+}
+
+/// A session's key after its shares were refreshed.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct RefreshResult {
+    pub id: RequestId,
+    /// Base58-encoded public key, the same as before the refresh
+    pub data: String,
+    /// Version of this node's share: `0` from keygen, one more after every refresh
+    pub version: u64,
+    pub server_id: u64,
+}
+
+/// A request that could not be completed.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct ErrorResult {
+    /// Id of the failed request; absent when the request was too malformed to read one
+    pub id: Option<RequestId>,
+    pub code: ErrorCode,
+    /// Human-readable detail, not meant to be matched on
+    pub error: String,
+    pub server_id: u64,
+    /// The rule a signer's policy refused the message under, with code `policy_violation`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub violation: Option<PolicyViolation>,
+    /// The refused message, with `violation`, when the bytes are a Solana message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Box<MessageSummary>>,
+    /// Position in `messages` of the message a batch request failed on, when one did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+}
+
+impl ErrorResult {
+    pub fn new(
+        id: Option<RequestId>,
+        server_id: u64,
+        code: ErrorCode,
+        error: impl Into<String>,
+    ) -> Self {
+        Self {
+            id,
+            code,
+            error: error.into(),
+            server_id,
+            violation: None,
+            summary: None,
+            index: None,
+        }
+    }
+
+    /// Error for a message this node's policy refused to sign.
+    pub fn policy_violation(
+        id: RequestId,
+        server_id: u64,
+        violation: PolicyViolation,
+        summary: Option<Box<MessageSummary>>,
+    ) -> Self {
+        Self {
+            violation: Some(violation.clone()),
+            summary,
+            ..Self::new(
+                Some(id),
+                server_id,
+                ErrorCode::PolicyViolation,
+                violation.to_string(),
+            )
+        }
+    }
+}
+
+/// Machine-readable reason carried by an [`ErrorResult`].
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Payload is not valid JSON or does not match the request schema
+    MalformedRequest,
+    /// `message` is not valid base64, not a Solana message the session key signs when
+    /// `format` is `solana`, or the fields to build it from are invalid; for a batch, `index`
+    /// names the message
+    InvalidMessage,
+    /// This node holds no share for the requested session
+    ShareNotFound,
+    /// `signers` does not match the key's threshold or parties
+    InvalidSigners,
+    /// The key share store failed
+    StoreError,
+    /// A session with the same id is already running
+    SessionInProgress,
+    /// The node is at its concurrent session limit and its queue is full
+    Busy,
+    /// A peer did not connect in time
+    PeerUnavailable,
+    /// The MPC protocol failed
+    ProtocolFailed,
+    /// The MPC protocol did not finish in time
+    Timeout,
+    /// The session already has a key and the keygen request did not set `rotate`
+    KeyExists,
+    /// The request id was already used for a different request
+    RequestIdReused,
+    /// The aggregated signature does not verify against the session's public key
+    InvalidSignature,
+    /// This node's signing policy refused the message; `violation` says which rule
+    PolicyViolation,
+    /// A `presigned` sign request found no presignature left for its signer set; send a
+    /// `presign` request and retry
+    PresignatureUnavailable,
+    /// Signers hold shares of different versions of the session key, e.g. because a refresh
+    /// did not commit on every node; resend the `refresh`, which brings them back in step
+    ShareVersionMismatch,
+}
+
+impl ErrorCode {
+    /// Whether the same request may succeed when retried, so its failure is not final.
+    pub fn is_transient(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::StoreError
+                | ErrorCode::SessionInProgress
+                | ErrorCode::Busy
+                | ErrorCode::PeerUnavailable
+                | ErrorCode::ProtocolFailed
+                | ErrorCode::Timeout
+                | ErrorCode::PresignatureUnavailable
+                | ErrorCode::ShareVersionMismatch
+        )
+    }
+}
+
+/// Outcome of reading a payload from a request stream.
+#[derive(Debug)]
+pub enum Incoming {
+    Request(ControlRequest),
+    /// Well-formed, but for an action this node does not serve on the stream
+    Unsupported(String),
+    /// Unreadable payload; the id is kept when one could be recovered
+    Malformed {
+        id: Option<RequestId>,
+        error: String,
+    },
+}
+
+/// Parses a control-plane payload without ever dropping it silently.
+pub fn parse_request(payload: &str) -> Incoming {
+    let value: serde_json::Value = match serde_json::from_str(payload) {
+        Ok(v) => v,
+        Err(e) => {
+            return Incoming::Malformed {
+                id: None,
+                error: format!("invalid JSON: {}", e),
+            };
+        }
+    };
+
+    let id = serde_json::from_value::<RequestId>(value["id"].clone()).ok();
+    let action = value["action"].as_str().map(str::to_string);
+
+    match serde_json::from_value::<ControlRequest>(value) {
+        Ok(request) => Incoming::Request(request),
+        Err(_) if action.as_deref().is_some_and(|a| !ACTIONS.contains(&a)) => {
+            Incoming::Unsupported(action.unwrap_or_default())
+        }
+        Err(e) => Incoming::Malformed {
+            id,
+            error: e.to_string(),
+        },
+    }
+}
+
+/// JSON Schema for everything the gateway publishes to the nodes.
+pub fn request_schema() -> Schema {
+    schema_for!(ControlRequest)
+}
+
+/// JSON Schema for everything the nodes publish back to the gateway.
+pub fn response_schema() -> Schema {
+    schema_for!(ControlResponse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_parse_by_action() {
+        let Incoming::Request(ControlRequest::StartDkg(request)) =
+            parse_request(r#"{"id": 1, "action": "startdkg", "session": "session-001"}"#)
+        else {
+            panic!("expected a keygen request");
+        };
+        assert_eq!(request.id, RequestId::Number(1));
+        assert_eq!(request.session.as_deref(), Some("session-001"));
+        assert!(!request.rotate);
+
+        let Incoming::Request(request) = parse_request(
+            r#"{"id": "a1", "action": "sign", "message": "aGVsbG8=", "signers": [0, 2], "format": "sign-in"}"#,
+        ) else {
+            panic!("expected a sign request");
+        };
+        assert!(request.is_sign());
+        assert_eq!(request.id(), &RequestId::Text("a1".into()));
+        assert_eq!(request.session(), None);
+        assert_eq!(request.signers(), Some(&[0, 2][..]));
+        assert_eq!(request.format(), Some(MessageFormat::SignIn));
+    }
+
+    #[test]
+    fn keygen_and_refresh_are_not_signing_requests() {
+        for payload in [
+            r#"{"id": 1, "action": "startdkg"}"#,
+            r#"{"id": 1, "action": "refresh", "session": "session-001"}"#,
+        ] {
+            let Incoming::Request(request) = parse_request(payload) else {
+                panic!("expected a request: {}", payload);
+            };
+            assert!(!request.is_sign());
+            assert_eq!(request.format(), None);
+        }
+    }
+
+    #[test]
+    fn numeric_and_text_ids_stay_apart() {
+        let Incoming::Request(number) = parse_request(r#"{"id": 1, "action": "startdkg"}"#) else {
+            panic!("expected a request");
+        };
+        let Incoming::Request(text) = parse_request(r#"{"id": "1", "action": "startdkg"}"#) else {
+            panic!("expected a request");
+        };
+        assert_ne!(number.id(), text.id());
+        assert_eq!(number.id().to_json(), "1");
+        assert_eq!(text.id().to_json(), r#""1""#);
+    }
+
+    #[test]
+    fn unknown_actions_are_unsupported_rather_than_malformed() {
+        assert!(matches!(
+            parse_request(r#"{"id": 1, "action": "reshare"}"#),
+            Incoming::Unsupported(action) if action == "reshare"
+        ));
+    }
+
+    #[test]
+    fn malformed_requests_keep_their_id_when_it_can_be_read() {
+        let Incoming::Malformed { id, .. } = parse_request(r#"{"id": 4, "action": "sign"}"#) else {
+            panic!("a sign request without a message is malformed");
+        };
+        assert_eq!(id, Some(RequestId::Number(4)));
+
+        assert!(matches!(
+            parse_request(r#"{"action": "presign", "count": 1}"#),
+            Incoming::Malformed { id: None, .. }
+        ));
+        assert!(matches!(
+            parse_request("not json"),
+            Incoming::Malformed { id: None, .. }
+        ));
+    }
+
+    #[test]
+    fn responses_go_to_the_stream_of_their_request() {
+        let error = |code| ErrorResult::new(Some(RequestId::Number(1)), 0, code, "failed");
+        assert_eq!(
+            ControlResponse::DkgError(error(ErrorCode::KeyExists)).stream(),
+            DKG_RESULT_STREAM
+        );
+        assert_eq!(
+            ControlResponse::RefreshError(error(ErrorCode::Timeout)).stream(),
+            DKG_RESULT_STREAM
+        );
+        assert_eq!(
+            ControlResponse::SignError(error(ErrorCode::Timeout)).stream(),
+            SIGN_RESULT_STREAM
+        );
+        assert!(ErrorCode::Timeout.is_transient());
+        assert!(!ErrorCode::KeyExists.is_transient());
+    }
+}
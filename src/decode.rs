@@ -0,0 +1,721 @@
+use anyhow::{Result, anyhow};
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_message::VersionedMessage;
+use solana_pubkey::{Pubkey, pubkey};
+use solana_system_interface::instruction::SystemInstruction;
+use spl_token_interface::instruction::TokenInstruction;
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::sign::{self, MEMO_PROGRAM_ID, MEMO_V1_PROGRAM_ID};
+use crate::token::TOKEN_2022_PROGRAM_ID;
+
+const SYSTEM_PROGRAM_ID: Pubkey = pubkey!("11111111111111111111111111111111");
+const COMPUTE_BUDGET_PROGRAM_ID: Pubkey = pubkey!("ComputeBudget111111111111111111111111111111");
+const ADDRESS_LOOKUP_TABLE_PROGRAM_ID: Pubkey =
+    pubkey!("AddressLookupTab1e1111111111111111111111111");
+
+/// An account a message refers to: one of its static keys, or an entry of an address lookup
+/// table, which is only known once the table is read. Written as the base58 address, or as
+/// `<table>#<index>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Account {
+    Key(Pubkey),
+    Lookup { table: Pubkey, index: u8 },
+}
+
+impl Account {
+    /// The account's address, if the message itself holds it.
+    pub fn address(&self) -> Option<&Pubkey> {
+        match self {
+            Account::Key(key) => Some(key),
+            Account::Lookup { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Account::Key(key) => write!(f, "{}", key),
+            Account::Lookup { table, index } => write!(f, "{}#{}", table, index),
+        }
+    }
+}
+
+impl FromStr for Account {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |key: &str| {
+            Pubkey::from_str(key).map_err(|e| anyhow!("`{}` is not a base58 address: {}", key, e))
+        };
+        match s.split_once('#') {
+            Some((table, index)) => Ok(Account::Lookup {
+                table: parse(table)?,
+                index: index.parse()?,
+            }),
+            None => Ok(Account::Key(parse(s)?)),
+        }
+    }
+}
+
+impl Serialize for Account {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Account {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl JsonSchema for Account {
+    fn schema_name() -> Cow<'static, str> {
+        "Account".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "description": "Base58 address, or `<table>#<index>` for an account loaded from an address lookup table"
+        })
+    }
+}
+
+/// Decoded contents of a Solana message, as logged before signing and returned with the
+/// signature.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct MessageSummary {
+    /// `legacy` or `v0`
+    pub version: String,
+    pub fee_payer: Account,
+    /// Every account whose signature the message requires, the fee payer first
+    pub signers: Vec<Account>,
+    pub recent_blockhash: String,
+    /// Address lookup tables a v0 message loads accounts from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lookup_tables: Vec<Account>,
+    pub instructions: Vec<InstructionSummary>,
+}
+
+/// One instruction of a [`MessageSummary`].
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct InstructionSummary {
+    pub program_id: Account,
+    /// Name of a well-known program, or `Unknown`
+    pub program: String,
+    pub accounts: Vec<Account>,
+    /// The instruction's arguments, for System, SPL Token, Token-2022, Associated Token
+    /// Account and Memo instructions this decoder knows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoded: Option<DecodedInstruction>,
+}
+
+/// An instruction decoded from its program's layout.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DecodedInstruction {
+    /// System Program transfer
+    Transfer {
+        from: Account,
+        to: Account,
+        lamports: u64,
+    },
+    /// System Program transfer out of an address derived from `base`, which signs for it
+    TransferWithSeed {
+        from: Account,
+        base: Account,
+        to: Account,
+        lamports: u64,
+    },
+    /// System Program account creation, funded by `from`; `CreateAccountWithSeed` decodes to
+    /// this too
+    CreateAccount {
+        from: Account,
+        to: Account,
+        lamports: u64,
+        space: u64,
+        owner: Account,
+    },
+    /// Withdrawal from a durable nonce account, signed by its `authority`
+    WithdrawNonce {
+        nonce: Account,
+        to: Account,
+        authority: Account,
+        lamports: u64,
+    },
+    /// SPL Token or Token-2022 `Transfer`, which does not name the mint
+    TokenTransfer {
+        source: Account,
+        destination: Account,
+        authority: Account,
+        amount: u64,
+    },
+    /// SPL Token or Token-2022 `TransferChecked`
+    TokenTransferChecked {
+        source: Account,
+        mint: Account,
+        destination: Account,
+        authority: Account,
+        amount: u64,
+        decimals: u8,
+    },
+    /// Associated Token Account creation; `idempotent` ones succeed if it already exists
+    CreateTokenAccount {
+        payer: Account,
+        account: Account,
+        wallet: Account,
+        mint: Account,
+        idempotent: bool,
+    },
+    /// Memo text, with invalid UTF-8 replaced
+    Memo { text: String },
+}
+
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodedInstruction::Transfer { from, to, lamports } => {
+                write!(f, "transfer {} lamports from {} to {}", lamports, from, to)
+            }
+            DecodedInstruction::TransferWithSeed {
+                from,
+                base,
+                to,
+                lamports,
+            } => write!(
+                f,
+                "transfer {} lamports from {} (derived from {}) to {}",
+                lamports, from, base, to
+            ),
+            DecodedInstruction::WithdrawNonce {
+                nonce,
+                to,
+                authority,
+                lamports,
+            } => write!(
+                f,
+                "withdraw {} lamports from nonce account {} to {}, authorized by {}",
+                lamports, nonce, to, authority
+            ),
+            DecodedInstruction::CreateAccount {
+                from,
+                to,
+                lamports,
+                space,
+                owner,
+            } => write!(
+                f,
+                "create account {} owned by {} with {} bytes and {} lamports from {}",
+                to, owner, space, lamports, from
+            ),
+            DecodedInstruction::TokenTransfer {
+                source,
+                destination,
+                amount,
+                ..
+            } => write!(
+                f,
+                "transfer {} base units from {} to {}",
+                amount, source, destination
+            ),
+            DecodedInstruction::TokenTransferChecked {
+                source,
+                mint,
+                destination,
+                amount,
+                decimals,
+                ..
+            } => write!(
+                f,
+                "transfer {} base units ({} decimals) of mint {} from {} to {}",
+                amount, decimals, mint, source, destination
+            ),
+            DecodedInstruction::CreateTokenAccount {
+                account,
+                wallet,
+                mint,
+                idempotent,
+                ..
+            } => write!(
+                f,
+                "create token account {} of {} for mint {}{}",
+                account,
+                wallet,
+                mint,
+                if *idempotent { " if missing" } else { "" }
+            ),
+            DecodedInstruction::Memo { text } => write!(f, "memo {:?}", text),
+        }
+    }
+}
+
+impl fmt::Display for InstructionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.decoded {
+            Some(decoded) => write!(f, "{}: {}", self.program, decoded),
+            None => write!(
+                f,
+                "{} ({}) with {} accounts",
+                self.program,
+                self.program_id,
+                self.accounts.len()
+            ),
+        }
+    }
+}
+
+/// One line for logs: the fee payer, then every instruction in order.
+impl fmt::Display for MessageSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} message paid by {} with {} instructions",
+            self.version,
+            self.fee_payer,
+            self.instructions.len()
+        )?;
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            write!(f, "; {}) {}", i + 1, instruction)?;
+        }
+        Ok(())
+    }
+}
+
+/// Summarizes a serialized legacy or v0 message; fails on anything `sign::parse_message`
+/// rejects.
+pub fn summarize_bytes(message: &[u8]) -> Result<MessageSummary> {
+    Ok(summarize(&sign::parse_message(message)?))
+}
+
+/// Summarizes a sanitized message, such as one returned by `sign::parse_message`.
+pub fn summarize(message: &VersionedMessage) -> MessageSummary {
+    let keys = message.static_account_keys();
+    let lookups = message.address_table_lookups().unwrap_or_default();
+
+    // Loaded accounts follow the static keys: every table's writable entries, then every
+    // table's read-only ones
+    let mut accounts: Vec<Account> = keys.iter().copied().map(Account::Key).collect();
+    for lookup in lookups {
+        accounts.extend(
+            lookup
+                .writable_indexes
+                .iter()
+                .map(|&index| Account::Lookup {
+                    table: lookup.account_key,
+                    index,
+                }),
+        );
+    }
+    for lookup in lookups {
+        accounts.extend(
+            lookup
+                .readonly_indexes
+                .iter()
+                .map(|&index| Account::Lookup {
+                    table: lookup.account_key,
+                    index,
+                }),
+        );
+    }
+
+    let instructions = message
+        .instructions()
+        .iter()
+        .map(|instruction| {
+            let program_id = accounts[usize::from(instruction.program_id_index)];
+            let instruction_accounts: Vec<Account> = instruction
+                .accounts
+                .iter()
+                .filter_map(|&index| accounts.get(usize::from(index)).copied())
+                .collect();
+            let decoded = program_id
+                .address()
+                .and_then(|program| decode(program, &instruction_accounts, &instruction.data));
+
+            InstructionSummary {
+                program: program_name(&program_id).to_string(),
+                program_id,
+                accounts: instruction_accounts,
+                decoded,
+            }
+        })
+        .collect();
+
+    let required = usize::from(message.header().num_required_signatures);
+    MessageSummary {
+        version: match message {
+            VersionedMessage::Legacy(_) => "legacy",
+            VersionedMessage::V0(_) => "v0",
+        }
+        .to_string(),
+        fee_payer: accounts[0],
+        signers: accounts[..required].to_vec(),
+        recent_blockhash: message.recent_blockhash().to_string(),
+        lookup_tables: lookups
+            .iter()
+            .map(|lookup| Account::Key(lookup.account_key))
+            .collect(),
+        instructions,
+    }
+}
+
+fn program_name(program: &Account) -> &'static str {
+    let Some(program) = program.address() else {
+        return "Unknown";
+    };
+    match *program {
+        SYSTEM_PROGRAM_ID => "System Program",
+        TOKEN_2022_PROGRAM_ID => "Token-2022",
+        MEMO_PROGRAM_ID | MEMO_V1_PROGRAM_ID => "Memo",
+        COMPUTE_BUDGET_PROGRAM_ID => "Compute Budget",
+        ADDRESS_LOOKUP_TABLE_PROGRAM_ID => "Address Lookup Table",
+        id if id == spl_token_interface::id() => "SPL Token",
+        id if id == spl_associated_token_account_interface::program::id() => {
+            "Associated Token Account"
+        }
+        _ => "Unknown",
+    }
+}
+
+/// Decodes the instructions this module knows, or `None` for any other instruction.
+///
+/// # Arguments
+/// * `program` - Program the instruction invokes
+/// * `accounts` - The instruction's accounts, in order
+/// * `data` - The instruction's data
+fn decode(program: &Pubkey, accounts: &[Account], data: &[u8]) -> Option<DecodedInstruction> {
+    let account = |position: usize| accounts.get(position).copied();
+
+    if *program == SYSTEM_PROGRAM_ID {
+        return match bincode::deserialize::<SystemInstruction>(data).ok()? {
+            SystemInstruction::Transfer { lamports } => Some(DecodedInstruction::Transfer {
+                from: account(0)?,
+                to: account(1)?,
+                lamports,
+            }),
+            SystemInstruction::TransferWithSeed { lamports, .. } => {
+                Some(DecodedInstruction::TransferWithSeed {
+                    from: account(0)?,
+                    base: account(1)?,
+                    to: account(2)?,
+                    lamports,
+                })
+            }
+            SystemInstruction::WithdrawNonceAccount(lamports) => {
+                Some(DecodedInstruction::WithdrawNonce {
+                    nonce: account(0)?,
+                    to: account(1)?,
+                    authority: account(4)?,
+                    lamports,
+                })
+            }
+            SystemInstruction::CreateAccount {
+                lamports,
+                space,
+                owner,
+            }
+            | SystemInstruction::CreateAccountWithSeed {
+                lamports,
+                space,
+                owner,
+                ..
+            } => Some(DecodedInstruction::CreateAccount {
+                from: account(0)?,
+                to: account(1)?,
+                lamports,
+                space,
+                owner: Account::Key(owner),
+            }),
+            _ => None,
+        };
+    }
+
+    if *program == spl_token_interface::id() || *program == TOKEN_2022_PROGRAM_ID {
+        return match TokenInstruction::unpack(data).ok()? {
+            TokenInstruction::Transfer { amount } => Some(DecodedInstruction::TokenTransfer {
+                source: account(0)?,
+                destination: account(1)?,
+                authority: account(2)?,
+                amount,
+            }),
+            TokenInstruction::TransferChecked { amount, decimals } => {
+                Some(DecodedInstruction::TokenTransferChecked {
+                    source: account(0)?,
+                    mint: account(1)?,
+                    destination: account(2)?,
+                    authority: account(3)?,
+                    amount,
+                    decimals,
+                })
+            }
+            _ => None,
+        };
+    }
+
+    if *program == spl_associated_token_account_interface::program::id() {
+        // An empty instruction is the original `Create`
+        let idempotent = match data.first() {
+            None | Some(0) => false,
+            Some(1) => true,
+            Some(_) => return None,
+        };
+        return Some(DecodedInstruction::CreateTokenAccount {
+            payer: account(0)?,
+            account: account(1)?,
+            wallet: account(2)?,
+            mint: account(3)?,
+            idempotent,
+        });
+    }
+
+    if *program == MEMO_PROGRAM_ID || *program == MEMO_V1_PROGRAM_ID {
+        return Some(DecodedInstruction::Memo {
+            text: String::from_utf8_lossy(data).into_owned(),
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sign::{memo_instruction, v0_message};
+    use crate::token::{TokenProgram, TokenTransfer, token_transfer_instructions};
+    use solana_hash::Hash;
+    use solana_instruction::Instruction;
+    use solana_message::{AddressLookupTableAccount, Message};
+    use solana_system_interface::instruction as system;
+
+    fn legacy(payer: &Pubkey, instructions: &[Instruction]) -> Vec<u8> {
+        let message = Message::new_with_blockhash(instructions, Some(payer), &Hash::new_unique());
+        VersionedMessage::Legacy(message).serialize()
+    }
+
+    #[test]
+    fn legacy_transfer_with_memo_decodes_from_its_bytes() {
+        let payer = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let bytes = legacy(
+            &payer,
+            &[
+                system::transfer(&payer, &to, 42),
+                memo_instruction("invoice 42", &payer),
+            ],
+        );
+
+        let summary = summarize_bytes(&bytes).unwrap();
+
+        assert_eq!(summary.version, "legacy");
+        assert_eq!(summary.fee_payer, Account::Key(payer));
+        assert_eq!(summary.signers, vec![Account::Key(payer)]);
+        assert!(summary.lookup_tables.is_empty());
+        assert_eq!(summary.instructions[0].program, "System Program");
+        assert_eq!(
+            summary.instructions[0].decoded,
+            Some(DecodedInstruction::Transfer {
+                from: Account::Key(payer),
+                to: Account::Key(to),
+                lamports: 42,
+            })
+        );
+        assert_eq!(summary.instructions[1].program, "Memo");
+        assert_eq!(
+            summary.instructions[1].decoded,
+            Some(DecodedInstruction::Memo {
+                text: "invoice 42".to_string()
+            })
+        );
+        assert_eq!(
+            summary.to_string(),
+            format!(
+                "legacy message paid by {payer} with 2 instructions; \
+                 1) System Program: transfer 42 lamports from {payer} to {to}; \
+                 2) Memo: memo \"invoice 42\""
+            )
+        );
+    }
+
+    #[test]
+    fn v0_accounts_from_lookup_tables_are_named_by_table_and_index() {
+        let payer = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![Pubkey::new_unique(), to],
+        };
+        let message = v0_message(
+            &payer,
+            &[system::transfer(&payer, &to, 7)],
+            std::slice::from_ref(&table),
+            Hash::new_unique(),
+        )
+        .unwrap();
+
+        let summary = summarize_bytes(&message.serialize()).unwrap();
+
+        assert_eq!(summary.version, "v0");
+        assert_eq!(summary.lookup_tables, vec![Account::Key(table.key)]);
+        let recipient = Account::Lookup {
+            table: table.key,
+            index: 1,
+        };
+        assert_eq!(
+            summary.instructions[0].decoded,
+            Some(DecodedInstruction::Transfer {
+                from: Account::Key(payer),
+                to: recipient,
+                lamports: 7,
+            })
+        );
+        assert_eq!(recipient.to_string(), format!("{}#1", table.key));
+        assert_eq!(recipient.to_string().parse::<Account>().unwrap(), recipient);
+    }
+
+    #[test]
+    fn seeded_transfers_and_nonce_withdrawals_name_their_signer() {
+        let base = Pubkey::new_unique();
+        let derived = Pubkey::create_with_seed(&base, "vault", &SYSTEM_PROGRAM_ID).unwrap();
+        let nonce = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let bytes = legacy(
+            &base,
+            &[
+                system::transfer_with_seed(
+                    &derived,
+                    &base,
+                    "vault".to_string(),
+                    &SYSTEM_PROGRAM_ID,
+                    &to,
+                    5,
+                ),
+                system::withdraw_nonce_account(&nonce, &base, &to, 6),
+            ],
+        );
+
+        let summary = summarize_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            summary.instructions[0].decoded,
+            Some(DecodedInstruction::TransferWithSeed {
+                from: Account::Key(derived),
+                base: Account::Key(base),
+                to: Account::Key(to),
+                lamports: 5,
+            })
+        );
+        assert_eq!(
+            summary.instructions[1].decoded,
+            Some(DecodedInstruction::WithdrawNonce {
+                nonce: Account::Key(nonce),
+                to: Account::Key(to),
+                authority: Account::Key(base),
+                lamports: 6,
+            })
+        );
+    }
+
+    #[test]
+    fn token_transfer_with_account_creation_decodes() {
+        let owner = Pubkey::new_unique();
+        let transfer = TokenTransfer {
+            owner,
+            mint: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            amount: 2_500,
+            decimals: 2,
+            program: TokenProgram::Token2022,
+            create_recipient_account: true,
+            memo: None,
+        };
+        let bytes = legacy(&owner, &token_transfer_instructions(&transfer));
+        let destination = crate::token::associated_token_address(
+            &transfer.recipient,
+            &transfer.mint,
+            TokenProgram::Token2022,
+        );
+
+        let summary = summarize_bytes(&bytes).unwrap();
+
+        assert_eq!(summary.instructions[0].program, "Associated Token Account");
+        assert_eq!(
+            summary.instructions[0].decoded,
+            Some(DecodedInstruction::CreateTokenAccount {
+                payer: Account::Key(owner),
+                account: Account::Key(destination),
+                wallet: Account::Key(transfer.recipient),
+                mint: Account::Key(transfer.mint),
+                idempotent: true,
+            })
+        );
+        assert_eq!(summary.instructions[1].program, "Token-2022");
+        assert_eq!(
+            summary.instructions[1].decoded,
+            Some(DecodedInstruction::TokenTransferChecked {
+                source: Account::Key(crate::token::associated_token_address(
+                    &owner,
+                    &transfer.mint,
+                    TokenProgram::Token2022
+                )),
+                mint: Account::Key(transfer.mint),
+                destination: Account::Key(destination),
+                authority: Account::Key(owner),
+                amount: 2_500,
+                decimals: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_programs_are_listed_without_decoding() {
+        let payer = Pubkey::new_unique();
+        let program = Pubkey::new_unique();
+        let bytes = legacy(
+            &payer,
+            &[Instruction::new_with_bytes(program, &[1, 2, 3], vec![])],
+        );
+
+        let summary = summarize_bytes(&bytes).unwrap();
+
+        assert_eq!(summary.instructions[0].program, "Unknown");
+        assert_eq!(summary.instructions[0].program_id, Account::Key(program));
+        assert_eq!(summary.instructions[0].decoded, None);
+    }
+
+    #[test]
+    fn summary_survives_a_json_round_trip() {
+        let payer = Pubkey::new_unique();
+        let bytes = legacy(
+            &payer,
+            &[system::transfer(&payer, &Pubkey::new_unique(), 1)],
+        );
+        let summary = summarize_bytes(&bytes).unwrap();
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["instructions"][0]["decoded"]["type"], "transfer");
+        assert_eq!(
+            serde_json::from_value::<MessageSummary>(json).unwrap(),
+            summary
+        );
+    }
+
+    #[test]
+    fn bytes_that_are_not_exactly_a_message_are_rejected() {
+        let payer = Pubkey::new_unique();
+        let mut bytes = legacy(
+            &payer,
+            &[system::transfer(&payer, &Pubkey::new_unique(), 1)],
+        );
+
+        assert!(summarize_bytes(b"not a message").is_err());
+        bytes.push(0);
+        assert!(summarize_bytes(&bytes).is_err());
+    }
+}
@@ -1,12 +1,16 @@
-use crate::transport::{TcpIncoming, TcpOutgoing};
+use crate::progress::Progress;
+use crate::rpc::SolanaRpc;
+use crate::transport::split_peers;
 
-use anyhow::Result;
+use anyhow::{Result, bail, ensure};
 use rand_core::OsRng;
 use round_based::MpcParty;
 use sha2::Sha256;
 use std::convert::TryInto;
-use tokio::net::TcpStream;
-use tracing::{error, info};
+use std::str::FromStr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::{Duration, sleep};
+use tracing::{error, info, warn};
 
 use givre::ciphersuite::AdditionalEntropy;
 use givre::generic_ec::{EncodedScalar, NonZero, SecretScalar, curves::Ed25519};
@@ -14,32 +18,79 @@ use givre::key_share::DirtyKeyShare;
 use givre::keygen::{ExecutionId, ThresholdMsg, keygen};
 use givre::keygen::{key_share::Valid, security_level::SecurityLevel128};
 
+use solana_commitment_config::CommitmentConfig;
 use solana_pubkey::Pubkey;
-use solana_rpc_client::rpc_client::RpcClient;
+use solana_signature::Signature;
 
 type KeygenMsg = ThresholdMsg<Ed25519, SecurityLevel128, Sha256>;
 
+/// Airdrop requests, and status polls per request, before giving up.
+const AIRDROP_ATTEMPTS: usize = 3;
+const AIRDROP_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Threshold parameters for a DKG run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeygenConfig {
+    /// Total number of parties
+    pub n: u16,
+    /// Number of parties required to sign
+    pub threshold: u16,
+}
+
+impl KeygenConfig {
+    /// Builds a `threshold`-of-`n` configuration, rejecting values the DKG cannot produce.
+    pub fn new(n: u16, threshold: u16) -> Result<Self> {
+        if threshold < 2 {
+            bail!("threshold must be at least 2, got {}", threshold);
+        }
+        if threshold > n {
+            bail!(
+                "threshold {} exceeds the number of parties {}",
+                threshold,
+                n
+            );
+        }
+        Ok(Self { n, threshold })
+    }
+}
+
 /// Runs the DKG protocol for this participant and returns the generated private share.
-pub async fn generate_private_share(
-    socket: tokio::net::TcpStream,
+///
+/// # Arguments
+/// * `peers` - One connection to every other party, keyed by that party's index
+/// * `id` - This party's index
+/// * `config` - Number of parties and signing threshold
+/// * `session` - Session identifier, used as the execution id
+/// * `progress` - Receives peer disconnects and round starts
+pub async fn generate_private_share<S>(
+    peers: Vec<(u16, S)>,
     id: u64,
-    n: u16,
+    config: KeygenConfig,
     session: &[u8],
-) -> Result<Valid<DirtyKeyShare<Ed25519>>> {
-    let std_stream: std::net::TcpStream = socket.into_std()?;
-    std_stream.set_nonblocking(true)?;
-    let std_stream_dkg: std::net::TcpStream = std_stream.try_clone()?;
-
-    // Convert std streams to tokio streams
-    let reader_stream_dkg = TcpStream::from_std(std_stream_dkg.try_clone()?)?;
-    let writer_stream_dkg = TcpStream::from_std(std_stream_dkg)?;
-
-    let incoming = TcpIncoming::<KeygenMsg>::new(reader_stream_dkg, id);
-    let outgoing = TcpOutgoing::<KeygenMsg>::new(writer_stream_dkg);
-
-    // Initialize builder for 2-of-2 threshold (adjust as needed)
+    progress: &Progress,
+) -> Result<Valid<DirtyKeyShare<Ed25519>>>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let KeygenConfig { n, threshold } = config;
+    ensure!(
+        id < u64::from(n),
+        "party index {} is out of range for n = {}",
+        id,
+        n
+    );
+    ensure!(
+        peers.len() + 1 == usize::from(n),
+        "expected connections to {} peers, got {}",
+        n - 1,
+        peers.len()
+    );
+
+    let (incoming, outgoing) = split_peers::<KeygenMsg, _>(id, peers, progress);
+
+    // Initialize builder for t-of-n threshold
     let eid = ExecutionId::new(session);
-    let builder = keygen::<Ed25519>(eid, id as u16, n).set_threshold(2);
+    let builder = keygen::<Ed25519>(eid, id as u16, n).set_threshold(threshold);
     let mut rng = OsRng;
 
     // Start MPC party
@@ -63,58 +114,129 @@ pub async fn generate_private_share(
     Ok(valid_share)
 }
 
-/// 🚀 Helper: Airdrops `lamports` to the given Solana address (Devnet)
-
-pub fn airdrop_funds(address: &str, lamports: u64) -> Result<Pubkey> {
-    let rpc_endpoints = [
-        "https://api.devnet.solana.com",
-        "https://rpc.ankr.com/solana_devnet",
-        "https://devnet.rpcpool.com",
-        "https://rpc-devnet.helius.xyz",
-        "https://devnet-rpc.triton.one",
-    ];
-
-    let pubkey = Pubkey::from_str_const(address);
-    let mut success = false;
-
-    for url in rpc_endpoints.iter() {
-        let rpc = RpcClient::new(url.to_string());
-
-        for attempt in 1..=3 {
-            match rpc.request_airdrop(&pubkey, lamports) {
-                Ok(sig) => match rpc.confirm_transaction(&sig) {
-                    Ok(confirmed) if confirmed => {
-                        info!(
-                            "✅ Airdrop successful via {} (attempt {}) — {} lamports sent to {}",
-                            url, attempt, lamports, address
-                        );
-                        success = true;
-                        break;
-                    }
-                    Ok(_) | Err(_) => {
-                        std::thread::sleep(std::time::Duration::from_secs(2));
+/// 🚀 Helper: Airdrops `lamports` to the given Solana address and waits for it to land.
+///
+/// # Arguments
+/// * `rpc` - RPC of a cluster with a faucet, e.g. devnet or a local validator
+/// * `address` - Recipient's Solana address
+/// * `lamports` - Amount to request
+pub async fn airdrop_funds(rpc: &dyn SolanaRpc, address: &str, lamports: u64) -> Result<Pubkey> {
+    let pubkey = Pubkey::from_str(address)?;
+
+    for attempt in 1..=AIRDROP_ATTEMPTS {
+        match rpc.airdrop(&pubkey, lamports).await {
+            Ok(sig) => {
+                if airdrop_landed(rpc, &sig).await {
+                    info!(
+                        "✅ Airdrop successful (attempt {}) — {} lamports sent to {}",
+                        attempt, lamports, address
+                    );
+                    match rpc.balance(&pubkey).await {
+                        Ok(balance) => info!("Balance of {}: {} lamports", address, balance),
+                        Err(e) => error!("Failed to fetch balance for {}: {:?}", address, e),
                     }
-                },
-                Err(e) => {
-                    error!("Airdrop attempt {} via {} failed: {:?}", attempt, url, e);
-                    std::thread::sleep(std::time::Duration::from_secs(2));
+                    return Ok(pubkey);
+                }
+                warn!("Airdrop {} did not confirm (attempt {})", sig, attempt);
+            }
+            Err(e) => error!("Airdrop attempt {} failed: {:?}", attempt, e),
+        }
+        sleep(AIRDROP_RETRY_DELAY).await;
+    }
+
+    bail!(
+        "airdrop to {} failed after {} attempts",
+        address,
+        AIRDROP_ATTEMPTS
+    )
+}
+
+/// Polls an airdrop until it is confirmed; `false` if it is not within a few polls.
+async fn airdrop_landed(rpc: &dyn SolanaRpc, signature: &Signature) -> bool {
+    for _ in 0..AIRDROP_ATTEMPTS {
+        match rpc.signature_status(signature).await {
+            Ok(Some(status)) if status.err.is_none() => {
+                if status.satisfies_commitment(CommitmentConfig::confirmed()) {
+                    return true;
                 }
             }
+            Ok(Some(_)) => return false,
+            Ok(None) => {}
+            Err(e) => warn!("Failed to fetch airdrop status {}: {:?}", signature, e),
         }
+        sleep(AIRDROP_RETRY_DELAY).await;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::MockRpc;
+    use crate::testing::keygen;
+
+    const ADDRESS: &str = "4Nd1mBQtrMJVYVfKf2PJy9NZUZdTAsp7D4xWLs4gDB4T";
+
+    #[test]
+    fn threshold_below_two_is_rejected() {
+        assert!(KeygenConfig::new(3, 0).is_err());
+        assert!(KeygenConfig::new(3, 1).is_err());
+    }
+
+    #[test]
+    fn threshold_above_the_number_of_parties_is_rejected() {
+        assert!(KeygenConfig::new(3, 4).is_err());
+    }
 
-        if success {
-            break;
+    #[test]
+    fn threshold_up_to_the_number_of_parties_is_accepted() {
+        assert_eq!(
+            KeygenConfig::new(3, 2).unwrap(),
+            KeygenConfig { n: 3, threshold: 2 }
+        );
+        assert_eq!(
+            KeygenConfig::new(3, 3).unwrap(),
+            KeygenConfig { n: 3, threshold: 3 }
+        );
+    }
+
+    #[tokio::test]
+    async fn two_of_three_keygen_gives_every_party_a_share_of_one_key() {
+        let shares = keygen(3, 2).await;
+
+        assert_eq!(shares.len(), 3);
+        for (party, share) in shares.iter().enumerate() {
+            assert_eq!(usize::from(share.i), party);
+            assert_eq!(share.n(), 3);
+            assert_eq!(share.min_signers(), 2);
+            assert_eq!(share.shared_public_key(), shares[0].shared_public_key());
         }
     }
 
-    let rpc = RpcClient::new("https://api.devnet.solana.com".to_string());
-    if let Err(e) = rpc.get_balance(&pubkey) {
-        error!("Failed to fetch balance for {}: {:?}", address, e);
+    #[tokio::test(start_paused = true)]
+    async fn airdrop_is_retried_until_the_faucet_grants_it() {
+        let rpc = MockRpc::new().fail_airdrops(AIRDROP_ATTEMPTS - 1);
+
+        let pubkey = airdrop_funds(&rpc, ADDRESS, 1_000).await.unwrap();
+
+        assert_eq!(pubkey, Pubkey::from_str(ADDRESS).unwrap());
+        assert_eq!(rpc.airdrops(), vec![(pubkey, 1_000)]);
+        assert_eq!(rpc.balance(&pubkey).await.unwrap(), 1_000);
     }
 
-    if !success {
-        error!("Airdrop failed on all RPC endpoints for {}", address);
+    #[tokio::test(start_paused = true)]
+    async fn airdrop_gives_up_after_every_attempt_failed() {
+        let rpc = MockRpc::new().fail_airdrops(AIRDROP_ATTEMPTS);
+
+        assert!(airdrop_funds(&rpc, ADDRESS, 1_000).await.is_err());
+        assert!(rpc.airdrops().is_empty());
     }
 
-    Ok(pubkey)
+    #[tokio::test]
+    async fn airdrop_to_an_invalid_address_asks_the_faucet_for_nothing() {
+        let rpc = MockRpc::new();
+
+        assert!(airdrop_funds(&rpc, "not-an-address", 1_000).await.is_err());
+        assert!(rpc.airdrops().is_empty());
+    }
 }
@@ -2,4 +2,20 @@
 pub mod keygen;
 pub mod sign;
 pub mod env_loader;
-pub mod transport;
\ No newline at end of file
+pub mod seal;
+pub mod store;
+pub mod transport;
+pub mod scheduler;
+pub mod control;
+pub mod progress;
+pub mod transaction;
+pub mod rpc;
+pub mod token;
+pub mod policy;
+pub mod decode;
+pub mod offchain;
+pub mod siws;
+pub mod presign;
+pub mod refresh;
+#[cfg(test)]
+mod testing;
\ No newline at end of file
@@ -0,0 +1,129 @@
+use anyhow::{Result, anyhow, ensure};
+use solana_offchain_message::{OffchainMessage, v0};
+use solana_pubkey::Pubkey;
+use solana_signature::Signature;
+
+/// Largest payload an off-chain message carries.
+pub const MAX_PAYLOAD_LEN: usize = v0::OffchainMessage::MAX_LEN;
+
+/// Largest payload a Ledger device can show when asked to sign it.
+pub const MAX_LEDGER_PAYLOAD_LEN: usize = v0::OffchainMessage::MAX_LEN_LEDGER;
+
+/// Wraps `payload` in a version 0 Solana off-chain message: the `\xffsolana offchain` signing
+/// domain, the header version, the payload's format and its length, then the payload itself.
+/// The format is printable ASCII or UTF-8 up to [`MAX_LEDGER_PAYLOAD_LEN`] bytes, and
+/// extended UTF-8 beyond that.
+///
+/// No transaction message starts with `0xff`, so a signature over the result can never be
+/// replayed as a transaction signature, nor a transaction signature as this one.
+pub fn offchain_message(payload: &[u8]) -> Result<Vec<u8>> {
+    ensure!(!payload.is_empty(), "Off-chain message is empty");
+    ensure!(
+        payload.len() <= MAX_PAYLOAD_LEN,
+        "Off-chain message is {} bytes, more than the {} allowed",
+        payload.len(),
+        MAX_PAYLOAD_LEN
+    );
+    ensure!(
+        std::str::from_utf8(payload).is_ok(),
+        "Off-chain message is not UTF-8"
+    );
+
+    OffchainMessage::new(0, payload)
+        .and_then(|message| message.serialize())
+        .map_err(|e| anyhow!("Failed to build off-chain message: {}", e))
+}
+
+/// Payload of a serialized off-chain message, as built by [`offchain_message`].
+pub fn parse_offchain_message(bytes: &[u8]) -> Result<Vec<u8>> {
+    ensure!(
+        bytes.starts_with(OffchainMessage::SIGNING_DOMAIN),
+        "Bytes do not start with the off-chain signing domain"
+    );
+    let message = OffchainMessage::deserialize(bytes)
+        .map_err(|e| anyhow!("Invalid off-chain message: {}", e))?;
+    Ok(message.get_message().clone())
+}
+
+/// Checks that `signature` is `signer`'s over `payload` wrapped by [`offchain_message`].
+///
+/// # Arguments
+/// * `signer` - Key expected to have signed, e.g. a session's public key
+/// * `payload` - The payload that was sent for signing, without the off-chain header
+/// * `signature` - Signature returned in the `sign-result`
+pub fn verify_offchain_signature(
+    signer: &Pubkey,
+    payload: &[u8],
+    signature: &Signature,
+) -> Result<()> {
+    let message = offchain_message(payload)?;
+    ensure!(
+        signature.verify(signer.as_ref(), &message),
+        "Signature is not {}'s over the off-chain message",
+        signer
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const DOMAIN: &[u8] = b"\xffsolana offchain";
+
+    #[test]
+    fn header_carries_domain_version_format_and_length() {
+        let message = offchain_message(b"hello").unwrap();
+
+        assert_eq!(&message[..16], DOMAIN);
+        // Version 0, printable ASCII, five bytes
+        assert_eq!(&message[16..20], &[0, 0, 5, 0]);
+        assert_eq!(&message[20..], b"hello");
+        assert_eq!(parse_offchain_message(&message).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn format_follows_the_payload() {
+        let utf8 = offchain_message("héllo".as_bytes()).unwrap();
+        assert_eq!(utf8[17], 1);
+
+        let long = "a".repeat(MAX_LEDGER_PAYLOAD_LEN + 1);
+        let extended = offchain_message(long.as_bytes()).unwrap();
+        assert_eq!(extended[17], 2);
+        assert_eq!(
+            u16::from_le_bytes([extended[18], extended[19]]),
+            long.len() as u16
+        );
+    }
+
+    #[test]
+    fn payloads_that_do_not_fit_are_refused() {
+        assert!(offchain_message(b"").is_err());
+        assert!(offchain_message(&[0xc3, 0x28]).is_err());
+        assert!(offchain_message(&vec![b'a'; MAX_PAYLOAD_LEN + 1]).is_err());
+    }
+
+    #[test]
+    fn parse_refuses_transactions_and_bad_lengths() {
+        assert!(parse_offchain_message(&[1, 0, 0]).is_err());
+
+        let mut message = offchain_message(b"hello").unwrap();
+        message.push(b'!');
+        assert!(parse_offchain_message(&message).is_err());
+    }
+
+    #[tokio::test]
+    async fn signature_verifies_over_the_wrapped_payload_only() {
+        let shares = testing::keygen(2, 2).await;
+        let signer = testing::public_key(&shares[0]);
+        let signature = testing::sign(&shares, &offchain_message(b"hello").unwrap()).await;
+
+        assert!(verify_offchain_signature(&signer, b"hello", &signature).is_ok());
+        assert!(verify_offchain_signature(&signer, b"hellO", &signature).is_err());
+
+        // A signature over the bare payload is not an off-chain signature
+        let bare = testing::sign(&shares, b"hello").await;
+        assert!(verify_offchain_signature(&signer, b"hello", &bare).is_err());
+    }
+}
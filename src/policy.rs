@@ -0,0 +1,818 @@
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use solana_pubkey::Pubkey;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::decode::{Account, DecodedInstruction, MessageSummary};
+
+/// Window `max_lamports_per_day` is enforced over.
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Rules a node checks every message against before co-signing it, read from a JSON file.
+/// A rule that is left out allows everything.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Programs a message may invoke
+    #[serde(default, deserialize_with = "pubkey_set")]
+    pub allowed_programs: Option<HashSet<Pubkey>>,
+    /// Largest single lamport transfer out of the session key
+    #[serde(default)]
+    pub max_lamports_per_transfer: Option<u64>,
+    /// Lamports the session key may send over any 24 hours
+    #[serde(default)]
+    pub max_lamports_per_day: Option<u64>,
+    /// Only these accounts may receive lamports or tokens
+    #[serde(default, deserialize_with = "pubkey_set")]
+    pub allowed_destinations: Option<HashSet<Pubkey>>,
+    /// These accounts may never receive lamports or tokens
+    #[serde(default, deserialize_with = "pubkey_set")]
+    pub denied_destinations: Option<HashSet<Pubkey>>,
+    /// Every message must carry a memo instruction
+    #[serde(default)]
+    pub require_memo: bool,
+    /// Sign bytes that are not a Solana message, which no other rule can check
+    #[serde(default)]
+    pub allow_raw_messages: bool,
+    /// Refuse off-chain and sign-in messages, which cannot move funds and are otherwise
+    /// signed whatever the other rules say
+    #[serde(default)]
+    pub deny_offchain_messages: bool,
+}
+
+/// Policy rule a message broke.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyRule {
+    /// The bytes are not a Solana message and `allow_raw_messages` is off
+    RawMessage,
+    /// An off-chain or sign-in message, and `deny_offchain_messages` is on
+    OffchainMessage,
+    ProgramNotAllowed,
+    TransferLimit,
+    DailyLimit,
+    DestinationNotAllowed,
+    DestinationDenied,
+    /// A destination is loaded from an address lookup table, so the lists cannot check it
+    UnresolvedDestination,
+    /// Lamports leave through an instruction the limits and lists do not count, such as a
+    /// nonce account withdrawal
+    UncheckedTransfer,
+    MemoRequired,
+}
+
+/// Why a node refused to co-sign, returned with the `policy_violation` error code.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct PolicyViolation {
+    pub rule: PolicyRule,
+    /// Base58 program or destination that broke the rule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// Lamports the message would send, counted the way the rule counts them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
+    /// The rule's limit in lamports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+}
+
+impl PolicyViolation {
+    fn new(rule: PolicyRule) -> Self {
+        Self {
+            rule,
+            subject: None,
+            amount: None,
+            limit: None,
+        }
+    }
+
+    fn subject(rule: PolicyRule, subject: &impl fmt::Display) -> Self {
+        Self {
+            subject: Some(subject.to_string()),
+            ..Self::new(rule)
+        }
+    }
+
+    fn limit(rule: PolicyRule, amount: u64, limit: u64) -> Self {
+        Self {
+            amount: Some(amount),
+            limit: Some(limit),
+            ..Self::new(rule)
+        }
+    }
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let subject = self.subject.as_deref().unwrap_or_default();
+        match self.rule {
+            PolicyRule::RawMessage => write!(f, "policy only signs Solana messages"),
+            PolicyRule::OffchainMessage => write!(f, "policy does not sign off-chain messages"),
+            PolicyRule::ProgramNotAllowed => write!(f, "program {} is not allowed", subject),
+            PolicyRule::TransferLimit => write!(
+                f,
+                "transfer of {} lamports exceeds the limit of {}",
+                self.amount.unwrap_or_default(),
+                self.limit.unwrap_or_default()
+            ),
+            PolicyRule::DailyLimit => write!(
+                f,
+                "{} lamports sent over 24 hours would exceed the daily limit of {}",
+                self.amount.unwrap_or_default(),
+                self.limit.unwrap_or_default()
+            ),
+            PolicyRule::DestinationNotAllowed => {
+                write!(f, "destination {} is not allowed", subject)
+            }
+            PolicyRule::DestinationDenied => write!(f, "destination {} is denied", subject),
+            PolicyRule::UnresolvedDestination => write!(
+                f,
+                "a destination is loaded from a lookup table and cannot be checked"
+            ),
+            PolicyRule::UncheckedTransfer => write!(
+                f,
+                "lamports leaving {} cannot be checked against the transfer rules",
+                subject
+            ),
+            PolicyRule::MemoRequired => write!(f, "policy requires a memo"),
+        }
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+/// A transfer found in a message.
+#[derive(Debug, Clone, Copy)]
+struct Transfer {
+    /// Account that signs for the transfer: the one debited, or the base of a derived one
+    source: Account,
+    destination: Account,
+    /// `None` for token transfers, which are not counted in lamports
+    lamports: Option<u64>,
+}
+
+/// What the policy looks at in a message.
+#[derive(Debug, Default)]
+struct Inspection {
+    programs: Vec<Account>,
+    transfers: Vec<Transfer>,
+    /// Accounts lamports leave through instructions that are not counted as transfers
+    unchecked: Vec<Account>,
+    has_memo: bool,
+}
+
+/// Collects the invoked programs, lamport and token transfers, other lamport withdrawals, and
+/// memos of `summary`.
+fn inspect(summary: &MessageSummary) -> Inspection {
+    let mut inspection = Inspection::default();
+
+    for instruction in &summary.instructions {
+        inspection.programs.push(instruction.program_id);
+
+        let transfer = match instruction.decoded {
+            Some(DecodedInstruction::Transfer { from, to, lamports })
+            | Some(DecodedInstruction::CreateAccount {
+                from, to, lamports, ..
+            }) => Some(Transfer {
+                source: from,
+                destination: to,
+                lamports: Some(lamports),
+            }),
+            Some(DecodedInstruction::TransferWithSeed {
+                base, to, lamports, ..
+            }) => Some(Transfer {
+                source: base,
+                destination: to,
+                lamports: Some(lamports),
+            }),
+            Some(DecodedInstruction::WithdrawNonce { nonce, .. }) => {
+                inspection.unchecked.push(nonce);
+                None
+            }
+            Some(DecodedInstruction::TokenTransfer {
+                source,
+                destination,
+                ..
+            })
+            | Some(DecodedInstruction::TokenTransferChecked {
+                source,
+                destination,
+                ..
+            }) => Some(Transfer {
+                source,
+                destination,
+                lamports: None,
+            }),
+            Some(DecodedInstruction::Memo { .. }) => {
+                inspection.has_memo = true;
+                None
+            }
+            _ => None,
+        };
+        inspection.transfers.extend(transfer);
+    }
+
+    inspection
+}
+
+/// Checks messages against a [`Policy`] and keeps the lamports each key sent over the last
+/// 24 hours. Spending is kept in memory, so the daily window restarts with the node.
+pub struct PolicyEngine {
+    policy: Policy,
+    spent: Mutex<HashMap<Pubkey, VecDeque<(Instant, u64)>>>,
+}
+
+impl PolicyEngine {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            spent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reads a policy from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read policy file {}", path.display()))?;
+        let policy = serde_json::from_str(&json)
+            .with_context(|| format!("invalid policy file {}", path.display()))?;
+        Ok(Self::new(policy))
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Checks a message `signer` is asked to sign. On approval the lamports the message sends
+    /// out of `signer` are reserved against its daily limit until the [`Approval`] is
+    /// committed once signed, or dropped if signing fails.
+    ///
+    /// # Arguments
+    /// * `signer` - The session key
+    /// * `summary` - The decoded bytes to sign, or `None` if they are not a Solana message,
+    ///   which is refused unless `allow_raw_messages` is set
+    pub fn check(
+        self: &Arc<Self>,
+        signer: &Pubkey,
+        summary: Option<&MessageSummary>,
+    ) -> Result<Approval, PolicyViolation> {
+        let policy = &self.policy;
+        let approval = Approval {
+            engine: self.clone(),
+            signer: *signer,
+            reserved: None,
+        };
+
+        let Some(summary) = summary else {
+            if policy.allow_raw_messages {
+                return Ok(approval);
+            }
+            return Err(PolicyViolation::new(PolicyRule::RawMessage));
+        };
+        let inspection = inspect(summary);
+
+        if let Some(allowed) = &policy.allowed_programs
+            && let Some(program) = inspection
+                .programs
+                .iter()
+                .find(|p| p.address().is_none_or(|p| !allowed.contains(p)))
+        {
+            return Err(PolicyViolation::subject(
+                PolicyRule::ProgramNotAllowed,
+                program,
+            ));
+        }
+
+        let checks_destinations =
+            policy.allowed_destinations.is_some() || policy.denied_destinations.is_some();
+        let checks_transfers = checks_destinations
+            || policy.max_lamports_per_transfer.is_some()
+            || policy.max_lamports_per_day.is_some();
+        if checks_transfers && let Some(account) = inspection.unchecked.first() {
+            return Err(PolicyViolation::subject(
+                PolicyRule::UncheckedTransfer,
+                account,
+            ));
+        }
+
+        for transfer in inspection.transfers.iter().filter(|_| checks_destinations) {
+            let Some(destination) = transfer.destination.address() else {
+                return Err(PolicyViolation::new(PolicyRule::UnresolvedDestination));
+            };
+            if let Some(denied) = &policy.denied_destinations
+                && denied.contains(destination)
+            {
+                return Err(PolicyViolation::subject(
+                    PolicyRule::DestinationDenied,
+                    destination,
+                ));
+            }
+            if let Some(allowed) = &policy.allowed_destinations
+                && !allowed.contains(destination)
+            {
+                return Err(PolicyViolation::subject(
+                    PolicyRule::DestinationNotAllowed,
+                    destination,
+                ));
+            }
+        }
+
+        if policy.require_memo && !inspection.has_memo {
+            return Err(PolicyViolation::new(PolicyRule::MemoRequired));
+        }
+
+        // Only lamports leaving the session key count against its limits
+        let sent: Vec<u64> = inspection
+            .transfers
+            .iter()
+            .filter(|t| t.source.address() == Some(signer))
+            .filter_map(|t| t.lamports)
+            .collect();
+        if let Some(limit) = policy.max_lamports_per_transfer
+            && let Some(&lamports) = sent.iter().find(|&&l| l > limit)
+        {
+            return Err(PolicyViolation::limit(
+                PolicyRule::TransferLimit,
+                lamports,
+                limit,
+            ));
+        }
+
+        let total = sent.iter().fold(0u64, |sum, &l| sum.saturating_add(l));
+        self.reserve(approval, total)
+    }
+
+    /// Checks an off-chain or sign-in message `signer` is asked to sign. Such messages move no
+    /// lamports, so nothing is reserved.
+    pub fn check_offchain(self: &Arc<Self>, signer: &Pubkey) -> Result<Approval, PolicyViolation> {
+        if self.policy.deny_offchain_messages {
+            return Err(PolicyViolation::new(PolicyRule::OffchainMessage));
+        }
+        Ok(Approval {
+            engine: self.clone(),
+            signer: *signer,
+            reserved: None,
+        })
+    }
+
+    /// Records `lamports` against the signer's daily limit, if it leaves room for them.
+    fn reserve(&self, mut approval: Approval, lamports: u64) -> Result<Approval, PolicyViolation> {
+        if lamports == 0 {
+            return Ok(approval);
+        }
+
+        let now = Instant::now();
+        let mut spent = self.spent.lock().unwrap();
+        let history = spent.entry(approval.signer).or_default();
+        while history
+            .front()
+            .is_some_and(|&(at, _)| now.duration_since(at) >= DAY)
+        {
+            history.pop_front();
+        }
+
+        if let Some(limit) = self.policy.max_lamports_per_day {
+            let today = history
+                .iter()
+                .fold(0u64, |sum, &(_, l)| sum.saturating_add(l));
+            if today.saturating_add(lamports) > limit {
+                return Err(PolicyViolation::limit(
+                    PolicyRule::DailyLimit,
+                    today.saturating_add(lamports),
+                    limit,
+                ));
+            }
+        }
+
+        history.push_back((now, lamports));
+        approval.reserved = Some((now, lamports));
+        Ok(approval)
+    }
+}
+
+/// A message the policy approved. Its lamports stay counted against the daily limit only if
+/// it is committed; dropping it releases them.
+pub struct Approval {
+    engine: Arc<PolicyEngine>,
+    signer: Pubkey,
+    reserved: Option<(Instant, u64)>,
+}
+
+impl Approval {
+    /// Keeps the reserved lamports counted, once the message has been signed.
+    pub fn commit(mut self) {
+        self.reserved = None;
+    }
+}
+
+impl Drop for Approval {
+    fn drop(&mut self) {
+        let Some(reserved) = self.reserved else {
+            return;
+        };
+        let mut spent = self.engine.spent.lock().unwrap();
+        if let Some(history) = spent.get_mut(&self.signer)
+            && let Some(position) = history.iter().position(|&entry| entry == reserved)
+        {
+            history.remove(position);
+        }
+    }
+}
+
+fn pubkey_set<'de, D>(deserializer: D) -> Result<Option<HashSet<Pubkey>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(addresses) = Option::<Vec<String>>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    addresses
+        .iter()
+        .map(|address| {
+            Pubkey::from_str(address).map_err(|e| {
+                serde::de::Error::custom(format!("`{}` is not a base58 address: {}", address, e))
+            })
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::summarize;
+    use crate::sign::{memo_instruction, v0_message};
+    use crate::token::{TokenProgram, TokenTransfer, token_transfer_instructions};
+    use solana_hash::Hash;
+    use solana_instruction::Instruction;
+    use solana_message::{AddressLookupTableAccount, Message, VersionedMessage};
+    use solana_system_interface::instruction as system;
+    use solana_system_interface::program::ID as SYSTEM_PROGRAM_ID;
+
+    fn engine(policy: Policy) -> Arc<PolicyEngine> {
+        Arc::new(PolicyEngine::new(policy))
+    }
+
+    fn summary(payer: &Pubkey, instructions: &[Instruction]) -> MessageSummary {
+        let message = Message::new_with_blockhash(instructions, Some(payer), &Hash::new_unique());
+        summarize(&VersionedMessage::Legacy(message))
+    }
+
+    fn transfer(from: &Pubkey, to: &Pubkey, lamports: u64) -> MessageSummary {
+        summary(from, &[system::transfer(from, to, lamports)])
+    }
+
+    fn rule(result: Result<Approval, PolicyViolation>) -> PolicyRule {
+        result.err().expect("policy approved the message").rule
+    }
+
+    #[test]
+    fn an_empty_policy_signs_any_message_but_raw_bytes() {
+        let engine = engine(Policy::default());
+        let signer = Pubkey::new_unique();
+
+        assert!(
+            engine
+                .check(&signer, Some(&transfer(&signer, &Pubkey::new_unique(), 1)))
+                .is_ok()
+        );
+        assert_eq!(rule(engine.check(&signer, None)), PolicyRule::RawMessage);
+        assert!(engine.check_offchain(&signer).is_ok());
+    }
+
+    #[test]
+    fn raw_and_offchain_messages_follow_their_switches() {
+        let engine = engine(Policy {
+            allow_raw_messages: true,
+            deny_offchain_messages: true,
+            ..Default::default()
+        });
+        let signer = Pubkey::new_unique();
+
+        assert!(engine.check(&signer, None).is_ok());
+        assert_eq!(
+            rule(engine.check_offchain(&signer)),
+            PolicyRule::OffchainMessage
+        );
+    }
+
+    #[test]
+    fn programs_outside_the_allow_list_are_refused() {
+        let signer = Pubkey::new_unique();
+        let engine = engine(Policy {
+            allowed_programs: Some(HashSet::from([SYSTEM_PROGRAM_ID])),
+            ..Default::default()
+        });
+
+        let with_memo = summary(
+            &signer,
+            &[
+                system::transfer(&signer, &Pubkey::new_unique(), 1),
+                memo_instruction("hi", &signer),
+            ],
+        );
+        let violation = engine.check(&signer, Some(&with_memo)).err().unwrap();
+        assert_eq!(violation.rule, PolicyRule::ProgramNotAllowed);
+        assert_eq!(
+            violation.subject,
+            Some(crate::sign::MEMO_PROGRAM_ID.to_string())
+        );
+        assert!(
+            engine
+                .check(&signer, Some(&transfer(&signer, &Pubkey::new_unique(), 1)))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn transfers_above_the_limit_are_refused() {
+        let signer = Pubkey::new_unique();
+        let engine = engine(Policy {
+            max_lamports_per_transfer: Some(1_000),
+            ..Default::default()
+        });
+        let to = Pubkey::new_unique();
+
+        assert!(
+            engine
+                .check(&signer, Some(&transfer(&signer, &to, 1_000)))
+                .is_ok()
+        );
+        let violation = engine
+            .check(&signer, Some(&transfer(&signer, &to, 1_001)))
+            .err()
+            .unwrap();
+        assert_eq!(
+            violation,
+            PolicyViolation::limit(PolicyRule::TransferLimit, 1_001, 1_000)
+        );
+
+        // Account creation funded by the session key moves its lamports too
+        let create = summary(
+            &signer,
+            &[system::create_account(
+                &signer,
+                &to,
+                5_000,
+                0,
+                &SYSTEM_PROGRAM_ID,
+            )],
+        );
+        assert_eq!(
+            rule(engine.check(&signer, Some(&create))),
+            PolicyRule::TransferLimit
+        );
+    }
+
+    #[test]
+    fn only_lamports_leaving_the_session_key_are_counted() {
+        let signer = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let engine = engine(Policy {
+            max_lamports_per_transfer: Some(1_000),
+            ..Default::default()
+        });
+
+        let message = summary(&signer, &[system::transfer(&other, &signer, 1_000_000)]);
+        assert!(engine.check(&signer, Some(&message)).is_ok());
+    }
+
+    #[test]
+    fn seeded_transfers_count_against_their_base() {
+        let signer = Pubkey::new_unique();
+        let derived = Pubkey::create_with_seed(&signer, "vault", &SYSTEM_PROGRAM_ID).unwrap();
+        let engine = engine(Policy {
+            max_lamports_per_transfer: Some(1_000),
+            ..Default::default()
+        });
+
+        let message = summary(
+            &signer,
+            &[system::transfer_with_seed(
+                &derived,
+                &signer,
+                "vault".to_string(),
+                &SYSTEM_PROGRAM_ID,
+                &Pubkey::new_unique(),
+                1_000_000,
+            )],
+        );
+        assert_eq!(
+            engine.check(&signer, Some(&message)).err().unwrap(),
+            PolicyViolation::limit(PolicyRule::TransferLimit, 1_000_000, 1_000)
+        );
+    }
+
+    #[test]
+    fn nonce_withdrawals_are_refused_while_transfers_are_checked() {
+        let signer = Pubkey::new_unique();
+        let nonce = Pubkey::new_unique();
+        let message = summary(
+            &signer,
+            &[system::withdraw_nonce_account(
+                &nonce,
+                &signer,
+                &Pubkey::new_unique(),
+                1_000_000,
+            )],
+        );
+
+        let limited = engine(Policy {
+            max_lamports_per_day: Some(1_000),
+            ..Default::default()
+        });
+        let violation = limited.check(&signer, Some(&message)).err().unwrap();
+        assert_eq!(violation.rule, PolicyRule::UncheckedTransfer);
+        assert_eq!(violation.subject, Some(nonce.to_string()));
+
+        let unlimited = engine(Policy::default());
+        assert!(unlimited.check(&signer, Some(&message)).is_ok());
+    }
+
+    #[test]
+    fn daily_limit_counts_committed_messages_only() {
+        let signer = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let engine = engine(Policy {
+            max_lamports_per_day: Some(1_000),
+            ..Default::default()
+        });
+
+        engine
+            .check(&signer, Some(&transfer(&signer, &to, 600)))
+            .unwrap()
+            .commit();
+
+        // Held while signing; released when the signing fails
+        let pending = engine
+            .check(&signer, Some(&transfer(&signer, &to, 400)))
+            .unwrap();
+        assert_eq!(
+            engine
+                .check(&signer, Some(&transfer(&signer, &to, 1)))
+                .err()
+                .unwrap(),
+            PolicyViolation::limit(PolicyRule::DailyLimit, 1_001, 1_000)
+        );
+        drop(pending);
+        assert!(
+            engine
+                .check(&signer, Some(&transfer(&signer, &to, 400)))
+                .is_ok()
+        );
+
+        // Each key has its own budget
+        let other = Pubkey::new_unique();
+        assert!(
+            engine
+                .check(&other, Some(&transfer(&other, &to, 1_000)))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn destinations_follow_the_allow_and_deny_lists() {
+        let signer = Pubkey::new_unique();
+        let friend = Pubkey::new_unique();
+        let foe = Pubkey::new_unique();
+
+        let allowing = engine(Policy {
+            allowed_destinations: Some(HashSet::from([friend])),
+            ..Default::default()
+        });
+        assert!(
+            allowing
+                .check(&signer, Some(&transfer(&signer, &friend, 1)))
+                .is_ok()
+        );
+        let violation = allowing
+            .check(&signer, Some(&transfer(&signer, &foe, 1)))
+            .err()
+            .unwrap();
+        assert_eq!(
+            violation,
+            PolicyViolation::subject(PolicyRule::DestinationNotAllowed, &foe)
+        );
+
+        let denying = engine(Policy {
+            denied_destinations: Some(HashSet::from([foe])),
+            ..Default::default()
+        });
+        assert!(
+            denying
+                .check(&signer, Some(&transfer(&signer, &friend, 1)))
+                .is_ok()
+        );
+        assert_eq!(
+            rule(denying.check(&signer, Some(&transfer(&signer, &foe, 1)))),
+            PolicyRule::DestinationDenied
+        );
+    }
+
+    #[test]
+    fn token_transfers_are_checked_by_their_destination_account() {
+        let signer = Pubkey::new_unique();
+        let transfer = TokenTransfer {
+            owner: signer,
+            mint: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            amount: u64::MAX,
+            decimals: 6,
+            program: TokenProgram::Token,
+            create_recipient_account: false,
+            memo: None,
+        };
+        let message = summary(&signer, &token_transfer_instructions(&transfer));
+        let destination = crate::token::associated_token_address(
+            &transfer.recipient,
+            &transfer.mint,
+            TokenProgram::Token,
+        );
+
+        let engine = engine(Policy {
+            denied_destinations: Some(HashSet::from([destination])),
+            // Token amounts are not lamports
+            max_lamports_per_transfer: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(
+            rule(engine.check(&signer, Some(&message))),
+            PolicyRule::DestinationDenied
+        );
+    }
+
+    #[test]
+    fn destinations_from_lookup_tables_cannot_be_checked() {
+        let signer = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![to],
+        };
+        let message = v0_message(
+            &signer,
+            &[system::transfer(&signer, &to, 1)],
+            &[table],
+            Hash::new_unique(),
+        )
+        .unwrap();
+
+        let engine = engine(Policy {
+            allowed_destinations: Some(HashSet::from([to])),
+            ..Default::default()
+        });
+        assert_eq!(
+            rule(engine.check(&signer, Some(&summarize(&message)))),
+            PolicyRule::UnresolvedDestination
+        );
+    }
+
+    #[test]
+    fn memo_is_required_when_configured() {
+        let signer = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let engine = engine(Policy {
+            require_memo: true,
+            ..Default::default()
+        });
+
+        assert_eq!(
+            rule(engine.check(&signer, Some(&transfer(&signer, &to, 1)))),
+            PolicyRule::MemoRequired
+        );
+        let with_memo = summary(
+            &signer,
+            &[
+                system::transfer(&signer, &to, 1),
+                memo_instruction("invoice 42", &signer),
+            ],
+        );
+        assert!(engine.check(&signer, Some(&with_memo)).is_ok());
+    }
+
+    #[test]
+    fn policy_files_reject_unknown_rules_and_bad_addresses() {
+        let policy: Policy = serde_json::from_str(
+            r#"{"allowed_programs": ["11111111111111111111111111111111"], "require_memo": true}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy.allowed_programs,
+            Some(HashSet::from([SYSTEM_PROGRAM_ID]))
+        );
+        assert!(policy.require_memo);
+
+        assert!(serde_json::from_str::<Policy>(r#"{"max_lamports": 1}"#).is_err());
+        assert!(
+            serde_json::from_str::<Policy>(r#"{"denied_destinations": ["not-an-address"]}"#)
+                .is_err()
+        );
+    }
+}
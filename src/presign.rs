@@ -0,0 +1,380 @@
+use crate::progress::Progress;
+use crate::sign::{peer_positions, signer_set, verified_signature};
+use crate::store::KeyShare;
+use crate::transport::split_peers;
+
+use anyhow::{Context, Result, anyhow, ensure};
+use futures::{SinkExt, StreamExt};
+use givre::ciphersuite::Ed25519 as CsEd25519;
+use givre::generic_ec::curves::Ed25519;
+use givre::key_share::KeyInfo;
+use givre::signing::aggregate;
+use givre::signing::round1::{self, PublicCommitments, SecretNonces};
+use givre::signing::round2::{self, SigShare};
+use rand_core::OsRng;
+use round_based::{Outgoing, ProtocolMessage};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use solana_signature::Signature;
+use std::fmt;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// One signer's secret nonces for a signing session that has not happened yet, with the
+/// commitments every signer of the set published for the same slot. Signing with it takes a
+/// single exchange of partial signatures.
+///
+/// **Never sign twice with one presignature**: two partial signatures over the same nonces
+/// reveal the key share. It is consumed by [`run_presigned_signing_phase`] and not `Clone`, and
+/// stores hand each one out at most once.
+#[derive(Serialize, Deserialize)]
+pub struct Presignature {
+    /// Position in the signer set's sequence of presignatures, the same on every signer
+    pub id: u64,
+    /// Keygen indices of the signers, in canonical order
+    pub signers: Vec<u16>,
+    nonces: SecretNonces<Ed25519>,
+    /// Every signer's commitments, in the order of `signers`
+    commitments: Vec<PublicCommitments<Ed25519>>,
+}
+
+impl fmt::Debug for Presignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Presignature")
+            .field("id", &self.id)
+            .field("signers", &self.signers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Signers picked different presignatures for one message, e.g. because one of them lost a
+/// session half way. Every presignature up to `highest` is stale on every signer and should be
+/// discarded, so that the next attempt picks the same one everywhere.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresignatureMismatch {
+    /// Presignature this signer used
+    pub used: u64,
+    /// Highest presignature any signer used
+    pub highest: u64,
+}
+
+impl fmt::Display for PresignatureMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "signers used different presignatures (this signer {}, highest {})",
+            self.used, self.highest
+        )
+    }
+}
+
+impl std::error::Error for PresignatureMismatch {}
+
+/// Nonce commitments a signer publishes in the offline phase.
+#[derive(Serialize, Deserialize, Clone)]
+struct Commitments {
+    /// Lowest id this signer can give the new presignatures without reusing one it holds
+    first_id: u64,
+    commitments: Vec<PublicCommitments<Ed25519>>,
+}
+
+/// The only round of the offline phase.
+impl ProtocolMessage for Commitments {
+    fn round(&self) -> u16 {
+        0
+    }
+}
+
+/// A signer's share of the signature in the online phase.
+#[derive(Serialize, Deserialize, Clone)]
+struct PartialSignature {
+    presignature: u64,
+    share: SigShare<Ed25519>,
+}
+
+/// The only round of the online phase.
+impl ProtocolMessage for PartialSignature {
+    fn round(&self) -> u16 {
+        0
+    }
+}
+
+/// Offline phase: every signer generates `count` pairs of FROST nonces and sends the others
+/// its commitments to them. Returns this signer's presignatures, numbered from the highest
+/// `first_id` any signer proposed so the ids agree everywhere. No message is needed yet.
+///
+/// # Arguments
+/// * `id` - Signer's party index at keygen
+/// * `key_share` - Participant's valid key share from DKG, mixed into the nonces
+/// * `signers` - Keygen indices of every party that will sign with the presignatures
+/// * `peers` - One connection to every other signer, keyed by that signer's keygen index
+/// * `count` - Number of presignatures to generate
+/// * `first_id` - Lowest id above every presignature this signer holds for the signer set
+/// * `progress` - Receives peer disconnects, by keygen index, and round starts
+pub async fn run_presign_phase<S>(
+    id: u64,
+    key_share: &KeyShare,
+    signers: &[u16],
+    peers: Vec<(u16, S)>,
+    count: usize,
+    first_id: u64,
+    progress: &Progress,
+) -> Result<Vec<Presignature>>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    ensure!(count > 0, "no presignatures to generate");
+    let parties = signer_set(key_share, signers)?;
+    let (i, peers) = peer_positions(id, &parties, peers)?;
+
+    let (nonces, commitments): (Vec<_>, Vec<_>) = (0..count)
+        .map(|_| round1::commit::<CsEd25519>(&mut OsRng, key_share))
+        .unzip();
+    let own = Commitments {
+        first_id,
+        commitments,
+    };
+    let received = exchange(i, peers, own, &progress.remap(&parties)).await?;
+
+    for (party, commitments) in parties.iter().zip(&received) {
+        ensure!(
+            commitments.commitments.len() == count,
+            "party {} committed to {} nonces, expected {}",
+            party,
+            commitments.commitments.len(),
+            count
+        );
+    }
+    let first_id = received
+        .iter()
+        .map(|c| c.first_id)
+        .max()
+        .unwrap_or(first_id);
+
+    Ok(nonces
+        .into_iter()
+        .enumerate()
+        .map(|(slot, nonces)| Presignature {
+            id: first_id + slot as u64,
+            signers: parties.clone(),
+            nonces,
+            commitments: received.iter().map(|c| c.commitments[slot]).collect(),
+        })
+        .collect())
+}
+
+/// Online phase: signs `message` with a presignature in one round, each signer sending the
+/// others its partial signature. Returns the aggregated signature once it verifies against the
+/// key's public key, an [`InvalidSignature`](crate::sign::InvalidSignature) error if it does
+/// not, or a [`PresignatureMismatch`] if the signers did not all use the same presignature.
+///
+/// The presignature must already be gone from the store, since the partial signature sent
+/// here spends its nonces.
+///
+/// # Arguments
+/// * `id` - Signer's party index at keygen
+/// * `key_share` - Participant's valid key share from DKG
+/// * `peers` - One connection to every signer of the presignature, keyed by keygen index
+/// * `presignature` - This signer's presignature, taken from the store
+/// * `message` - The serialized message bytes to be signed
+/// * `progress` - Receives peer disconnects, by keygen index, and round starts
+pub async fn run_presigned_signing_phase<S>(
+    id: u64,
+    key_share: &KeyShare,
+    peers: Vec<(u16, S)>,
+    presignature: Presignature,
+    message: &[u8],
+    progress: &Progress,
+) -> Result<Signature>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let Presignature {
+        id: presignature,
+        signers,
+        nonces,
+        commitments,
+    } = presignature;
+    let parties = signer_set(key_share, &signers)?;
+    ensure!(
+        parties == signers && commitments.len() == signers.len(),
+        "presignature {} does not match its signer set",
+        presignature
+    );
+    let (i, peers) = peer_positions(id, &parties, peers)?;
+
+    // Partial signatures name signers by keygen index, while the links use positions
+    let signers_list: Vec<_> = parties.iter().copied().zip(commitments).collect();
+    let share = round2::sign::<CsEd25519>(key_share, nonces, message, &signers_list)
+        .map_err(|e| anyhow!("failed to compute partial signature: {}", e))?;
+
+    let own = PartialSignature {
+        presignature,
+        share,
+    };
+    let received = exchange(i, peers, own, &progress.remap(&parties)).await?;
+
+    let highest = received
+        .iter()
+        .map(|p| p.presignature)
+        .max()
+        .unwrap_or(presignature);
+    if received.iter().any(|p| p.presignature != presignature) {
+        return Err(PresignatureMismatch {
+            used: presignature,
+            highest,
+        }
+        .into());
+    }
+
+    let shares: Vec<_> = signers_list
+        .into_iter()
+        .zip(received)
+        .map(|((j, commitments), partial)| (j, commitments, partial.share))
+        .collect();
+    let key_info: &KeyInfo<Ed25519> = key_share.as_ref();
+    let signature = aggregate::aggregate::<CsEd25519>(key_info, &shares, message)
+        .map_err(|e| anyhow!("failed to aggregate partial signatures: {}", e))?;
+
+    verified_signature(key_share, &signature, message)
+}
+
+/// Sends `own` to every peer and waits for one message from each. Returns every signer's
+/// message, this one's included, in the order of their positions.
+async fn exchange<M, S>(i: u16, peers: Vec<(u16, S)>, own: M, progress: &Progress) -> Result<Vec<M>>
+where
+    M: ProtocolMessage + Serialize + DeserializeOwned + Clone + Send + Unpin + 'static,
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let mut received: Vec<Option<M>> = (0..=peers.len()).map(|_| None).collect();
+    let (mut incoming, mut outgoing) = split_peers::<M, S>(u64::from(i), peers, progress);
+
+    outgoing.send(Outgoing::broadcast(own.clone())).await?;
+    received[usize::from(i)] = Some(own);
+
+    while received.iter().any(Option::is_none) {
+        let message = incoming
+            .next()
+            .await
+            .context("a signer closed its connection")??;
+        let slot = received
+            .get_mut(usize::from(message.sender))
+            .with_context(|| format!("message from unknown signer {}", message.sender))?;
+        ensure!(
+            slot.is_none(),
+            "signer {} sent more than one message",
+            message.sender
+        );
+        *slot = Some(message.msg);
+    }
+
+    Ok(received.into_iter().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{keygen, mesh, presign, public_key};
+
+    /// Signs `message` with one presignature per signer of `signers`, in the same order.
+    async fn sign(
+        shares: &[KeyShare],
+        signers: &[u16],
+        presignatures: Vec<Presignature>,
+        message: &[u8],
+    ) -> Vec<Result<Signature>> {
+        let runs: Vec<_> = mesh(signers)
+            .into_iter()
+            .zip(presignatures)
+            .map(|((party, peers), presignature)| {
+                let share = shares[usize::from(party)].clone();
+                let message = message.to_vec();
+                tokio::spawn(async move {
+                    let progress = Progress::default();
+                    run_presigned_signing_phase(
+                        party.into(),
+                        &share,
+                        peers,
+                        presignature,
+                        &message,
+                        &progress,
+                    )
+                    .await
+                })
+            })
+            .collect();
+
+        let mut results = Vec::new();
+        for run in runs {
+            results.push(run.await.unwrap());
+        }
+        results
+    }
+
+    #[tokio::test]
+    async fn presignatures_are_numbered_alike_and_sign_in_one_round() {
+        let shares = keygen(3, 2).await;
+        let public_key = public_key(&shares[0]);
+
+        // The signers disagree on where to start; the highest proposal wins everywhere
+        let first = presign(&shares, &[0, 2], 1, 3).await;
+        assert_eq!(first[0][0].id, 3);
+        let mut stocks = presign(&shares, &[0, 2], 3, 4).await;
+        for stock in &stocks {
+            let ids: Vec<u64> = stock.iter().map(|p| p.id).collect();
+            assert_eq!(ids, vec![4, 5, 6]);
+            assert!(stock.iter().all(|p| p.signers == vec![0, 2]));
+        }
+
+        let slot: Vec<Presignature> = stocks.iter_mut().map(|s| s.remove(1)).collect();
+        let signatures = sign(&shares, &[0, 2], slot, b"hello").await;
+        let signature = signatures[0].as_ref().unwrap();
+        assert_eq!(signatures[1].as_ref().unwrap(), signature);
+        assert!(signature.verify(public_key.as_ref(), b"hello"));
+    }
+
+    #[tokio::test]
+    async fn signers_on_different_presignatures_report_the_highest() {
+        let shares = keygen(2, 2).await;
+        let mut stocks = presign(&shares, &[0, 1], 3, 0).await;
+
+        // Party 0 lost presignature 0 in an earlier attempt that party 1 never saw
+        let slot = vec![stocks[0].remove(1), stocks[1].remove(0)];
+        let results = sign(&shares, &[0, 1], slot, b"hello").await;
+
+        let mismatches: Vec<PresignatureMismatch> = results
+            .into_iter()
+            .map(|r| r.unwrap_err().downcast().unwrap())
+            .collect();
+        assert_eq!(
+            mismatches,
+            vec![
+                PresignatureMismatch {
+                    used: 1,
+                    highest: 1
+                },
+                PresignatureMismatch {
+                    used: 0,
+                    highest: 1
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn presigning_needs_a_valid_signer_set_and_count() {
+        let shares = keygen(2, 2).await;
+        let peers = || Vec::<(u16, tokio::io::DuplexStream)>::new();
+        let progress = Progress::default();
+
+        assert!(
+            run_presign_phase(0, &shares[0], &[0, 1], peers(), 0, 0, &progress)
+                .await
+                .is_err()
+        );
+        assert!(
+            run_presign_phase(0, &shares[0], &[0], peers(), 1, 0, &progress)
+                .await
+                .is_err()
+        );
+    }
+}
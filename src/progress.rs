@@ -0,0 +1,67 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// Something observable that happened in a running MPC session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// A link to `party` is open
+    PeerConnected { party: u16 },
+    /// The link to `party` closed or failed
+    PeerDisconnected { party: u16 },
+    /// This party started sending messages of protocol round `round`, counting from 1
+    RoundStarted { round: u16 },
+}
+
+type Observer = Arc<dyn Fn(ProgressEvent) + Send + Sync>;
+
+/// Where a session reports its progress. Clones share one observer; the default discards
+/// every event.
+#[derive(Clone, Default)]
+pub struct Progress {
+    observer: Option<Observer>,
+}
+
+impl Progress {
+    /// Reports every event to `observer`, which must not block.
+    pub fn new(observer: impl Fn(ProgressEvent) + Send + Sync + 'static) -> Self {
+        Self {
+            observer: Some(Arc::new(observer)),
+        }
+    }
+
+    pub fn emit(&self, event: ProgressEvent) {
+        if let Some(observer) = &self.observer {
+            observer(event);
+        }
+    }
+
+    /// Reports peers by `parties[p]` instead of `p`, for protocols that address parties by
+    /// their position in a subset, like signing.
+    pub fn remap(&self, parties: &[u16]) -> Self {
+        let Some(observer) = self.observer.clone() else {
+            return Self::default();
+        };
+        let parties = parties.to_vec();
+        let index = move |p: u16| parties.get(usize::from(p)).copied().unwrap_or(p);
+
+        Self::new(move |event| {
+            observer(match event {
+                ProgressEvent::PeerConnected { party } => ProgressEvent::PeerConnected {
+                    party: index(party),
+                },
+                ProgressEvent::PeerDisconnected { party } => ProgressEvent::PeerDisconnected {
+                    party: index(party),
+                },
+                other => other,
+            })
+        })
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("observed", &self.observer.is_some())
+            .finish()
+    }
+}
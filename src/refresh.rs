@@ -0,0 +1,559 @@
+use crate::progress::Progress;
+use crate::sign::peer_positions;
+use crate::store::{KeyShare, KeyShareStore, RefreshStore};
+use crate::transport::split_peers;
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use futures::{SinkExt, Stream, StreamExt};
+use givre::generic_ec::curves::Ed25519;
+use givre::generic_ec::{NonZero, Point, Scalar, SecretScalar};
+use givre::key_share::DirtyKeyShare;
+use givre::keygen::key_share::Valid;
+use rand_core::OsRng;
+use round_based::{Incoming, Outgoing, ProtocolMessage};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::{fmt, io};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::info;
+use zeroize::Zeroize;
+
+/// A share after a refresh: the same public key, a new secret share for this party and new
+/// public shares for every party.
+pub struct Refreshed {
+    /// Version the share was committed under
+    pub version: u64,
+    pub key_share: KeyShare,
+}
+
+/// A co-signer holds a share of another version of the key than this party, e.g. because a
+/// refresh committed on some parties only. Shares of different versions cannot sign together;
+/// the next refresh brings the parties back to one version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareVersionMismatch {
+    /// Keygen index of the co-signer
+    pub party: u16,
+    /// Version of this party's share
+    pub version: u64,
+    /// Version of the co-signer's share
+    pub theirs: u64,
+}
+
+impl fmt::Display for ShareVersionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "party {} holds share version {} while this signer holds version {}",
+            self.party, self.theirs, self.version
+        )
+    }
+}
+
+impl std::error::Error for ShareVersionMismatch {}
+
+/// Sends this party's share version over every link and checks that every co-signer holds the
+/// same one, before any signing round runs on the links. Fails with a [`ShareVersionMismatch`]
+/// naming the first co-signer that holds another version.
+///
+/// # Arguments
+/// * `version` - Version of this party's share, see [`RefreshStore::share_version`]
+/// * `peers` - One connection to every co-signer, keyed by keygen index
+pub async fn check_share_versions<S>(version: u64, peers: &mut [(u16, S)]) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // ✅ Every side writes before it reads, so the exchange cannot deadlock
+    let exchanges = peers.iter_mut().map(|(party, stream)| async move {
+        stream.write_u64(version).await?;
+        stream.flush().await?;
+        let theirs = stream
+            .read_u64()
+            .await
+            .with_context(|| format!("party {} closed its connection", party))?;
+        Ok::<_, anyhow::Error>((*party, theirs))
+    });
+
+    for (party, theirs) in futures::future::try_join_all(exchanges).await? {
+        if theirs != version {
+            return Err(ShareVersionMismatch {
+                party,
+                version,
+                theirs,
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Messages of the refresh protocol, one variant per round.
+#[derive(Serialize, Deserialize, Clone)]
+enum RefreshMessage {
+    /// Round 1, broadcast: the party's share versions and the commitments to its polynomial
+    Commit {
+        version: u64,
+        staged: Option<u64>,
+        commitments: Vec<Point<Ed25519>>,
+    },
+    /// Round 2, to one party: the polynomial evaluated at that party's share index
+    Share { delta: SecretScalar<Ed25519> },
+    /// Round 3, broadcast: the new share is staged, under this version and public shares
+    Confirm { version: u64, digest: [u8; 32] },
+}
+
+impl ProtocolMessage for RefreshMessage {
+    fn round(&self) -> u16 {
+        match self {
+            RefreshMessage::Commit { .. } => 0,
+            RefreshMessage::Share { .. } => 1,
+            RefreshMessage::Confirm { .. } => 2,
+        }
+    }
+}
+
+/// Refreshes this party's share of a session in place, keeping its public key. Every party of
+/// the key takes part: each adds to its share the sum of random polynomials that are zero at
+/// zero, one per party, so the shares change while still interpolating to the same secret.
+/// Shares from before the refresh and after it cannot be combined, so an attacker has to
+/// compromise `threshold` parties between two refreshes.
+///
+/// The new share is staged in `store` and committed only once every party has confirmed
+/// staging the same key, which overwrites the old share. A party that missed a commit, e.g.
+/// because it crashed in between, commits its staged share first and is then refreshed with
+/// the others.
+///
+/// # Arguments
+/// * `id` - Party index at keygen, also the node id the share is stored under
+/// * `session` - Key session whose share is refreshed
+/// * `store` - Store holding the share and its versions
+/// * `peers` - One connection to every other party of the key, keyed by keygen index
+/// * `progress` - Receives peer disconnects and round starts
+pub async fn run_refresh_phase<R, S>(
+    id: u64,
+    session: &str,
+    store: &R,
+    peers: Vec<(u16, S)>,
+    progress: &Progress,
+) -> Result<Refreshed>
+where
+    R: KeyShareStore + RefreshStore + ?Sized,
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let mut key_share = store
+        .get(id, session)
+        .await?
+        .with_context(|| format!("no share for session {}", session))?;
+    let mut version = store.share_version(id, session).await?.unwrap_or(0);
+    let staged = store.staged_version(id, session).await?;
+
+    let setup = key_share
+        .vss_setup
+        .clone()
+        .context("only threshold key shares can be refreshed")?;
+    let n = key_share.n();
+    let parties: Vec<u16> = (0..n).collect();
+    let (i, peers) = peer_positions(id, &parties, peers)?;
+
+    // ✅ f(0) = 0, so the shares keep summing to the same secret
+    let coefficients: Vec<SecretScalar<Ed25519>> = (1..setup.min_signers)
+        .map(|_| SecretScalar::random(&mut OsRng))
+        .collect();
+    let commitments: Vec<Point<Ed25519>> = coefficients
+        .iter()
+        .map(|a| Point::generator() * a)
+        .collect();
+
+    let (incoming, mut outgoing) = split_peers::<RefreshMessage, S>(id, peers, progress);
+    let mut rounds = Rounds::new(incoming, i, usize::from(n));
+
+    // Round 1: share versions and commitments
+    outgoing
+        .send(Outgoing::broadcast(RefreshMessage::Commit {
+            version,
+            staged,
+            commitments: commitments.clone(),
+        }))
+        .await?;
+    let mut committed = BTreeMap::from([(i, commitments)]);
+    let mut highest = version;
+    for (j, message) in rounds.next(0).await? {
+        if let RefreshMessage::Commit {
+            version: theirs,
+            commitments,
+            ..
+        } = message
+        {
+            ensure!(
+                commitments.len() + 1 == usize::from(setup.min_signers),
+                "party {} committed to a polynomial of degree {}, expected {}",
+                j,
+                commitments.len(),
+                setup.min_signers - 1
+            );
+            highest = highest.max(theirs);
+            committed.insert(j, commitments);
+        }
+    }
+
+    // Another party committed a refresh this one only staged
+    if version < highest {
+        ensure!(
+            staged == Some(highest),
+            "share is at version {} while other parties are at {}, and version {} is not staged",
+            version,
+            highest,
+            highest
+        );
+        store.commit_share(id, session, highest).await?;
+        key_share = store
+            .get(id, session)
+            .await?
+            .with_context(|| format!("no share for session {}", session))?;
+        version = highest;
+        info!(
+            "[REFRESH] Caught up to share version {} for session {}",
+            version, session
+        );
+    }
+
+    // Round 2: every party's point on this party's polynomial
+    let mut deltas = Vec::with_capacity(usize::from(n));
+    for (j, index) in setup.I.iter().enumerate() {
+        let delta = evaluate(&coefficients, index.as_ref());
+        if j == usize::from(i) {
+            deltas.push(delta);
+        } else {
+            outgoing
+                .send(Outgoing::p2p(j as u16, RefreshMessage::Share { delta }))
+                .await?;
+        }
+    }
+    drop(coefficients);
+
+    let own_index: &Scalar<Ed25519> = setup.I[usize::from(i)].as_ref();
+    for (j, message) in rounds.next(1).await? {
+        if let RefreshMessage::Share { delta } = message {
+            let expected = expected_delta(&committed[&j], own_index);
+            ensure!(
+                Point::generator() * &delta == expected,
+                "party {} sent a share that does not match its commitments",
+                j
+            );
+            deltas.push(delta);
+        }
+    }
+
+    let current: &Scalar<Ed25519> = key_share.x.as_ref();
+    let mut x = current + deltas.iter().sum::<Scalar<Ed25519>>();
+    drop(deltas);
+    let new_x = NonZero::from_scalar(x).map(NonZero::into_secret);
+    x.zeroize();
+    let new_x = new_x.context("refreshed share is zero")?;
+
+    let public_shares = key_share
+        .public_shares
+        .iter()
+        .zip(&setup.I)
+        .map(|(share, index)| {
+            let offset: Point<Ed25519> = committed
+                .values()
+                .map(|commitments| expected_delta(commitments, index.as_ref()))
+                .sum();
+            NonZero::from_point(**share + offset).context("refreshed public share is zero")
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let digest = public_shares_digest(&public_shares);
+
+    let mut key_info = key_share.key_info.clone();
+    key_info.public_shares = public_shares;
+    let refreshed = Valid::validate(DirtyKeyShare {
+        i: key_share.i,
+        key_info,
+        x: new_x,
+    })
+    .map_err(|e| anyhow!("refreshed share is invalid: {}", e.into_error()))?;
+
+    // Round 3: staged everywhere before any party lets go of its old share
+    let version = version + 1;
+    store.stage_share(id, session, version, &refreshed).await?;
+    outgoing
+        .send(Outgoing::broadcast(RefreshMessage::Confirm {
+            version,
+            digest,
+        }))
+        .await?;
+    for (j, message) in rounds.next(2).await? {
+        if let RefreshMessage::Confirm {
+            version: theirs,
+            digest: their_digest,
+        } = message
+        {
+            ensure!(
+                theirs == version && their_digest == digest,
+                "party {} staged a different refresh (version {})",
+                j,
+                theirs
+            );
+        }
+    }
+
+    store.commit_share(id, session, version).await?;
+
+    Ok(Refreshed {
+        version,
+        key_share: refreshed,
+    })
+}
+
+/// `f(x)` for the polynomial with the given coefficients of degree 1 and up, and none at 0.
+fn evaluate(coefficients: &[SecretScalar<Ed25519>], x: &Scalar<Ed25519>) -> SecretScalar<Ed25519> {
+    let mut value = coefficients
+        .iter()
+        .rev()
+        .fold(Scalar::zero(), |acc, a| (acc + a.as_ref()) * x);
+    SecretScalar::new(&mut value)
+}
+
+/// `f(x)·G` of a party's polynomial, from its commitments alone.
+fn expected_delta(commitments: &[Point<Ed25519>], x: &Scalar<Ed25519>) -> Point<Ed25519> {
+    commitments
+        .iter()
+        .rev()
+        .fold(Point::zero(), |acc, c| (acc + c) * x)
+}
+
+/// What parties compare before committing: the same digest means the same refreshed key.
+fn public_shares_digest(public_shares: &[NonZero<Point<Ed25519>>]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for share in public_shares {
+        hasher.update(share.to_bytes(true));
+    }
+    hasher.finalize().into()
+}
+
+/// Incoming messages sorted into rounds; a party that is a round ahead may send its next
+/// message before this one has heard from everyone.
+struct Rounds<In> {
+    incoming: In,
+    early: Vec<Incoming<RefreshMessage>>,
+    i: u16,
+    n: usize,
+}
+
+impl<In> Rounds<In>
+where
+    In: Stream<Item = Result<Incoming<RefreshMessage>, io::Error>> + Unpin,
+{
+    fn new(incoming: In, i: u16, n: usize) -> Self {
+        Self {
+            incoming,
+            early: Vec::new(),
+            i,
+            n,
+        }
+    }
+
+    /// One message of `round` from every other party, in the order of their indices.
+    async fn next(&mut self, round: u16) -> Result<Vec<(u16, RefreshMessage)>> {
+        let mut received = BTreeMap::new();
+        for message in std::mem::take(&mut self.early) {
+            if message.msg.round() == round {
+                self.accept(&mut received, message, round)?;
+            } else {
+                self.early.push(message);
+            }
+        }
+
+        while received.len() + 1 < self.n {
+            let message = self
+                .incoming
+                .next()
+                .await
+                .context("a party closed its connection")??;
+            match message.msg.round() {
+                later if later > round => self.early.push(message),
+                earlier if earlier < round => bail!(
+                    "party {} sent a second message in round {}",
+                    message.sender,
+                    earlier + 1
+                ),
+                _ => self.accept(&mut received, message, round)?,
+            }
+        }
+
+        Ok(received.into_iter().collect())
+    }
+
+    fn accept(
+        &self,
+        received: &mut BTreeMap<u16, RefreshMessage>,
+        message: Incoming<RefreshMessage>,
+        round: u16,
+    ) -> Result<()> {
+        ensure!(
+            message.sender != self.i && usize::from(message.sender) < self.n,
+            "message from unknown party {}",
+            message.sender
+        );
+        ensure!(
+            received.insert(message.sender, message.msg).is_none(),
+            "party {} sent more than one message in round {}",
+            message.sender,
+            round + 1
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use crate::testing::{keygen, mesh, public_key, sign};
+    use std::sync::Arc;
+
+    /// Refreshes every party's share of `session-001` at once, party `i` using `stores[i]`.
+    async fn refresh(stores: &[Arc<MemoryStore>]) -> Vec<Refreshed> {
+        let parties: Vec<u16> = (0..stores.len() as u16).collect();
+        let runs: Vec<_> = mesh(&parties)
+            .into_iter()
+            .map(|(party, peers)| {
+                let store = stores[usize::from(party)].clone();
+                tokio::spawn(async move {
+                    let progress = Progress::default();
+                    run_refresh_phase(party.into(), "session-001", &*store, peers, &progress).await
+                })
+            })
+            .collect();
+
+        let mut refreshed = Vec::new();
+        for run in runs {
+            refreshed.push(run.await.unwrap().unwrap());
+        }
+        refreshed
+    }
+
+    /// One memory store per party, each holding that party's share at version 0.
+    async fn stores(shares: &[KeyShare]) -> Vec<Arc<MemoryStore>> {
+        let mut stores = Vec::new();
+        for (party, share) in shares.iter().enumerate() {
+            let store = Arc::new(MemoryStore::new());
+            store.put(party as u64, "session-001", share).await.unwrap();
+            stores.push(store);
+        }
+        stores
+    }
+
+    fn secret(share: &KeyShare) -> Scalar<Ed25519> {
+        let x: &Scalar<Ed25519> = share.x.as_ref();
+        *x
+    }
+
+    #[tokio::test]
+    async fn refresh_changes_every_share_and_keeps_the_public_key() {
+        let shares = keygen(3, 2).await;
+        let stores = stores(&shares).await;
+
+        let refreshed = refresh(&stores).await;
+        let new_shares: Vec<KeyShare> = refreshed.iter().map(|r| r.key_share.clone()).collect();
+        for (party, refreshed) in refreshed.iter().enumerate() {
+            assert_eq!(refreshed.version, 1);
+            assert_eq!(public_key(&refreshed.key_share), public_key(&shares[0]));
+            assert_ne!(
+                secret(&refreshed.key_share),
+                secret(&shares[party]),
+                "party {} kept its share",
+                party
+            );
+            let stored = stores[party]
+                .get(party as u64, "session-001")
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(secret(&stored), secret(&refreshed.key_share));
+        }
+
+        let signature = sign(&new_shares, b"hello").await;
+        assert!(signature.verify(public_key(&shares[0]).as_ref(), b"hello"));
+    }
+
+    #[tokio::test]
+    async fn a_party_that_missed_the_commit_commits_its_staged_share_first() {
+        let shares = keygen(3, 2).await;
+        let stores = stores(&shares).await;
+        let first = refresh(&stores).await;
+
+        // Party 0 staged version 1 but crashed before committing it
+        let behind = Arc::new(MemoryStore::new());
+        behind.put(0, "session-001", &shares[0]).await.unwrap();
+        behind
+            .stage_share(0, "session-001", 1, &first[0].key_share)
+            .await
+            .unwrap();
+        let stores = vec![behind, stores[1].clone(), stores[2].clone()];
+
+        let second = refresh(&stores).await;
+        for (party, refreshed) in second.iter().enumerate() {
+            assert_eq!(refreshed.version, 2);
+            assert_eq!(
+                stores[party]
+                    .share_version(party as u64, "session-001")
+                    .await
+                    .unwrap(),
+                Some(2)
+            );
+            assert_eq!(public_key(&refreshed.key_share), public_key(&shares[0]));
+        }
+        assert_eq!(
+            stores[0].staged_version(0, "session-001").await.unwrap(),
+            None
+        );
+
+        // The caught-up share combines with the others'
+        let new_shares: Vec<KeyShare> = second.into_iter().map(|r| r.key_share).collect();
+        let signature = sign(&new_shares, b"hello").await;
+        assert!(signature.verify(public_key(&shares[0]).as_ref(), b"hello"));
+    }
+
+    #[tokio::test]
+    async fn signers_of_different_share_versions_are_told_apart() {
+        let mut links = mesh(&[0, 1]);
+        let mut zero = links.remove(&0).unwrap();
+        let mut one = links.remove(&1).unwrap();
+
+        let (same_zero, same_one) = tokio::join!(
+            check_share_versions(1, &mut zero),
+            check_share_versions(1, &mut one)
+        );
+        same_zero.unwrap();
+        same_one.unwrap();
+
+        let (behind, ahead) = tokio::join!(
+            check_share_versions(1, &mut zero),
+            check_share_versions(2, &mut one)
+        );
+        assert_eq!(
+            behind
+                .unwrap_err()
+                .downcast::<ShareVersionMismatch>()
+                .unwrap(),
+            ShareVersionMismatch {
+                party: 1,
+                version: 1,
+                theirs: 2
+            }
+        );
+        assert_eq!(
+            ahead
+                .unwrap_err()
+                .downcast::<ShareVersionMismatch>()
+                .unwrap(),
+            ShareVersionMismatch {
+                party: 0,
+                version: 2,
+                theirs: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use solana_address_lookup_table_interface::state::AddressLookupTable;
+use solana_commitment_config::CommitmentConfig;
+use solana_hash::Hash;
+use solana_message::AddressLookupTableAccount;
+use solana_pubkey::Pubkey;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use solana_rpc_client_api::config::{RpcSendTransactionConfig, RpcSimulateTransactionConfig};
+use solana_signature::Signature;
+use solana_transaction::versioned::VersionedTransaction;
+use solana_transaction_error::TransactionError;
+use solana_transaction_status_client_types::{TransactionConfirmationStatus, TransactionStatus};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// A Solana cluster, by name or RPC URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cluster {
+    Mainnet,
+    Devnet,
+    Testnet,
+    /// A `solana-test-validator` on its default port
+    Localnet,
+    /// Any other RPC endpoint
+    Custom(String),
+}
+
+impl Cluster {
+    /// JSON-RPC endpoint of the cluster.
+    pub fn url(&self) -> &str {
+        match self {
+            Cluster::Mainnet => "https://api.mainnet-beta.solana.com",
+            Cluster::Devnet => "https://api.devnet.solana.com",
+            Cluster::Testnet => "https://api.testnet.solana.com",
+            Cluster::Localnet => "http://127.0.0.1:8899",
+            Cluster::Custom(url) => url,
+        }
+    }
+
+    /// Async RPC client for the cluster, reading at `commitment` unless told otherwise.
+    pub fn client(&self, commitment: CommitmentConfig) -> RpcClient {
+        RpcClient::new_with_commitment(self.url().to_string(), commitment)
+    }
+}
+
+impl FromStr for Cluster {
+    type Err = anyhow::Error;
+
+    /// Accepts `mainnet` (or `mainnet-beta`), `devnet`, `testnet`, `localnet` (or `localhost`),
+    /// or an `http(s)://` URL.
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "mainnet" | "mainnet-beta" => Cluster::Mainnet,
+            "devnet" => Cluster::Devnet,
+            "testnet" => Cluster::Testnet,
+            "localnet" | "localhost" => Cluster::Localnet,
+            _ if s.starts_with("http://") || s.starts_with("https://") => {
+                Cluster::Custom(s.to_string())
+            }
+            _ => bail!(
+                "unknown cluster {:?}; expected mainnet, devnet, testnet, localnet or an RPC URL",
+                s
+            ),
+        })
+    }
+}
+
+impl fmt::Display for Cluster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cluster::Mainnet => write!(f, "mainnet"),
+            Cluster::Devnet => write!(f, "devnet"),
+            Cluster::Testnet => write!(f, "testnet"),
+            Cluster::Localnet => write!(f, "localnet"),
+            Cluster::Custom(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+/// Outcome of simulating a transaction.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Simulation {
+    /// Why the transaction would fail, if it would
+    pub err: Option<String>,
+    pub logs: Vec<String>,
+    pub units_consumed: Option<u64>,
+}
+
+/// The Solana RPC calls the library makes, so it can run against a real cluster or
+/// [`MockRpc`].
+#[async_trait]
+pub trait SolanaRpc: Send + Sync {
+    /// Latest blockhash and the last block height at which it is accepted.
+    async fn latest_blockhash(&self, commitment: CommitmentConfig) -> Result<(Hash, u64)>;
+
+    /// Simulates `transaction`, verifying its signatures.
+    async fn simulate(
+        &self,
+        transaction: &VersionedTransaction,
+        commitment: CommitmentConfig,
+    ) -> Result<Simulation>;
+
+    /// Sends `transaction` once, without preflight checks or retries by the RPC node.
+    async fn send(&self, transaction: &VersionedTransaction) -> Result<Signature>;
+
+    /// Status of a transaction, or `None` if the cluster has not seen it.
+    async fn signature_status(&self, signature: &Signature) -> Result<Option<TransactionStatus>>;
+
+    async fn block_height(&self, commitment: CommitmentConfig) -> Result<u64>;
+
+    /// Asks the cluster's faucet for `lamports`; returns the airdrop transaction's signature.
+    async fn airdrop(&self, pubkey: &Pubkey, lamports: u64) -> Result<Signature>;
+
+    async fn balance(&self, pubkey: &Pubkey) -> Result<u64>;
+
+    /// Addresses stored in the address lookup table at `address`, for compiling v0 messages.
+    async fn lookup_table(&self, address: &Pubkey) -> Result<AddressLookupTableAccount>;
+}
+
+#[async_trait]
+impl SolanaRpc for RpcClient {
+    async fn latest_blockhash(&self, commitment: CommitmentConfig) -> Result<(Hash, u64)> {
+        Ok(self
+            .get_latest_blockhash_with_commitment(commitment)
+            .await?)
+    }
+
+    async fn simulate(
+        &self,
+        transaction: &VersionedTransaction,
+        commitment: CommitmentConfig,
+    ) -> Result<Simulation> {
+        let simulation = self
+            .simulate_transaction_with_config(
+                transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: true,
+                    commitment: Some(commitment),
+                    ..Default::default()
+                },
+            )
+            .await?
+            .value;
+
+        Ok(Simulation {
+            err: simulation.err.map(|e| e.to_string()),
+            logs: simulation.logs.unwrap_or_default(),
+            units_consumed: simulation.units_consumed,
+        })
+    }
+
+    async fn send(&self, transaction: &VersionedTransaction) -> Result<Signature> {
+        let config = RpcSendTransactionConfig {
+            skip_preflight: true,
+            max_retries: Some(0),
+            ..Default::default()
+        };
+        Ok(self
+            .send_transaction_with_config(transaction, config)
+            .await?)
+    }
+
+    async fn signature_status(&self, signature: &Signature) -> Result<Option<TransactionStatus>> {
+        let statuses = self.get_signature_statuses(&[*signature]).await?.value;
+        Ok(statuses.into_iter().next().flatten())
+    }
+
+    async fn block_height(&self, commitment: CommitmentConfig) -> Result<u64> {
+        Ok(self.get_block_height_with_commitment(commitment).await?)
+    }
+
+    async fn airdrop(&self, pubkey: &Pubkey, lamports: u64) -> Result<Signature> {
+        Ok(self.request_airdrop(pubkey, lamports).await?)
+    }
+
+    async fn balance(&self, pubkey: &Pubkey) -> Result<u64> {
+        Ok(self.get_balance(pubkey).await?)
+    }
+
+    async fn lookup_table(&self, address: &Pubkey) -> Result<AddressLookupTableAccount> {
+        let data = self.get_account_data(address).await?;
+        let table = AddressLookupTable::deserialize(&data)
+            .map_err(|e| anyhow!("{} is not an address lookup table: {}", address, e))?;
+        Ok(AddressLookupTableAccount {
+            key: *address,
+            addresses: table.addresses.to_vec(),
+        })
+    }
+}
+
+/// In-memory [`SolanaRpc`] for exercising blockhash handling, submission and airdrops offline.
+///
+/// Every call advances the block height by one. Sent transactions land and are finalized at
+/// once unless their blockhash has expired or they are among the first
+/// [`MockRpc::drop_sends`], and fail on chain if [`MockRpc::transaction_error`] is set;
+/// airdrops credit the balance right away.
+#[derive(Debug, Default)]
+pub struct MockRpc {
+    state: Mutex<MockState>,
+}
+
+#[derive(Debug)]
+struct MockState {
+    block_height: u64,
+    /// Last valid block height of every blockhash handed out
+    blockhashes: HashMap<Hash, u64>,
+    blockhash_lifetime: u64,
+    balances: HashMap<Pubkey, u64>,
+    landed: HashSet<Signature>,
+    /// Landed transactions that failed on chain
+    failed: HashMap<Signature, TransactionError>,
+    sent: Vec<VersionedTransaction>,
+    airdrops: Vec<(Pubkey, u64)>,
+    lookup_tables: HashMap<Pubkey, Vec<Pubkey>>,
+    simulation_error: Option<String>,
+    transaction_error: Option<TransactionError>,
+    dropped_sends: usize,
+    failed_airdrops: usize,
+    next_id: u64,
+}
+
+impl Default for MockState {
+    fn default() -> Self {
+        Self {
+            block_height: 0,
+            blockhashes: HashMap::new(),
+            // Roughly what a real cluster allows
+            blockhash_lifetime: 150,
+            balances: HashMap::new(),
+            landed: HashSet::new(),
+            failed: HashMap::new(),
+            sent: Vec::new(),
+            airdrops: Vec::new(),
+            lookup_tables: HashMap::new(),
+            simulation_error: None,
+            transaction_error: None,
+            dropped_sends: 0,
+            failed_airdrops: 0,
+            next_id: 0,
+        }
+    }
+}
+
+impl MockState {
+    /// Advances the chain by one block and returns the new height.
+    fn tick(&mut self) -> u64 {
+        self.block_height += 1;
+        self.block_height
+    }
+
+    /// A value no earlier call has returned, for synthetic hashes and signatures.
+    fn next_id(&mut self) -> [u8; 8] {
+        self.next_id += 1;
+        self.next_id.to_le_bytes()
+    }
+}
+
+impl MockRpc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Blocks each blockhash stays valid for.
+    pub fn blockhash_lifetime(self, blocks: u64) -> Self {
+        self.state.lock().unwrap().blockhash_lifetime = blocks;
+        self
+    }
+
+    /// Makes every simulation fail with `error`.
+    pub fn simulation_error(self, error: impl Into<String>) -> Self {
+        self.state.lock().unwrap().simulation_error = Some(error.into());
+        self
+    }
+
+    /// Makes every transaction that lands fail on chain with `error`.
+    pub fn transaction_error(self, error: TransactionError) -> Self {
+        self.state.lock().unwrap().transaction_error = Some(error);
+        self
+    }
+
+    /// Accepts but never lands the next `count` sends, as a congested cluster would.
+    pub fn drop_sends(self, count: usize) -> Self {
+        self.state.lock().unwrap().dropped_sends = count;
+        self
+    }
+
+    /// Fails the next `count` airdrop requests, as a rate-limited faucet would.
+    pub fn fail_airdrops(self, count: usize) -> Self {
+        self.state.lock().unwrap().failed_airdrops = count;
+        self
+    }
+
+    /// Serves `table` from [`SolanaRpc::lookup_table`]; v0 transactions referring to any other
+    /// table fail simulation.
+    pub fn lookup_table(self, table: AddressLookupTableAccount) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .lookup_tables
+            .insert(table.key, table.addresses);
+        self
+    }
+
+    /// Every transaction sent so far, in order.
+    pub fn sent(&self) -> Vec<VersionedTransaction> {
+        self.state.lock().unwrap().sent.clone()
+    }
+
+    /// Every airdrop granted so far, in order.
+    pub fn airdrops(&self) -> Vec<(Pubkey, u64)> {
+        self.state.lock().unwrap().airdrops.clone()
+    }
+}
+
+#[async_trait]
+impl SolanaRpc for MockRpc {
+    async fn latest_blockhash(&self, _commitment: CommitmentConfig) -> Result<(Hash, u64)> {
+        let mut state = self.state.lock().unwrap();
+        let height = state.tick();
+        let mut bytes = [0u8; 32];
+        bytes[..8].copy_from_slice(&state.next_id());
+        let blockhash = Hash::new_from_array(bytes);
+        let last_valid = height + state.blockhash_lifetime;
+        state.blockhashes.insert(blockhash, last_valid);
+        Ok((blockhash, last_valid))
+    }
+
+    async fn simulate(
+        &self,
+        transaction: &VersionedTransaction,
+        _commitment: CommitmentConfig,
+    ) -> Result<Simulation> {
+        let mut state = self.state.lock().unwrap();
+        state.tick();
+        let message = &transaction.message;
+        let unknown_table = message
+            .address_table_lookups()
+            .unwrap_or_default()
+            .iter()
+            .any(|lookup| !state.lookup_tables.contains_key(&lookup.account_key));
+        let err = match &state.simulation_error {
+            Some(error) => Some(error.clone()),
+            None if !state.blockhashes.contains_key(message.recent_blockhash()) => {
+                Some("BlockhashNotFound".to_string())
+            }
+            None if unknown_table => Some("AddressLookupTableNotFound".to_string()),
+            None => None,
+        };
+        Ok(Simulation {
+            err,
+            logs: Vec::new(),
+            units_consumed: Some(150),
+        })
+    }
+
+    async fn send(&self, transaction: &VersionedTransaction) -> Result<Signature> {
+        let mut state = self.state.lock().unwrap();
+        let height = state.tick();
+        let signature = *transaction
+            .signatures
+            .first()
+            .ok_or_else(|| anyhow!("transaction has no signatures"))?;
+        state.sent.push(transaction.clone());
+
+        let valid = state
+            .blockhashes
+            .get(transaction.message.recent_blockhash())
+            .is_some_and(|&last_valid| height <= last_valid);
+        if state.dropped_sends > 0 {
+            state.dropped_sends -= 1;
+        } else if valid {
+            state.landed.insert(signature);
+            if let Some(error) = state.transaction_error.clone() {
+                state.failed.insert(signature, error);
+            }
+        }
+        Ok(signature)
+    }
+
+    async fn signature_status(&self, signature: &Signature) -> Result<Option<TransactionStatus>> {
+        let mut state = self.state.lock().unwrap();
+        let slot = state.tick();
+        if !state.landed.contains(signature) {
+            return Ok(None);
+        }
+        let err = state.failed.get(signature).cloned();
+        Ok(Some(TransactionStatus {
+            slot,
+            // Rooted, so every commitment is satisfied
+            confirmations: None,
+            status: err.clone().map_or(Ok(()), Err),
+            err,
+            confirmation_status: Some(TransactionConfirmationStatus::Finalized),
+        }))
+    }
+
+    async fn block_height(&self, _commitment: CommitmentConfig) -> Result<u64> {
+        Ok(self.state.lock().unwrap().tick())
+    }
+
+    async fn airdrop(&self, pubkey: &Pubkey, lamports: u64) -> Result<Signature> {
+        let mut state = self.state.lock().unwrap();
+        state.tick();
+        if state.failed_airdrops > 0 {
+            state.failed_airdrops -= 1;
+            bail!("airdrop request failed: rate limited");
+        }
+
+        *state.balances.entry(*pubkey).or_default() += lamports;
+        state.airdrops.push((*pubkey, lamports));
+        let mut bytes = [0u8; 64];
+        bytes[..8].copy_from_slice(&state.next_id());
+        let signature = Signature::from(bytes);
+        state.landed.insert(signature);
+        Ok(signature)
+    }
+
+    async fn balance(&self, pubkey: &Pubkey) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        state.tick();
+        Ok(state.balances.get(pubkey).copied().unwrap_or_default())
+    }
+
+    async fn lookup_table(&self, address: &Pubkey) -> Result<AddressLookupTableAccount> {
+        let mut state = self.state.lock().unwrap();
+        state.tick();
+        let addresses = state
+            .lookup_tables
+            .get(address)
+            .ok_or_else(|| anyhow!("account {} not found", address))?;
+        Ok(AddressLookupTableAccount {
+            key: *address,
+            addresses: addresses.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clusters_parse_by_name_or_url() {
+        assert_eq!("mainnet-beta".parse::<Cluster>().unwrap(), Cluster::Mainnet);
+        assert_eq!("Devnet".parse::<Cluster>().unwrap(), Cluster::Devnet);
+        assert_eq!("localhost".parse::<Cluster>().unwrap(), Cluster::Localnet);
+        let custom: Cluster = "https://rpc.example.com".parse().unwrap();
+        assert_eq!(custom.url(), "https://rpc.example.com");
+        assert_eq!(custom.to_string(), "https://rpc.example.com");
+        assert!("moonnet".parse::<Cluster>().is_err());
+    }
+
+    #[tokio::test]
+    async fn mock_blockhashes_are_fresh_and_expire_after_their_lifetime() {
+        let rpc = MockRpc::new().blockhash_lifetime(2);
+        let commitment = CommitmentConfig::confirmed();
+
+        let (first, last_valid) = rpc.latest_blockhash(commitment).await.unwrap();
+        let (second, _) = rpc.latest_blockhash(commitment).await.unwrap();
+        assert_ne!(first, second);
+        assert_eq!(last_valid, 1 + 2);
+        // Every call is a block: the first blockhash is valid through this one, and no further
+        assert_eq!(rpc.block_height(commitment).await.unwrap(), last_valid);
+        assert!(rpc.block_height(commitment).await.unwrap() > last_valid);
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{Result, bail, ensure};
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Runs each MPC session as its own task, at most `max_concurrent` at a time.
+/// Sessions submitted beyond the cap wait in FIFO order, up to `max_queued` of them.
+#[derive(Clone)]
+pub struct SessionScheduler {
+    permits: Arc<Semaphore>,
+    pending: Arc<AtomicUsize>,
+    capacity: usize,
+}
+
+impl SessionScheduler {
+    /// # Arguments
+    /// * `max_concurrent` - Sessions allowed to run at once
+    /// * `max_queued` - Sessions allowed to wait for a free slot before new ones are rejected
+    pub fn new(max_concurrent: usize, max_queued: usize) -> Result<Self> {
+        ensure!(
+            max_concurrent > 0,
+            "at least one concurrent session must be allowed"
+        );
+
+        Ok(Self {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            pending: Arc::new(AtomicUsize::new(0)),
+            capacity: max_concurrent + max_queued,
+        })
+    }
+
+    /// Spawns `session`, queueing it if the concurrency cap is reached.
+    /// Fails without spawning when the queue is already full.
+    pub fn spawn<F>(&self, label: &str, session: F) -> Result<JoinHandle<()>>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let pending = self.pending.fetch_add(1, Ordering::SeqCst);
+        if pending >= self.capacity {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            bail!(
+                "session queue is full ({} running or queued), rejecting {}",
+                pending,
+                label
+            );
+        }
+
+        if self.permits.available_permits() == 0 {
+            warn!("All session slots busy; queueing {}", label);
+        }
+
+        let permits = self.permits.clone();
+        let guard = PendingGuard(self.pending.clone());
+        let label = label.to_string();
+
+        Ok(tokio::spawn(async move {
+            let _guard = guard;
+            // Semaphore waiters are served in order, so queued sessions start first-come first-served
+            let Ok(_permit) = permits.acquire_owned().await else {
+                return;
+            };
+            debug!("Session {} started", label);
+            session.await;
+            debug!("Session {} finished", label);
+        }))
+    }
+}
+
+/// Releases a queue position when the session task ends, even if it panicked.
+struct PendingGuard(Arc<AtomicUsize>);
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn sessions_beyond_the_cap_wait_their_turn_in_order() {
+        let scheduler = SessionScheduler::new(1, 8).unwrap();
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let (release, blocked) = oneshot::channel::<()>();
+
+        let first = {
+            let started = started.clone();
+            scheduler
+                .spawn("first", async move {
+                    started.lock().unwrap().push(0);
+                    let _ = blocked.await;
+                })
+                .unwrap()
+        };
+        let queued: Vec<_> = (1..4)
+            .map(|i| {
+                let started = started.clone();
+                scheduler
+                    .spawn(&format!("queued {}", i), async move {
+                        started.lock().unwrap().push(i);
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        tokio::task::yield_now().await;
+        assert_eq!(*started.lock().unwrap(), vec![0]);
+
+        release.send(()).unwrap();
+        first.await.unwrap();
+        for handle in queued {
+            handle.await.unwrap();
+        }
+        assert_eq!(*started.lock().unwrap(), vec![0, 1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn a_full_queue_rejects_new_sessions_until_one_ends() {
+        let scheduler = SessionScheduler::new(1, 1).unwrap();
+        let (release, blocked) = oneshot::channel::<()>();
+
+        let running = scheduler
+            .spawn("running", async move {
+                let _ = blocked.await;
+            })
+            .unwrap();
+        let queued = scheduler.spawn("queued", async {}).unwrap();
+        assert!(scheduler.spawn("rejected", async {}).is_err());
+
+        release.send(()).unwrap();
+        running.await.unwrap();
+        queued.await.unwrap();
+        assert!(scheduler.spawn("accepted", async {}).unwrap().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_panicking_session_gives_its_slot_back() {
+        let scheduler = SessionScheduler::new(1, 0).unwrap();
+        let failed = scheduler.spawn("panics", async { panic!("session failed") });
+        assert!(failed.unwrap().await.is_err());
+
+        assert!(scheduler.spawn("next", async {}).unwrap().await.is_ok());
+    }
+
+    #[test]
+    fn at_least_one_session_must_run() {
+        assert!(SessionScheduler::new(0, 8).is_err());
+    }
+}
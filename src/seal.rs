@@ -0,0 +1,310 @@
+use anyhow::{Context, Result, anyhow, ensure};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand_core::OsRng;
+use std::collections::BTreeMap;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Magic prefix identifying a sealed record.
+const MAGIC: &[u8; 4] = b"IDMS";
+/// Layout version of the sealed record header.
+const FORMAT_V1: u8 = 1;
+const NONCE_LEN: usize = 24;
+/// `MAGIC | format (u8) | key version (u32 BE) | nonce (24 bytes)`
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + NONCE_LEN;
+
+/// Key-encryption keys indexed by version. The highest version seals new records;
+/// older versions are kept only to unseal records that have not been re-wrapped yet.
+pub struct Keyring {
+    keys: BTreeMap<u32, Zeroizing<[u8; 32]>>,
+}
+
+impl Keyring {
+    /// Creates an empty keyring.
+    pub fn new() -> Self {
+        Self {
+            keys: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a raw 32-byte key under `version`.
+    pub fn insert(&mut self, version: u32, key: [u8; 32]) -> Result<()> {
+        ensure!(
+            !self.keys.contains_key(&version),
+            "duplicate key-encryption key version {}",
+            version
+        );
+        self.keys.insert(version, Zeroizing::new(key));
+        Ok(())
+    }
+
+    /// Loads keys from a keyfile with one `<version> <64 hex chars>` entry per line.
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn from_keyfile(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = Zeroizing::new(
+            std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read keyfile {}", path.display()))?,
+        );
+
+        let mut keyring = Self::new();
+        for (line_no, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (version, key_hex) = line.split_once(char::is_whitespace).ok_or_else(|| {
+                anyhow!(
+                    "keyfile line {}: expected `<version> <hex key>`",
+                    line_no + 1
+                )
+            })?;
+            let version: u32 = version
+                .parse()
+                .with_context(|| format!("keyfile line {}: invalid version", line_no + 1))?;
+
+            let mut key = [0u8; 32];
+            hex::decode_to_slice(key_hex.trim(), &mut key).with_context(|| {
+                format!(
+                    "keyfile line {}: key must be 32 hex-encoded bytes",
+                    line_no + 1
+                )
+            })?;
+            keyring.insert(version, key)?;
+        }
+
+        ensure!(
+            !keyring.keys.is_empty(),
+            "keyfile {} holds no keys",
+            path.display()
+        );
+        Ok(keyring)
+    }
+
+    /// Derives a key from `passphrase` with Argon2id and adds it under `version`.
+    ///
+    /// # Arguments
+    /// * `version` - Key version recorded in every record sealed with this key
+    /// * `passphrase` - Operator-supplied secret
+    /// * `salt` - Deployment-specific salt, at least 16 bytes
+    pub fn add_passphrase(&mut self, version: u32, passphrase: &str, salt: &[u8]) -> Result<()> {
+        ensure!(salt.len() >= 16, "KEK salt must be at least 16 bytes");
+
+        let params = Params::new(64 * 1024, 3, 1, Some(32)).map_err(|e| anyhow!("{}", e))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+            .map_err(|e| anyhow!("failed to derive key-encryption key: {}", e))?;
+
+        self.insert(version, *key)
+    }
+
+    /// Version of the key used to seal new records.
+    pub fn current_version(&self) -> Result<u32> {
+        self.keys
+            .keys()
+            .next_back()
+            .copied()
+            .ok_or_else(|| anyhow!("keyring is empty"))
+    }
+
+    fn cipher(&self, version: u32) -> Result<XChaCha20Poly1305> {
+        let key = self
+            .keys
+            .get(&version)
+            .ok_or_else(|| anyhow!("no key-encryption key with version {}", version))?;
+        Ok(XChaCha20Poly1305::new(key.as_ref().into()))
+    }
+}
+
+impl Default for Keyring {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds a keyring from the configured sources, or `None` when neither is set.
+///
+/// # Arguments
+/// * `keyfile` - Path to a keyfile (see [`Keyring::from_keyfile`])
+/// * `passphrase` - Passphrase to derive a key from, together with `salt` and `version`
+pub fn load_keyring(
+    keyfile: Option<&str>,
+    passphrase: Option<&str>,
+    salt: Option<&str>,
+    version: u32,
+) -> Result<Option<Keyring>> {
+    let mut keyring = match keyfile {
+        Some(path) => Keyring::from_keyfile(path)?,
+        None => Keyring::new(),
+    };
+
+    if let Some(passphrase) = passphrase {
+        let salt = salt.ok_or_else(|| anyhow!("a KEK salt is required with a passphrase"))?;
+        keyring.add_passphrase(version, passphrase, salt.as_bytes())?;
+    }
+
+    Ok((!keyring.keys.is_empty()).then_some(keyring))
+}
+
+/// Returns true if `record` carries a sealed-record header.
+pub fn is_sealed(record: &[u8]) -> bool {
+    record.len() > HEADER_LEN && record.starts_with(MAGIC)
+}
+
+/// Key version a sealed record was written with.
+pub fn key_version(record: &[u8]) -> Result<u32> {
+    ensure!(is_sealed(record), "record is not sealed");
+    let format = record[MAGIC.len()];
+    ensure!(
+        format == FORMAT_V1,
+        "unsupported sealed record format {}",
+        format
+    );
+
+    let start = MAGIC.len() + 1;
+    Ok(u32::from_be_bytes(record[start..start + 4].try_into()?))
+}
+
+/// Encrypts `plaintext` under the keyring's current key.
+/// `aad` binds the record to its context (e.g. node and session) without being stored.
+pub fn seal(keyring: &Keyring, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let version = keyring.current_version()?;
+    let cipher = keyring.cipher(version)?;
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut record = Vec::with_capacity(HEADER_LEN + plaintext.len() + 16);
+    record.extend_from_slice(MAGIC);
+    record.push(FORMAT_V1);
+    record.extend_from_slice(&version.to_be_bytes());
+    record.extend_from_slice(&nonce);
+
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: &associated_data(&record, aad),
+            },
+        )
+        .map_err(|_| anyhow!("failed to seal record"))?;
+    record.extend_from_slice(&ciphertext);
+
+    Ok(record)
+}
+
+/// Decrypts a record produced by [`seal`] with the key named in its header.
+pub fn unseal(keyring: &Keyring, record: &[u8], aad: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    let version = key_version(record)?;
+    let cipher = keyring.cipher(version)?;
+    let (header, ciphertext) = record.split_at(HEADER_LEN);
+    let nonce: [u8; NONCE_LEN] = header[HEADER_LEN - NONCE_LEN..].try_into()?;
+    let nonce = XNonce::from(nonce);
+
+    let plaintext = cipher
+        .decrypt(
+            &nonce,
+            Payload {
+                msg: ciphertext,
+                aad: &associated_data(header, aad),
+            },
+        )
+        .map_err(|_| anyhow!("failed to unseal record with key version {}", version))?;
+
+    Ok(Zeroizing::new(plaintext))
+}
+
+/// Re-encrypts a sealed record under the current key.
+/// Returns `None` if the record is already sealed with the current version.
+pub fn rewrap(keyring: &Keyring, record: &[u8], aad: &[u8]) -> Result<Option<Vec<u8>>> {
+    if key_version(record)? == keyring.current_version()? {
+        return Ok(None);
+    }
+    let plaintext = unseal(keyring, record, aad)?;
+    seal(keyring, &plaintext, aad).map(Some)
+}
+
+/// The header is authenticated along with the caller's context so the key version
+/// cannot be altered without detection.
+fn associated_data(header: &[u8], aad: &[u8]) -> Vec<u8> {
+    [&header[..HEADER_LEN], aad].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyring(versions: &[u32]) -> Keyring {
+        let mut keyring = Keyring::new();
+        for &version in versions {
+            keyring.insert(version, [version as u8; 32]).unwrap();
+        }
+        keyring
+    }
+
+    #[test]
+    fn sealed_record_unseals_with_the_same_aad() {
+        let keyring = keyring(&[1]);
+        let record = seal(&keyring, b"key share", b"0/session-001").unwrap();
+
+        assert!(is_sealed(&record));
+        assert_eq!(key_version(&record).unwrap(), 1);
+        assert_eq!(
+            unseal(&keyring, &record, b"0/session-001")
+                .unwrap()
+                .as_slice(),
+            b"key share"
+        );
+    }
+
+    #[test]
+    fn unseal_rejects_tampered_ciphertext() {
+        let keyring = keyring(&[1]);
+        let mut record = seal(&keyring, b"key share", b"0/session-001").unwrap();
+        *record.last_mut().unwrap() ^= 1;
+
+        assert!(unseal(&keyring, &record, b"0/session-001").is_err());
+    }
+
+    #[test]
+    fn unseal_rejects_another_records_aad() {
+        let keyring = keyring(&[1]);
+        let record = seal(&keyring, b"key share", b"0/session-001").unwrap();
+
+        // A share copied over another session's row does not open
+        assert!(unseal(&keyring, &record, b"0/session-002").is_err());
+    }
+
+    #[test]
+    fn unseal_rejects_an_altered_key_version() {
+        let keyring = keyring(&[1, 2]);
+        let mut record = seal(&keyring, b"key share", b"aad").unwrap();
+        record[MAGIC.len() + 4] = 1;
+
+        assert_eq!(key_version(&record).unwrap(), 1);
+        assert!(unseal(&keyring, &record, b"aad").is_err());
+    }
+
+    #[test]
+    fn rewrap_moves_records_to_the_current_key() {
+        let old = keyring(&[1]);
+        let record = seal(&old, b"key share", b"aad").unwrap();
+
+        let rotated = keyring(&[1, 2]);
+        let rewrapped = rewrap(&rotated, &record, b"aad").unwrap().unwrap();
+        assert_eq!(key_version(&rewrapped).unwrap(), 2);
+        assert_eq!(
+            unseal(&rotated, &rewrapped, b"aad").unwrap().as_slice(),
+            b"key share"
+        );
+        assert!(rewrap(&rotated, &rewrapped, b"aad").unwrap().is_none());
+
+        // Once the old key is gone only the re-wrapped record opens
+        let current = keyring(&[2]);
+        assert!(unseal(&current, &record, b"aad").is_err());
+        assert!(unseal(&current, &rewrapped, b"aad").is_ok());
+    }
+}
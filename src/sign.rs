@@ -1,118 +1,386 @@
-use crate::transport::{TcpIncoming, TcpOutgoing};
+use crate::progress::Progress;
+use crate::rpc::SolanaRpc;
+use crate::transport::{TcpOutgoing, split_peers, split_peers_batched};
 
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail, ensure};
 use futures::SinkExt;
-use givre::ciphersuite::{AdditionalEntropy, Ciphersuite, Ed25519 as CsEd25519};
-use givre::generic_ec::{Scalar, curves::Ed25519};
+use givre::ciphersuite::Ed25519 as CsEd25519;
+use givre::generic_ec::curves::Ed25519;
 use givre::key_share::DirtyKeyShare;
 use givre::keygen::key_share::Valid;
 use givre::signing;
-use givre::signing::{aggregate::Signature, full_signing::Msg};
+use givre::signing::{aggregate::Signature as FrostSignature, full_signing::Msg};
 use rand_core::OsRng;
-use round_based::{MpcParty, Outgoing};
+use round_based::{MpcParty, Outgoing, ProtocolMessage};
 use serde::{Deserialize, Serialize};
+use solana_commitment_config::CommitmentConfig;
+use solana_hash::Hash;
 use solana_instruction::Instruction;
-use solana_message::Message;
+use solana_message::{AddressLookupTableAccount, Message, VersionedMessage, v0};
 use solana_program::instruction::AccountMeta;
-use solana_pubkey::Pubkey;
-use solana_rpc_client::rpc_client::RpcClient;
-use std::{convert::TryInto, str::FromStr};
+use solana_pubkey::{Pubkey, pubkey};
+use solana_signature::Signature;
+use std::{fmt, str::FromStr};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
 use tracing::error;
 
 type SigningMsg = Msg<Ed25519>;
 
+/// SPL Memo program, the one memo instructions are built for.
+pub const MEMO_PROGRAM_ID: Pubkey = pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+/// Legacy SPL Memo program, still recognized as a memo.
+pub const MEMO_V1_PROGRAM_ID: Pubkey = pubkey!("Memo1UhkJRfHyvLMcVucJwxXeuD728EqVDDwQDxFMNo");
+
 /// Runs the distributed signing phase using the participant's valid key share.
-/// Returns the `r` and `z` components of the threshold signature for broadcasting.
+/// Returns the aggregated signature once it verifies against the key's public key and
+/// `message_data`, or an [`InvalidSignature`] error if it does not.
 ///
 /// # Arguments
-/// * `id` - Signer ID
+/// * `id` - Signer's party index at keygen
 /// * `valid_shares` - Participant's valid key share from DKG
-/// * `socket` - TCP stream used for signing phase communication
+/// * `signers` - Keygen indices of every party taking part in this signing session
+/// * `peers` - One connection to every other signer, keyed by that signer's keygen index
 /// * `message_data` - The serialized message bytes to be signed
-pub async fn run_signing_phase(
+/// * `progress` - Receives peer disconnects, by keygen index, and round starts
+pub async fn run_signing_phase<S>(
     id: u64,
     valid_shares: Valid<DirtyKeyShare<Ed25519>>,
-    socket: tokio::net::TcpStream,
+    signers: &[u16],
+    peers: Vec<(u16, S)>,
     message_data: Vec<u8>,
-) -> Result<(Vec<u8>, Vec<u8>)> {
-    let std_stream: std::net::TcpStream = socket.into_std()?;
-    std_stream.set_nonblocking(true)?;
-    let std_stream_sign: std::net::TcpStream = std_stream.try_clone()?;
-
-    // Convert standard TCP stream into tokio streams for async communication
-    let reader_stream_sign = TcpStream::from_std(std_stream_sign.try_clone()?)?;
-    let writer_stream_sign = TcpStream::from_std(std_stream_sign)?;
+    progress: &Progress,
+) -> Result<Signature>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let key_share: Valid<DirtyKeyShare<Ed25519>> = valid_shares;
+    let parties_indexes_at_keygen = signer_set(&key_share, signers)?;
+    let (i, peers) = peer_positions(id, &parties_indexes_at_keygen, peers)?;
 
-    // Wrap streams in TcpIncoming/TcpOutgoing to be used by the MPC party
-    let incoming = TcpIncoming::<SigningMsg>::new(reader_stream_sign, id);
-    let outgoing = TcpOutgoing::<SigningMsg>::new(writer_stream_sign);
+    // Wrap the peer connections in TcpIncoming/TcpOutgoing to be used by the MPC party
+    let progress = progress.remap(&parties_indexes_at_keygen);
+    let (incoming, outgoing) = split_peers::<SigningMsg, _>(u64::from(i), peers, &progress);
 
     // Create the MPC party for threshold signing
     let party = MpcParty::connected((incoming, outgoing));
-    let i = id as u16;
-
-    // TODO: update this dynamically based on the number of signers
-    let parties_indexes_at_keygen: [u16; 2] = [0, 1];
-    let key_share: Valid<DirtyKeyShare<Ed25519>> = valid_shares;
 
     // Distributed signing
     let mut rng = OsRng;
-    let signature: Signature<CsEd25519> = match signing::<CsEd25519>(
-        i,
-        &key_share,
-        &parties_indexes_at_keygen,
-        &message_data,
-    )
-    .sign(&mut rng, party)
-    .await
-    {
-        Ok(sig) => sig,
-        Err(e) => {
-            error!("Threshold signing failed: {:?}", e);
-            return Err(e.into());
-        }
-    };
+    let signature: FrostSignature<CsEd25519> =
+        match signing::<CsEd25519>(i, &key_share, &parties_indexes_at_keygen, &message_data)
+            .sign(&mut rng, party)
+            .await
+        {
+            Ok(sig) => sig,
+            Err(e) => {
+                error!("Threshold signing failed: {:?}", e);
+                return Err(e.into());
+            }
+        };
+
+    verified_signature(&key_share, &signature, &message_data)
+}
+
+/// Signs every message with the same key share and signer set over one connection per
+/// co-signer. The protocol runs for all messages at once, their rounds multiplexed on the
+/// connections, and the signatures come back in the order of `messages`. Any failure fails
+/// the whole batch.
+///
+/// # Arguments
+/// * `id` - Signer's party index at keygen
+/// * `valid_shares` - Participant's valid key share from DKG
+/// * `signers` - Keygen indices of every party taking part in this signing session
+/// * `peers` - One connection to every other signer, keyed by that signer's keygen index
+/// * `messages` - The serialized messages to be signed, in the same order on every signer
+/// * `progress` - Receives peer disconnects, by keygen index, and round starts
+pub async fn run_batch_signing_phase<S>(
+    id: u64,
+    valid_shares: Valid<DirtyKeyShare<Ed25519>>,
+    signers: &[u16],
+    peers: Vec<(u16, S)>,
+    messages: Vec<Vec<u8>>,
+    progress: &Progress,
+) -> Result<Vec<Signature>>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    ensure!(!messages.is_empty(), "no messages to sign");
+    let key_share = &valid_shares;
+    let parties_indexes_at_keygen = signer_set(key_share, signers)?;
+    let parties = parties_indexes_at_keygen.as_slice();
+    let (i, peers) = peer_positions(id, parties, peers)?;
+
+    let progress = progress.remap(parties);
+    let links =
+        split_peers_batched::<SigningMsg, _>(u64::from(i), peers, messages.len(), &progress);
+
+    let sessions =
+        links
+            .into_iter()
+            .zip(&messages)
+            .enumerate()
+            .map(|(index, (link, message))| async move {
+                let party = MpcParty::connected(link);
+                let mut rng = OsRng;
+                let signature = signing::<CsEd25519>(i, key_share, parties, message)
+                    .sign(&mut rng, party)
+                    .await
+                    .map_err(|e| {
+                        error!("Threshold signing of batch entry {} failed: {:?}", index, e);
+                        anyhow!("batch entry {}: {}", index, e)
+                    })?;
+                verified_signature(key_share, &signature, message)
+            });
+    futures::future::try_join_all(sessions).await
+}
 
-    // Extract r and z scalars from the signature
-    let r_bytes = signature.r.to_bytes();
-    let z_bytes_generic: <CsEd25519 as Ciphersuite>::ScalarBytes =
-        <Scalar<Ed25519> as AdditionalEntropy<CsEd25519>>::to_bytes(&signature.z);
-    let z_bytes: [u8; 32] = z_bytes_generic.as_ref().try_into().unwrap();
+/// This signer's position in `parties` and every peer connection keyed by its position,
+/// since givre addresses signers by their position in the signer list, not by keygen index.
+pub(crate) fn peer_positions<S>(
+    id: u64,
+    parties: &[u16],
+    peers: Vec<(u16, S)>,
+) -> Result<(u16, Vec<(u16, S)>)> {
+    let i = signer_position(parties, id as u16)?;
+    let peers = peers
+        .into_iter()
+        .map(|(peer, stream)| {
+            ensure!(
+                peer != id as u16,
+                "connection to self in signer {} peer list",
+                id
+            );
+            Ok((signer_position(parties, peer)?, stream))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(
+        peers.len() + 1 == parties.len(),
+        "expected connections to {} co-signers, got {}",
+        parties.len() - 1,
+        peers.len()
+    );
+    Ok((i, peers))
+}
 
-    Ok((r_bytes.as_ref().to_vec(), z_bytes.to_vec()))
+/// Converts an aggregated signature to Solana's form after checking it against the key's
+/// public key and `message`.
+pub(crate) fn verified_signature(
+    key_share: &Valid<DirtyKeyShare<Ed25519>>,
+    signature: &FrostSignature<CsEd25519>,
+    message: &[u8],
+) -> Result<Signature> {
+    // ✅ Solana checks R || z against the compressed public key; do the same before returning
+    let mut bytes = [0u8; 64];
+    signature.write_to_slice(&mut bytes);
+    let signature = Signature::from(bytes);
+
+    let public_key: [u8; 32] = key_share
+        .shared_public_key()
+        .to_bytes(true)
+        .as_ref()
+        .try_into()?;
+    if !signature.verify(&public_key, message) {
+        let invalid = InvalidSignature {
+            signature,
+            public_key: Pubkey::new_from_array(public_key),
+        };
+        error!("{}", invalid);
+        return Err(invalid.into());
+    }
+
+    Ok(signature)
+}
+
+/// An aggregated signature that does not verify against the key's public key and the signed
+/// message, e.g. because signers were given different messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSignature {
+    pub signature: Signature,
+    pub public_key: Pubkey,
 }
 
-/// Generates a Solana transfer message to be signed.
+impl fmt::Display for InvalidSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "aggregated signature {} does not verify against public key {}",
+            self.signature, self.public_key
+        )
+    }
+}
+
+impl std::error::Error for InvalidSignature {}
+
+/// Validates a signer set against the key share and returns it in canonical (sorted) order,
+/// so every signer derives the same positions regardless of how the set was supplied.
+pub fn signer_set(key_share: &Valid<DirtyKeyShare<Ed25519>>, signers: &[u16]) -> Result<Vec<u16>> {
+    let n = key_share.n();
+    let threshold = key_share.min_signers();
+
+    let mut sorted = signers.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    if sorted.len() != signers.len() {
+        bail!("signer set {:?} contains duplicate indices", signers);
+    }
+    if let Some(out_of_range) = sorted.iter().find(|&&j| j >= n) {
+        bail!("signer {} is out of range for n = {}", out_of_range, n);
+    }
+    if sorted.len() != usize::from(threshold) {
+        bail!(
+            "expected exactly {} signers for a {}-of-{} key, got {}",
+            threshold,
+            threshold,
+            n,
+            sorted.len()
+        );
+    }
+
+    Ok(sorted)
+}
+
+/// Position of keygen index `party` within a canonical signer set.
+fn signer_position(signers: &[u16], party: u16) -> Result<u16> {
+    signers
+        .iter()
+        .position(|&j| j == party)
+        .map(|pos| pos as u16)
+        .ok_or_else(|| anyhow!("party {} is not in the signer set {:?}", party, signers))
+}
+
+/// Generates a Solana transfer message to be signed, with a confirmed recent blockhash.
 ///
 /// # Arguments
+/// * `rpc` - RPC of the cluster the transfer is for
 /// * `from_address` - Sender's Solana address
 /// * `to_address` - Receiver's Solana address
 /// * `lamports` - Amount to transfer in lamports
-pub fn create_transfer_message(
+pub async fn create_transfer_message(
+    rpc: &dyn SolanaRpc,
     from_address: &str,
     to_address: &str,
     lamports: u64,
 ) -> Result<Message> {
-    const SYSTEM_PROGRAM_ID: &str = "11111111111111111111111111111111";
-    let rpc = RpcClient::new("https://api.devnet.solana.com".to_string());
+    let mut message = transfer_message(from_address, to_address, lamports)?;
+    let (blockhash, _) = rpc.latest_blockhash(CommitmentConfig::confirmed()).await?;
+    message.recent_blockhash = blockhash;
+
+    Ok(message)
+}
 
+/// Builds a System Program transfer paid for by `from_address`, without a recent blockhash.
+/// [`crate::transaction::submit_transaction`] sets the blockhash before signing.
+///
+/// # Arguments
+/// * `from_address` - Sender's Solana address, also the fee payer
+/// * `to_address` - Receiver's Solana address
+/// * `lamports` - Amount to transfer in lamports
+pub fn transfer_message(from_address: &str, to_address: &str, lamports: u64) -> Result<Message> {
     let from = Pubkey::from_str(from_address)?;
     let to = Pubkey::from_str(to_address)?;
+    let instruction = transfer_instruction(&from, &to, lamports)?;
+
+    Ok(Message::new(&[instruction], Some(&from)))
+}
+
+/// Generates the same transfer as [`create_transfer_message`] as a v0 message, compiling its
+/// accounts against the given address lookup tables.
+///
+/// # Arguments
+/// * `rpc` - RPC of the cluster the transfer is for
+/// * `from_address` - Sender's Solana address, also the fee payer
+/// * `to_address` - Receiver's Solana address
+/// * `lamports` - Amount to transfer in lamports
+/// * `lookup_tables` - Addresses of the lookup tables to load accounts from
+pub async fn create_transfer_message_v0(
+    rpc: &dyn SolanaRpc,
+    from_address: &str,
+    to_address: &str,
+    lamports: u64,
+    lookup_tables: &[Pubkey],
+) -> Result<VersionedMessage> {
+    let from = Pubkey::from_str(from_address)?;
+    let to = Pubkey::from_str(to_address)?;
+    let instruction = transfer_instruction(&from, &to, lamports)?;
+
+    let tables = resolve_lookup_tables(rpc, lookup_tables).await?;
+    let (blockhash, _) = rpc.latest_blockhash(CommitmentConfig::confirmed()).await?;
+
+    v0_message(&from, &[instruction], &tables, blockhash)
+}
+
+fn transfer_instruction(from: &Pubkey, to: &Pubkey, lamports: u64) -> Result<Instruction> {
+    const SYSTEM_PROGRAM_ID: &str = "11111111111111111111111111111111";
 
     let mut data = vec![];
     data.extend_from_slice(&2u32.to_le_bytes());
     data.extend_from_slice(&lamports.to_le_bytes());
 
-    let instruction = Instruction {
+    Ok(Instruction {
         program_id: Pubkey::from_str(SYSTEM_PROGRAM_ID)?,
-        accounts: vec![AccountMeta::new(from, true), AccountMeta::new(to, false)],
+        accounts: vec![AccountMeta::new(*from, true), AccountMeta::new(*to, false)],
         data,
-    };
+    })
+}
+
+/// Memo instruction carrying `memo`, which the memo program checks is signed by `signer`.
+pub fn memo_instruction(memo: &str, signer: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: MEMO_PROGRAM_ID,
+        accounts: vec![AccountMeta::new_readonly(*signer, true)],
+        data: memo.as_bytes().to_vec(),
+    }
+}
 
-    let mut message = Message::new(&[instruction], Some(&from));
-    message.recent_blockhash = rpc.get_latest_blockhash()?;
+/// Compiles `instructions` into a v0 message. Accounts found in `lookup_tables` are loaded
+/// from them instead of being listed in the message, except signers and invoked programs,
+/// which must stay static.
+///
+/// # Arguments
+/// * `payer` - Fee payer; the MPC-controlled key
+/// * `instructions` - Instructions in execution order
+/// * `lookup_tables` - Resolved lookup tables, e.g. from [`resolve_lookup_tables`]
+/// * `recent_blockhash` - Blockhash the message is valid under
+pub fn v0_message(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    lookup_tables: &[AddressLookupTableAccount],
+    recent_blockhash: Hash,
+) -> Result<VersionedMessage> {
+    let message = v0::Message::try_compile(payer, instructions, lookup_tables, recent_blockhash)?;
+    Ok(VersionedMessage::V0(message))
+}
 
+/// Fetches the addresses stored in each lookup table, in the given order.
+///
+/// # Arguments
+/// * `rpc` - RPC of the cluster the tables live on
+/// * `addresses` - Lookup table accounts
+pub async fn resolve_lookup_tables(
+    rpc: &dyn SolanaRpc,
+    addresses: &[Pubkey],
+) -> Result<Vec<AddressLookupTableAccount>> {
+    let mut tables = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        tables.push(rpc.lookup_table(address).await?);
+    }
+    Ok(tables)
+}
+
+/// Reads a serialized legacy or v0 message, as produced by `VersionedMessage::serialize`.
+/// Only the exact serialization is accepted, so the bytes are the ones that get signed.
+///
+/// # Arguments
+/// * `bytes` - Serialized message; v0 messages start with the `0x80` version prefix
+pub fn parse_message(bytes: &[u8]) -> Result<VersionedMessage> {
+    let message: VersionedMessage = bincode::deserialize(bytes)?;
+    message.sanitize()?;
+    ensure!(
+        message.serialize() == bytes,
+        "message has trailing or non-canonical bytes"
+    );
     Ok(message)
 }
 
@@ -121,6 +389,13 @@ pub struct MessageToSign {
     pub data: Vec<u8>,
 }
 
+/// A one-shot message outside any protocol, so it counts as the first round.
+impl ProtocolMessage for MessageToSign {
+    fn round(&self) -> u16 {
+        0
+    }
+}
+
 /// Sends a serialized Solana message to another server for coordinated signing.
 ///
 /// # Arguments
@@ -131,7 +406,8 @@ pub async fn send_message_to_other_server(
     message: Message,
 ) -> Result<()> {
     let writer_stream_send = TcpStream::from_std(std_stream_send)?;
-    let mut outgoing_send = TcpOutgoing::<MessageToSign>::new(writer_stream_send);
+    let mut outgoing_send =
+        TcpOutgoing::<MessageToSign>::new(vec![(1, writer_stream_send)], &Progress::default());
 
     let message_data: Vec<u8> = message.serialize();
     let signing_msg = MessageToSign {
@@ -146,3 +422,267 @@ pub async fn send_message_to_other_server(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::MockRpc;
+    use crate::testing::{keygen, mesh, public_key, sign, sign_each};
+
+    const FROM: &str = "4Nd1mBQtrMJVYVfKf2PJy9NZUZdTAsp7D4xWLs4gDB4T";
+    const TO: &str = "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin";
+
+    #[tokio::test]
+    async fn any_threshold_of_the_parties_signs_in_any_order() {
+        let shares = keygen(3, 2).await;
+
+        let mut signatures = Vec::new();
+        for signers in [[0, 1], [0, 2], [2, 1]] {
+            let results = sign_each(&shares, &signers, &[b"hello", b"hello"]).await;
+            let signature = *results[0].as_ref().unwrap();
+            assert_eq!(results[1].as_ref().unwrap(), &signature);
+            signatures.push(signature);
+        }
+        // Fresh nonces every session, so no two sessions give the same signature
+        assert_ne!(signatures[0], signatures[1]);
+    }
+
+    #[tokio::test]
+    async fn signatures_verify_under_the_shared_key_for_their_message_only() {
+        let shares = keygen(3, 2).await;
+        let public_key = public_key(&shares[2]);
+
+        let signature = sign(&shares, b"hello").await;
+        assert!(signature.verify(public_key.as_ref(), b"hello"));
+        assert!(!signature.verify(public_key.as_ref(), b"other"));
+    }
+
+    #[tokio::test]
+    async fn signers_given_different_messages_get_no_signature() {
+        let shares = keygen(2, 2).await;
+
+        let results = sign_each(&shares, &[0, 1], &[b"hello", b"other"]).await;
+        for result in results {
+            let error = result.unwrap_err();
+            // Caught by the protocol or by the final check, but never returned as a signature
+            if let Some(invalid) = error.downcast_ref::<InvalidSignature>() {
+                assert_eq!(
+                    invalid.public_key.to_bytes(),
+                    public_key(&shares[0]).to_bytes()
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn verified_signature_rejects_a_signature_over_another_message() {
+        let shares = keygen(2, 2).await;
+        let signature = sign(&shares, b"hello").await;
+
+        let bytes: [u8; 64] = signature.into();
+        let frost = FrostSignature::<CsEd25519>::read_from_slice(&bytes).unwrap();
+        assert_eq!(
+            verified_signature(&shares[0], &frost, b"hello").unwrap(),
+            signature
+        );
+        let error = verified_signature(&shares[0], &frost, b"other").unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<InvalidSignature>().unwrap().signature,
+            signature
+        );
+    }
+
+    #[tokio::test]
+    async fn signer_sets_must_name_exactly_threshold_distinct_parties() {
+        let shares = keygen(3, 2).await;
+
+        assert_eq!(signer_set(&shares[0], &[2, 0]).unwrap(), vec![0, 2]);
+        assert!(signer_set(&shares[0], &[0]).is_err());
+        assert!(signer_set(&shares[0], &[0, 1, 2]).is_err());
+        assert!(signer_set(&shares[0], &[1, 1]).is_err());
+        assert!(signer_set(&shares[0], &[0, 3]).is_err());
+    }
+
+    #[test]
+    fn peers_are_keyed_by_their_position_in_the_signer_set() {
+        assert_eq!(
+            peer_positions(2, &[0, 2], vec![(0, "to 0")]).unwrap(),
+            (1, vec![(0, "to 0")])
+        );
+        assert_eq!(
+            peer_positions(0, &[0, 2], vec![(2, "to 2")]).unwrap(),
+            (0, vec![(1, "to 2")])
+        );
+
+        // Not a signer, a link to a non-signer or to itself, or a missing link
+        assert!(peer_positions(1, &[0, 2], vec![(0, "to 0")]).is_err());
+        assert!(peer_positions(0, &[0, 2], vec![(1, "to 1")]).is_err());
+        assert!(peer_positions(0, &[0, 2], vec![(0, "to 0")]).is_err());
+        assert!(peer_positions(0, &[0, 2], Vec::<(u16, &str)>::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn transfer_message_is_built_under_a_fresh_blockhash() {
+        let rpc = MockRpc::new();
+
+        let message = create_transfer_message(&rpc, FROM, TO, 1_000)
+            .await
+            .unwrap();
+
+        assert_ne!(message.recent_blockhash, Hash::default());
+        assert_eq!(message.header.num_required_signatures, 1);
+        assert_eq!(message.account_keys[0].to_string(), FROM);
+        assert_eq!(message.account_keys[1].to_string(), TO);
+    }
+
+    #[tokio::test]
+    async fn v0_transfer_loads_the_recipient_from_the_lookup_table() {
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![Pubkey::new_unique(), Pubkey::from_str(TO).unwrap()],
+        };
+        let rpc = MockRpc::new().lookup_table(table.clone());
+
+        let message = create_transfer_message_v0(&rpc, FROM, TO, 1_000, &[table.key])
+            .await
+            .unwrap();
+
+        let VersionedMessage::V0(message) = message else {
+            panic!("expected a v0 message");
+        };
+        // Payer and the System Program stay static; the recipient comes from the table
+        assert_eq!(message.account_keys.len(), 2);
+        assert_eq!(message.account_keys[0].to_string(), FROM);
+        assert_eq!(message.address_table_lookups.len(), 1);
+        assert_eq!(message.address_table_lookups[0].account_key, table.key);
+        assert_eq!(message.address_table_lookups[0].writable_indexes, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn lookup_tables_resolve_in_order_and_unknown_ones_fail() {
+        let first = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![Pubkey::new_unique()],
+        };
+        let second = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        };
+        let rpc = MockRpc::new()
+            .lookup_table(first.clone())
+            .lookup_table(second.clone());
+
+        let tables = resolve_lookup_tables(&rpc, &[second.key, first.key])
+            .await
+            .unwrap();
+        assert_eq!(tables, vec![second.clone(), first.clone()]);
+
+        let missing = resolve_lookup_tables(&rpc, &[first.key, Pubkey::new_unique()]).await;
+        assert!(missing.is_err());
+        assert!(
+            create_transfer_message_v0(&rpc, FROM, TO, 1_000, &[Pubkey::new_unique()])
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn parse_message_reads_legacy_and_v0_messages_exactly_as_serialized() {
+        let legacy = VersionedMessage::Legacy(transfer_message(FROM, TO, 1_000).unwrap());
+        let from = Pubkey::from_str(FROM).unwrap();
+        let to = Pubkey::from_str(TO).unwrap();
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![to],
+        };
+        let instruction = transfer_instruction(&from, &to, 1_000).unwrap();
+        let v0 = v0_message(&from, &[instruction], &[table], Hash::new_unique()).unwrap();
+
+        for message in [legacy, v0] {
+            let bytes = message.serialize();
+            assert_eq!(parse_message(&bytes).unwrap(), message);
+
+            let mut trailing = bytes.clone();
+            trailing.push(0);
+            assert!(parse_message(&trailing).is_err());
+            assert!(parse_message(&bytes[..bytes.len() - 1]).is_err());
+        }
+        assert!(parse_message(b"hello").is_err());
+    }
+
+    #[tokio::test]
+    async fn threshold_signature_over_a_v0_message_verifies() {
+        let shares = keygen(2, 2).await;
+        let payer = public_key(&shares[0]);
+        let to = Pubkey::from_str(TO).unwrap();
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![payer, to],
+        };
+        let instruction = transfer_instruction(&payer, &to, 1_000).unwrap();
+        let message = v0_message(&payer, &[instruction], &[table], Hash::new_unique()).unwrap();
+
+        // The payer signs, so it stays static even though the table lists it
+        assert_eq!(message.static_account_keys()[0], payer);
+        let bytes = message.serialize();
+        assert_eq!(bytes[0], 0x80);
+        let signature = sign(&shares, &bytes).await;
+        assert!(signature.verify(payer.as_ref(), &bytes));
+    }
+
+    #[tokio::test]
+    async fn batch_signatures_come_back_in_the_order_of_the_messages() {
+        let shares = keygen(3, 2).await;
+        let public_key = public_key(&shares[0]);
+        let messages: Vec<Vec<u8>> = (0..5)
+            .map(|i| format!("message {i}").into_bytes())
+            .collect();
+
+        let runs: Vec<_> = mesh(&[0, 2])
+            .into_iter()
+            .map(|(party, peers)| {
+                let share = shares[usize::from(party)].clone();
+                let messages = messages.clone();
+                tokio::spawn(async move {
+                    let progress = Progress::default();
+                    run_batch_signing_phase(
+                        party.into(),
+                        share,
+                        &[0, 2],
+                        peers,
+                        messages,
+                        &progress,
+                    )
+                    .await
+                })
+            })
+            .collect();
+        let mut batches = Vec::new();
+        for run in runs {
+            batches.push(run.await.unwrap().unwrap());
+        }
+
+        assert_eq!(batches[0], batches[1]);
+        assert_eq!(batches[0].len(), messages.len());
+        for (signature, message) in batches[0].iter().zip(&messages) {
+            assert!(signature.verify(public_key.as_ref(), message));
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_batch_is_refused() {
+        let shares = keygen(2, 2).await;
+        let peers: Vec<(u16, tokio::io::DuplexStream)> = Vec::new();
+
+        let result = run_batch_signing_phase(
+            0,
+            shares[0].clone(),
+            &[0, 1],
+            peers,
+            Vec::new(),
+            &Progress::default(),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+}
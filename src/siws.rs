@@ -0,0 +1,452 @@
+use anyhow::{Context, Result, bail, ensure};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rand_core::{OsRng, RngCore};
+use solana_pubkey::Pubkey;
+use solana_signature::Signature;
+use std::str::FromStr;
+
+const HEADER: &str = " wants you to sign in with your Solana account:";
+const RESOURCES: &str = "Resources:";
+
+/// Optional fields, in the order they are written.
+const FIELDS: [&str; 8] = [
+    "URI",
+    "Version",
+    "Chain ID",
+    "Nonce",
+    "Issued At",
+    "Expiration Time",
+    "Not Before",
+    "Request ID",
+];
+
+/// A Sign-In With Solana message: the text a wallet signs to log in to a dApp. It is laid out
+/// like the wallet standard's `solana:signIn` messages, so dApps verify it with their usual
+/// tools, and is signed as is rather than as an off-chain message.
+///
+/// Timestamps are kept as the RFC 3339 strings that were written, so a parsed message renders
+/// back to exactly the text that was signed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignInMessage {
+    /// Host of the dApp asking for the sign-in, e.g. `example.com`
+    pub domain: String,
+    /// Account signing in; the session key when an MPC wallet signs
+    pub address: Pubkey,
+    /// One line shown to the user
+    pub statement: Option<String>,
+    pub uri: Option<String>,
+    pub version: Option<String>,
+    /// Cluster the sign-in is for, e.g. `mainnet` or `devnet`
+    pub chain_id: Option<String>,
+    /// Value the dApp issued for this sign-in alone, so a signed message cannot be replayed
+    pub nonce: Option<String>,
+    pub issued_at: Option<String>,
+    pub expiration_time: Option<String>,
+    pub not_before: Option<String>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+impl SignInMessage {
+    /// Version 1 sign-in to `domain` by `address`, with a random nonce and issued now.
+    pub fn new(domain: impl Into<String>, address: Pubkey) -> Self {
+        let mut nonce = [0u8; 16];
+        OsRng.fill_bytes(&mut nonce);
+
+        Self {
+            domain: domain.into(),
+            address,
+            statement: None,
+            uri: None,
+            version: Some("1".into()),
+            chain_id: None,
+            nonce: Some(hex::encode(nonce)),
+            issued_at: Some(timestamp(Utc::now())),
+            expiration_time: None,
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        }
+    }
+
+    pub fn statement(mut self, statement: impl Into<String>) -> Self {
+        self.statement = Some(statement.into());
+        self
+    }
+
+    pub fn uri(mut self, uri: impl Into<String>) -> Self {
+        self.uri = Some(uri.into());
+        self
+    }
+
+    pub fn chain_id(mut self, chain_id: impl Into<String>) -> Self {
+        self.chain_id = Some(chain_id.into());
+        self
+    }
+
+    /// Replaces the random nonce with one the dApp issued.
+    pub fn nonce(mut self, nonce: impl Into<String>) -> Self {
+        self.nonce = Some(nonce.into());
+        self
+    }
+
+    pub fn issued_at(mut self, time: DateTime<Utc>) -> Self {
+        self.issued_at = Some(timestamp(time));
+        self
+    }
+
+    pub fn expiration_time(mut self, time: DateTime<Utc>) -> Self {
+        self.expiration_time = Some(timestamp(time));
+        self
+    }
+
+    pub fn not_before(mut self, time: DateTime<Utc>) -> Self {
+        self.not_before = Some(timestamp(time));
+        self
+    }
+
+    pub fn request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    pub fn resource(mut self, resource: impl Into<String>) -> Self {
+        self.resources.push(resource.into());
+        self
+    }
+
+    /// The text to sign; fails if a field would break the layout, e.g. by holding a newline.
+    pub fn text(&self) -> Result<String> {
+        self.validate()?;
+
+        let mut text = format!("{}{}\n{}", self.domain, HEADER, self.address);
+        if let Some(statement) = &self.statement {
+            text.push_str("\n\n");
+            text.push_str(statement);
+        }
+
+        let mut lines: Vec<String> = FIELDS
+            .iter()
+            .zip(self.fields())
+            .filter_map(|(name, value)| value.map(|value| format!("{}: {}", name, value)))
+            .collect();
+        if !self.resources.is_empty() {
+            lines.push(RESOURCES.into());
+            lines.extend(self.resources.iter().map(|r| format!("- {}", r)));
+        }
+        if !lines.is_empty() {
+            text.push_str("\n\n");
+            text.push_str(&lines.join("\n"));
+        }
+        Ok(text)
+    }
+
+    /// Parses the text of a sign-in message, which must be laid out exactly as [`Self::text`]
+    /// writes it.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.split('\n');
+        let domain = lines
+            .next()
+            .and_then(|line| line.strip_suffix(HEADER))
+            .context("Not a sign-in message")?;
+        let address = lines.next().context("Sign-in message has no address")?;
+        let address = Pubkey::from_str(address)
+            .with_context(|| format!("Sign-in address `{}` is not base58", address))?;
+        let mut message = Self {
+            domain: domain.into(),
+            address,
+            statement: None,
+            uri: None,
+            version: None,
+            chain_id: None,
+            nonce: None,
+            issued_at: None,
+            expiration_time: None,
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        };
+
+        // Then a blank line before the statement, and another before the fields
+        let rest: Vec<&str> = lines.collect();
+        let mut rest = rest.as_slice();
+        if let ["", line, tail @ ..] = rest
+            && !is_field(line)
+        {
+            message.statement = Some(line.to_string());
+            rest = tail;
+        }
+        if let ["", tail @ ..] = rest {
+            rest = tail;
+        }
+
+        let mut rest = rest.iter().peekable();
+        for (name, slot) in FIELDS.iter().zip(message.fields_mut()) {
+            if let Some(value) = rest
+                .peek()
+                .and_then(|line| line.strip_prefix(name))
+                .and_then(|line| line.strip_prefix(": "))
+            {
+                *slot = Some(value.to_string());
+                rest.next();
+            }
+        }
+        if rest.next_if(|line| **line == RESOURCES).is_some() {
+            while let Some(resource) = rest.next_if(|line| line.starts_with("- ")) {
+                message.resources.push(resource[2..].to_string());
+            }
+        }
+        if let Some(line) = rest.next() {
+            bail!("Unexpected line in sign-in message: `{}`", line);
+        }
+
+        // Catches blank lines and empty sections that would render differently
+        ensure!(
+            message.text()? == text,
+            "Sign-in message is not laid out as expected"
+        );
+        Ok(message)
+    }
+
+    /// Checks that `signature` is the address's over [`Self::text`] and that the message is
+    /// valid at `now`, i.e. not before `not_before` and before `expiration_time`.
+    pub fn verify(&self, signature: &Signature, now: DateTime<Utc>) -> Result<()> {
+        let text = self.text()?;
+        ensure!(
+            signature.verify(self.address.as_ref(), text.as_bytes()),
+            "Signature is not {}'s over the sign-in message",
+            self.address
+        );
+        if let Some(time) = &self.expiration_time {
+            ensure!(now < parse_timestamp(time)?, "Sign-in expired at {}", time);
+        }
+        if let Some(time) = &self.not_before {
+            ensure!(
+                now >= parse_timestamp(time)?,
+                "Sign-in is not valid before {}",
+                time
+            );
+        }
+        Ok(())
+    }
+
+    fn fields(&self) -> [Option<&String>; 8] {
+        [
+            self.uri.as_ref(),
+            self.version.as_ref(),
+            self.chain_id.as_ref(),
+            self.nonce.as_ref(),
+            self.issued_at.as_ref(),
+            self.expiration_time.as_ref(),
+            self.not_before.as_ref(),
+            self.request_id.as_ref(),
+        ]
+    }
+
+    fn fields_mut(&mut self) -> [&mut Option<String>; 8] {
+        [
+            &mut self.uri,
+            &mut self.version,
+            &mut self.chain_id,
+            &mut self.nonce,
+            &mut self.issued_at,
+            &mut self.expiration_time,
+            &mut self.not_before,
+            &mut self.request_id,
+        ]
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            !self.domain.is_empty() && !self.domain.contains(char::is_whitespace),
+            "Sign-in domain `{}` is empty or holds whitespace",
+            self.domain
+        );
+        if let Some(statement) = &self.statement {
+            ensure!(
+                !statement.is_empty() && !statement.contains('\n') && !is_field(statement),
+                "Sign-in statement must be one line that does not start like a field"
+            );
+        }
+        for (name, value) in FIELDS.iter().zip(self.fields()) {
+            if let Some(value) = value {
+                ensure!(
+                    !value.is_empty() && !value.contains('\n'),
+                    "Sign-in `{}` must be one non-empty line",
+                    name
+                );
+            }
+        }
+        for time in [&self.issued_at, &self.expiration_time, &self.not_before]
+            .into_iter()
+            .flatten()
+        {
+            parse_timestamp(time)?;
+        }
+        for resource in &self.resources {
+            ensure!(
+                !resource.is_empty() && !resource.contains('\n'),
+                "Sign-in resources must be non-empty lines"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Parses and verifies a signed sign-in message the way a dApp does before logging the user
+/// in, and returns it. The caller still checks that the address is an account it knows.
+///
+/// # Arguments
+/// * `text` - The signed text, as returned by the wallet
+/// * `signature` - The address's signature over `text`
+/// * `domain` - The dApp's own domain, which the message must name
+/// * `nonce` - The nonce the dApp issued for this sign-in
+/// * `now` - Current time, checked against the message's validity window
+pub fn verify_sign_in(
+    text: &str,
+    signature: &Signature,
+    domain: &str,
+    nonce: &str,
+    now: DateTime<Utc>,
+) -> Result<SignInMessage> {
+    let message = SignInMessage::parse(text)?;
+    ensure!(
+        message.domain == domain,
+        "Sign-in is for {}, not {}",
+        message.domain,
+        domain
+    );
+    ensure!(
+        message.nonce.as_deref() == Some(nonce),
+        "Sign-in nonce does not match the one issued"
+    );
+    message.verify(signature, now)?;
+    Ok(message)
+}
+
+fn is_field(line: &str) -> bool {
+    line == RESOURCES
+        || FIELDS
+            .iter()
+            .any(|name| line.strip_prefix(name).is_some_and(|l| l.starts_with(": ")))
+}
+
+/// RFC 3339 in UTC with milliseconds, as JavaScript's `toISOString` writes it.
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn parse_timestamp(time: &str) -> Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&Utc))
+        .with_context(|| format!("`{}` is not an RFC 3339 timestamp", time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use chrono::TimeDelta;
+
+    fn message(address: Pubkey, now: DateTime<Utc>) -> SignInMessage {
+        SignInMessage::new("example.com", address)
+            .statement("Sign in to Example")
+            .uri("https://example.com/login")
+            .chain_id("mainnet")
+            .nonce("abc123")
+            .issued_at(now)
+            .expiration_time(now + TimeDelta::minutes(10))
+            .resource("https://example.com/terms")
+    }
+
+    #[test]
+    fn text_is_laid_out_like_the_wallet_standard_and_parses_back() {
+        let address = Pubkey::new_unique();
+        let now = DateTime::parse_from_rfc3339("2024-01-02T03:04:05.678Z")
+            .unwrap()
+            .to_utc();
+        let message = message(address, now);
+
+        let text = message.text().unwrap();
+
+        assert_eq!(
+            text,
+            format!(
+                "example.com wants you to sign in with your Solana account:\n\
+                 {address}\n\
+                 \n\
+                 Sign in to Example\n\
+                 \n\
+                 URI: https://example.com/login\n\
+                 Version: 1\n\
+                 Chain ID: mainnet\n\
+                 Nonce: abc123\n\
+                 Issued At: 2024-01-02T03:04:05.678Z\n\
+                 Expiration Time: 2024-01-02T03:14:05.678Z\n\
+                 Resources:\n\
+                 - https://example.com/terms"
+            )
+        );
+        assert_eq!(SignInMessage::parse(&text).unwrap(), message);
+    }
+
+    #[test]
+    fn fields_that_would_break_the_layout_are_refused() {
+        let address = Pubkey::new_unique();
+
+        assert!(
+            SignInMessage::new("example.com", address)
+                .statement("two\nlines")
+                .text()
+                .is_err()
+        );
+        assert!(
+            SignInMessage::new("example.com", address)
+                .statement("Nonce: forged")
+                .text()
+                .is_err()
+        );
+        assert!(SignInMessage::new("evil .com", address).text().is_err());
+
+        let text = SignInMessage::new("example.com", address).text().unwrap();
+        assert!(SignInMessage::parse(&format!("{text}\n")).is_err());
+        assert!(SignInMessage::parse("example.com wants you to sign in").is_err());
+    }
+
+    #[tokio::test]
+    async fn dapp_accepts_a_signed_sign_in_for_its_domain_and_nonce_only() {
+        let shares = testing::keygen(2, 2).await;
+        let address = testing::public_key(&shares[0]);
+        let now = Utc::now();
+        let text = message(address, now).text().unwrap();
+        let signature = testing::sign(&shares, text.as_bytes()).await;
+
+        let verified = verify_sign_in(&text, &signature, "example.com", "abc123", now).unwrap();
+        assert_eq!(verified.address, address);
+
+        // Another dApp, or a replay with another nonce
+        assert!(verify_sign_in(&text, &signature, "evil.com", "abc123", now).is_err());
+        assert!(verify_sign_in(&text, &signature, "example.com", "other", now).is_err());
+        // Past its expiration
+        let later = now + TimeDelta::minutes(11);
+        assert!(verify_sign_in(&text, &signature, "example.com", "abc123", later).is_err());
+    }
+
+    #[tokio::test]
+    async fn sign_in_for_another_address_is_refused() {
+        let shares = testing::keygen(2, 2).await;
+        let now = Utc::now();
+        // Signed by the session key, but claims to be another account's sign-in
+        let text = message(Pubkey::new_unique(), now).text().unwrap();
+        let signature = testing::sign(&shares, text.as_bytes()).await;
+
+        assert!(verify_sign_in(&text, &signature, "example.com", "abc123", now).is_err());
+
+        // A signature over the off-chain wrapping of the text is not a sign-in signature
+        let address = testing::public_key(&shares[0]);
+        let text = message(address, now).text().unwrap();
+        let wrapped = crate::offchain::offchain_message(text.as_bytes()).unwrap();
+        let signature = testing::sign(&shares, &wrapped).await;
+        assert!(verify_sign_in(&text, &signature, "example.com", "abc123", now).is_err());
+    }
+}
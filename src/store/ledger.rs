@@ -0,0 +1,64 @@
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+
+/// Persistent record of the control requests a node has handled, keyed by `(node_id, request_id)`.
+#[async_trait]
+pub trait RequestLedger: Send + Sync {
+    /// Loads the entry for a request, if this node has seen it.
+    async fn get_request(&self, node_id: u64, request_id: &str) -> Result<Option<LedgerEntry>>;
+
+    /// Records `entry`, replacing the previous state of the same request.
+    async fn put_request(&self, node_id: u64, entry: &LedgerEntry) -> Result<()>;
+}
+
+/// What a node knows about one request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerEntry {
+    /// Request id as JSON, so `1` and `"1"` stay distinct
+    pub request_id: String,
+    pub session: String,
+    /// The request as JSON; a replay must match it exactly
+    pub request: String,
+    pub status: RequestStatus,
+    /// Response JSON once the request has finished
+    pub result: Option<String>,
+}
+
+/// Lifecycle of a ledger entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestStatus {
+    /// Started and not finished; a replay after a restart runs it again
+    Running,
+    /// Finished with a result, which replays return
+    Succeeded,
+    /// Failed for good, e.g. an invalid request; replays return the same error
+    Rejected,
+    /// Failed for a reason that may pass, e.g. a peer being down; a replay runs it again
+    Failed,
+}
+
+impl RequestStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RequestStatus::Running => "running",
+            RequestStatus::Succeeded => "succeeded",
+            RequestStatus::Rejected => "rejected",
+            RequestStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(status: &str) -> Result<Self> {
+        Ok(match status {
+            "running" => RequestStatus::Running,
+            "succeeded" => RequestStatus::Succeeded,
+            "rejected" => RequestStatus::Rejected,
+            "failed" => RequestStatus::Failed,
+            other => bail!("unknown request status {:?}", other),
+        })
+    }
+
+    /// Whether a replay is answered from the ledger instead of running again.
+    pub fn is_final(self) -> bool {
+        matches!(self, RequestStatus::Succeeded | RequestStatus::Rejected)
+    }
+}
@@ -0,0 +1,190 @@
+use super::{
+    KeyShare, KeyShareStore, LedgerEntry, PresignStock, PresignStore, RefreshStore, RequestLedger,
+};
+use crate::presign::Presignature;
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use tokio::sync::RwLock;
+
+/// Presignatures of one session and signer set, by id.
+type PresignKey = (u64, String, Vec<u16>);
+
+/// A share with its version.
+type Versioned = (u64, KeyShare);
+
+/// Volatile store; every share, presignature and ledger entry is lost when the process exits.
+#[derive(Default)]
+pub struct MemoryStore {
+    shares: RwLock<HashMap<(u64, String), Versioned>>,
+    staged: RwLock<HashMap<(u64, String), Versioned>>,
+    requests: RwLock<HashMap<(u64, String), LedgerEntry>>,
+    presignatures: RwLock<HashMap<PresignKey, BTreeMap<u64, Presignature>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl KeyShareStore for MemoryStore {
+    async fn put(&self, node_id: u64, session: &str, share: &KeyShare) -> Result<()> {
+        let mut shares = self.shares.write().await;
+        let key = (node_id, session.to_string());
+        self.staged.write().await.remove(&key);
+        shares.insert(key, (0, share.clone()));
+        drop(shares);
+
+        let mut presignatures = self.presignatures.write().await;
+        presignatures.retain(|(node, s, _), _| *node != node_id || s != session);
+        Ok(())
+    }
+
+    async fn get(&self, node_id: u64, session: &str) -> Result<Option<KeyShare>> {
+        let shares = self.shares.read().await;
+        Ok(shares
+            .get(&(node_id, session.to_string()))
+            .map(|(_, share)| share.clone()))
+    }
+
+    async fn rewrap(&self) -> Result<usize> {
+        // Nothing is written out, so there is nothing to re-seal
+        Ok(0)
+    }
+}
+
+#[async_trait]
+impl RefreshStore for MemoryStore {
+    async fn share_version(&self, node_id: u64, session: &str) -> Result<Option<u64>> {
+        let shares = self.shares.read().await;
+        Ok(shares
+            .get(&(node_id, session.to_string()))
+            .map(|&(version, _)| version))
+    }
+
+    async fn stage_share(
+        &self,
+        node_id: u64,
+        session: &str,
+        version: u64,
+        share: &KeyShare,
+    ) -> Result<()> {
+        let mut staged = self.staged.write().await;
+        staged.insert((node_id, session.to_string()), (version, share.clone()));
+        Ok(())
+    }
+
+    async fn staged_version(&self, node_id: u64, session: &str) -> Result<Option<u64>> {
+        let staged = self.staged.read().await;
+        Ok(staged
+            .get(&(node_id, session.to_string()))
+            .map(|&(version, _)| version))
+    }
+
+    async fn commit_share(&self, node_id: u64, session: &str, version: u64) -> Result<()> {
+        // Same lock order as `put`, so the two never deadlock
+        let mut shares = self.shares.write().await;
+        let mut staged = self.staged.write().await;
+        let key = (node_id, session.to_string());
+        if staged.get(&key).map(|&(v, _)| v) != Some(version) {
+            bail!(
+                "no share of version {} is staged for session {}",
+                version,
+                session
+            );
+        }
+        if let Some(share) = staged.remove(&key) {
+            shares.insert(key, share);
+        }
+        drop((shares, staged));
+
+        let mut presignatures = self.presignatures.write().await;
+        presignatures.retain(|(node, s, _), _| *node != node_id || s != session);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RequestLedger for MemoryStore {
+    async fn get_request(&self, node_id: u64, request_id: &str) -> Result<Option<LedgerEntry>> {
+        let requests = self.requests.read().await;
+        Ok(requests.get(&(node_id, request_id.to_string())).cloned())
+    }
+
+    async fn put_request(&self, node_id: u64, entry: &LedgerEntry) -> Result<()> {
+        let mut requests = self.requests.write().await;
+        requests.insert((node_id, entry.request_id.clone()), entry.clone());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PresignStore for MemoryStore {
+    async fn put_presignatures(
+        &self,
+        node_id: u64,
+        session: &str,
+        presignatures: Vec<Presignature>,
+    ) -> Result<()> {
+        let mut stock = self.presignatures.write().await;
+        for presignature in presignatures {
+            let key = (node_id, session.to_string(), presignature.signers.clone());
+            stock
+                .entry(key)
+                .or_default()
+                .insert(presignature.id, presignature);
+        }
+        Ok(())
+    }
+
+    async fn take_presignature(
+        &self,
+        node_id: u64,
+        session: &str,
+        signers: &[u16],
+    ) -> Result<Option<Presignature>> {
+        let mut stock = self.presignatures.write().await;
+        let key = (node_id, session.to_string(), signers.to_vec());
+        Ok(stock
+            .get_mut(&key)
+            .and_then(|presignatures| presignatures.pop_first())
+            .map(|(_, presignature)| presignature))
+    }
+
+    async fn discard_presignatures(
+        &self,
+        node_id: u64,
+        session: &str,
+        signers: &[u16],
+        up_to: u64,
+    ) -> Result<usize> {
+        let mut stock = self.presignatures.write().await;
+        let key = (node_id, session.to_string(), signers.to_vec());
+        let Some(presignatures) = stock.get_mut(&key) else {
+            return Ok(0);
+        };
+        let before = presignatures.len();
+        presignatures.retain(|&id, _| id > up_to);
+        Ok(before - presignatures.len())
+    }
+
+    async fn presignature_stock(
+        &self,
+        node_id: u64,
+        session: &str,
+        signers: &[u16],
+    ) -> Result<PresignStock> {
+        let stock = self.presignatures.read().await;
+        let key = (node_id, session.to_string(), signers.to_vec());
+        Ok(stock
+            .get(&key)
+            .map(|presignatures| PresignStock {
+                available: presignatures.len(),
+                next_id: presignatures.last_key_value().map_or(0, |(&id, _)| id + 1),
+            })
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,460 @@
+mod ledger;
+mod memory;
+mod postgres;
+mod presign;
+mod refresh;
+mod sqlite;
+
+pub use ledger::{LedgerEntry, RequestLedger, RequestStatus};
+pub use memory::MemoryStore;
+pub use postgres::PostgresStore;
+pub use presign::{PresignStock, PresignStore};
+pub use refresh::RefreshStore;
+pub use sqlite::SqliteStore;
+
+use crate::presign::Presignature;
+use crate::seal::{self, Keyring};
+
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use givre::generic_ec::curves::Ed25519;
+use givre::key_share::DirtyKeyShare;
+use givre::keygen::key_share::Valid;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+/// A validated Ed25519 key share as produced by `keygen::generate_private_share`.
+pub type KeyShare = Valid<DirtyKeyShare<Ed25519>>;
+
+/// Persistent storage for key shares, keyed by `(node_id, session)`.
+#[async_trait]
+pub trait KeyShareStore: Send + Sync {
+    /// Stores `share` as version `0`, replacing any share already held for the same node and
+    /// session and dropping any share staged by a refresh, along with the session's
+    /// presignatures, which belong to the key being replaced.
+    async fn put(&self, node_id: u64, session: &str, share: &KeyShare) -> Result<()>;
+
+    /// Loads the share for a node and session, if one exists.
+    async fn get(&self, node_id: u64, session: &str) -> Result<Option<KeyShare>>;
+
+    /// Re-seals every stored share, staged share and presignature under the keyring's current key, including
+    /// records written before encryption was enabled. Returns the number of records rewritten.
+    async fn rewrap(&self) -> Result<usize>;
+}
+
+/// Everything a node persists: its key shares and their versions, presignatures and request
+/// ledger, kept in one database.
+pub trait Store: KeyShareStore + RefreshStore + RequestLedger + PresignStore {}
+
+impl<T: KeyShareStore + RefreshStore + RequestLedger + PresignStore> Store for T {}
+
+/// Opens the store selected by `url` and applies any pending migrations.
+///
+/// # Arguments
+/// * `url` - `memory`, `sqlite://<path>` or `postgres://<connection string>`
+/// * `keyring` - Key-encryption keys used to seal shares at rest; `None` stores them in the clear
+pub async fn open_store(url: &str, keyring: Option<Keyring>) -> Result<Arc<dyn Store>> {
+    let codec = ShareCodec {
+        keyring: keyring.map(Arc::new),
+    };
+
+    let store: Arc<dyn Store> = if url == "memory" || url.starts_with("memory:") {
+        Arc::new(MemoryStore::new())
+    } else if url.starts_with("sqlite:") {
+        Arc::new(SqliteStore::connect(url, codec.clone()).await?)
+    } else if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+        Arc::new(PostgresStore::connect(url, codec.clone()).await?)
+    } else {
+        bail!("unsupported key share store url: {}", url);
+    };
+
+    if codec.keyring.is_none() && !url.starts_with("memory") {
+        warn!("No key-encryption key configured; key shares will be stored unencrypted");
+    }
+
+    info!("Opened key share store: {}", redact_url(url));
+    Ok(store)
+}
+
+/// Serializes shares and, when a keyring is configured, seals them bound to their row key.
+#[derive(Clone)]
+struct ShareCodec {
+    keyring: Option<Arc<Keyring>>,
+}
+
+impl ShareCodec {
+    fn encode(&self, node_id: u64, session: &str, share: &KeyShare) -> Result<Vec<u8>> {
+        self.seal_json(share, &share_aad(node_id, session))
+    }
+
+    fn decode(&self, node_id: u64, session: &str, record: &[u8]) -> Result<KeyShare> {
+        let what = format!("share for session {}", session);
+        self.open_json(record, &share_aad(node_id, session), &what)
+    }
+
+    fn encode_presignature(
+        &self,
+        node_id: u64,
+        session: &str,
+        presignature: &Presignature,
+    ) -> Result<Vec<u8>> {
+        let signers = signers_key(&presignature.signers);
+        let aad = presignature_aad(node_id, session, &signers, presignature.id);
+        self.seal_json(presignature, &aad)
+    }
+
+    fn decode_presignature(
+        &self,
+        node_id: u64,
+        session: &str,
+        signers: &[u16],
+        id: u64,
+        record: &[u8],
+    ) -> Result<Presignature> {
+        let what = format!("presignature {} for session {}", id, session);
+        let aad = presignature_aad(node_id, session, &signers_key(signers), id);
+        self.open_json(record, &aad, &what)
+    }
+
+    /// Returns the record re-sealed under the current key, or `None` if it is already current.
+    ///
+    /// # Arguments
+    /// * `aad` - The row binding the record was sealed with, from `share_aad()` or
+    ///   `presignature_aad()`
+    /// * `record` - The stored record, sealed or not
+    fn rewrap(&self, aad: &[u8], record: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some(keyring) = &self.keyring else {
+            bail!("cannot re-wrap shares without a key-encryption key");
+        };
+
+        if seal::is_sealed(record) {
+            seal::rewrap(keyring, record, aad)
+        } else {
+            seal::seal(keyring, record, aad).map(Some)
+        }
+    }
+
+    fn seal_json<T: Serialize>(&self, value: &T, aad: &[u8]) -> Result<Vec<u8>> {
+        let plaintext = Zeroizing::new(serde_json::to_vec(value)?);
+        match &self.keyring {
+            Some(keyring) => seal::seal(keyring, &plaintext, aad),
+            None => Ok(plaintext.to_vec()),
+        }
+    }
+
+    fn open_json<T: DeserializeOwned>(&self, record: &[u8], aad: &[u8], what: &str) -> Result<T> {
+        if !seal::is_sealed(record) {
+            // ✅ With a keyring every record is sealed, since `rewrap` seals plaintext ones at
+            // startup; an unsealed one would skip the AEAD check and its row binding
+            if self.keyring.is_some() {
+                bail!(
+                    "{} is not sealed although a key-encryption key is configured",
+                    what
+                );
+            }
+            return Ok(serde_json::from_slice(record)?);
+        }
+
+        let keyring = self
+            .keyring
+            .as_ref()
+            .ok_or_else(|| anyhow!("{} is sealed but no key-encryption key is configured", what))?;
+        let plaintext = seal::unseal(keyring, record, aad)
+            .with_context(|| format!("failed to unseal {}", what))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+/// Binds a sealed share to the row it was written for, so records cannot be swapped. A staged
+/// share is bound the same way, so committing it moves the record as it is.
+fn share_aad(node_id: u64, session: &str) -> Vec<u8> {
+    format!("key_share:{}:{}", node_id, session).into_bytes()
+}
+
+/// Binds a sealed presignature to its row, so one cannot be passed off as another.
+fn presignature_aad(node_id: u64, session: &str, signers: &str, id: u64) -> Vec<u8> {
+    format!("presignature:{}:{}:{}:{}", node_id, session, signers, id).into_bytes()
+}
+
+/// Signer set as stored next to its presignatures, e.g. `0,2`.
+fn signers_key(signers: &[u16]) -> String {
+    signers
+        .iter()
+        .map(u16::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Strips credentials from a connection url before it is logged.
+fn redact_url(url: &str) -> String {
+    match (url.find("://"), url.rfind('@')) {
+        (Some(scheme_end), Some(at)) if at > scheme_end => {
+            format!("{}://***{}", &url[..scheme_end], &url[at..])
+        }
+        _ => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{keygen, presign};
+
+    /// A fresh store of every backend that runs without a server.
+    async fn stores(name: &str) -> Vec<Arc<dyn Store>> {
+        let path =
+            std::env::temp_dir().join(format!("dkg-store-{}-{}.db", std::process::id(), name));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        vec![
+            open_store("memory", None).await.unwrap(),
+            open_store(&format!("sqlite://{}", path.display()), None)
+                .await
+                .unwrap(),
+        ]
+    }
+
+    fn same_share(a: &KeyShare, b: &KeyShare) -> bool {
+        serde_json::to_value(a).unwrap() == serde_json::to_value(b).unwrap()
+    }
+
+    #[tokio::test]
+    async fn shares_are_kept_per_node_and_session() {
+        let shares = keygen(2, 2).await;
+        for store in stores("per-session").await {
+            assert!(store.get(0, "session-001").await.unwrap().is_none());
+
+            store.put(0, "session-001", &shares[0]).await.unwrap();
+            store.put(1, "session-001", &shares[1]).await.unwrap();
+
+            let stored = store.get(0, "session-001").await.unwrap().unwrap();
+            assert!(same_share(&stored, &shares[0]));
+            let stored = store.get(1, "session-001").await.unwrap().unwrap();
+            assert!(same_share(&stored, &shares[1]));
+            assert!(store.get(0, "session-002").await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn put_replaces_the_share_of_a_session() {
+        let old = keygen(2, 2).await;
+        let new = keygen(2, 2).await;
+        for store in stores("replace").await {
+            store.put(0, "session-001", &old[0]).await.unwrap();
+            store.put(0, "session-001", &new[0]).await.unwrap();
+
+            let stored = store.get(0, "session-001").await.unwrap().unwrap();
+            assert!(same_share(&stored, &new[0]));
+        }
+    }
+
+    #[tokio::test]
+    async fn sqlite_shares_outlive_the_store() {
+        let shares = keygen(2, 2).await;
+        let path = std::env::temp_dir().join(format!("dkg-store-{}-reopen.db", std::process::id()));
+        let url = format!("sqlite://{}", path.display());
+        let _ = std::fs::remove_file(&path);
+
+        let store = open_store(&url, None).await.unwrap();
+        store.put(0, "session-001", &shares[0]).await.unwrap();
+        drop(store);
+
+        let reopened = open_store(&url, None).await.unwrap();
+        let stored = reopened.get(0, "session-001").await.unwrap().unwrap();
+        assert!(same_share(&stored, &shares[0]));
+    }
+
+    #[tokio::test]
+    async fn plaintext_rows_are_refused_under_a_keyring_until_rewrapped() {
+        let shares = keygen(2, 2).await;
+        let path =
+            std::env::temp_dir().join(format!("dkg-store-{}-plaintext.db", std::process::id()));
+        let url = format!("sqlite://{}", path.display());
+        let _ = std::fs::remove_file(&path);
+        let mut keyring = Keyring::new();
+        keyring.insert(1, [7; 32]).unwrap();
+        let store = open_store(&url, Some(keyring)).await.unwrap();
+        store.put(0, "session-001", &shares[0]).await.unwrap();
+
+        // Someone with write access to the database swaps the sealed share for a plaintext one
+        let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+        sqlx::query("UPDATE key_shares SET share = ? WHERE node_id = 0 AND session_id = ?")
+            .bind(serde_json::to_vec(&shares[1]).unwrap())
+            .bind("session-001")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert!(store.get(0, "session-001").await.is_err());
+
+        // The startup re-wrap still seals plaintext rows, e.g. from before sealing was enabled
+        assert_eq!(store.rewrap().await.unwrap(), 1);
+        let stored = store.get(0, "session-001").await.unwrap().unwrap();
+        assert!(same_share(&stored, &shares[1]));
+    }
+
+    #[tokio::test]
+    async fn unknown_store_urls_are_refused() {
+        assert!(open_store("redis://127.0.0.1", None).await.is_err());
+    }
+
+    #[test]
+    fn logged_urls_hide_credentials() {
+        assert_eq!(
+            redact_url("postgres://idmap:secret@db:5432/idmap"),
+            "postgres://***@db:5432/idmap"
+        );
+        assert_eq!(redact_url("sqlite://shares.db"), "sqlite://shares.db");
+    }
+
+    #[tokio::test]
+    async fn presignatures_are_taken_once_in_id_order() {
+        let shares = keygen(2, 2).await;
+        for store in stores("take-once").await {
+            let presignatures = presign(&shares, &[0, 1], 3, 0).await.remove(0);
+            store
+                .put_presignatures(0, "session-001", presignatures)
+                .await
+                .unwrap();
+            assert_eq!(
+                store
+                    .presignature_stock(0, "session-001", &[0, 1])
+                    .await
+                    .unwrap(),
+                PresignStock {
+                    available: 3,
+                    next_id: 3
+                }
+            );
+
+            let mut taken = Vec::new();
+            while let Some(presignature) = store
+                .take_presignature(0, "session-001", &[0, 1])
+                .await
+                .unwrap()
+            {
+                taken.push(presignature.id);
+            }
+            assert_eq!(taken, vec![0, 1, 2]);
+            assert_eq!(
+                store
+                    .presignature_stock(0, "session-001", &[0, 1])
+                    .await
+                    .unwrap()
+                    .available,
+                0
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn presignatures_are_kept_per_node_session_and_signer_set() {
+        let shares = keygen(3, 2).await;
+        for store in stores("per-set").await {
+            let presignatures = presign(&shares, &[0, 1], 2, 0).await.remove(0);
+            store
+                .put_presignatures(0, "session-001", presignatures)
+                .await
+                .unwrap();
+
+            for (node, session, signers) in [
+                (1, "session-001", [0, 1]),
+                (0, "session-002", [0, 1]),
+                (0, "session-001", [0, 2]),
+            ] {
+                assert!(
+                    store
+                        .take_presignature(node, session, &signers)
+                        .await
+                        .unwrap()
+                        .is_none()
+                );
+            }
+
+            assert_eq!(
+                store
+                    .discard_presignatures(0, "session-001", &[0, 1], 0)
+                    .await
+                    .unwrap(),
+                1
+            );
+            let next = store
+                .take_presignature(0, "session-001", &[0, 1])
+                .await
+                .unwrap();
+            assert_eq!(next.map(|p| p.id), Some(1));
+        }
+    }
+
+    #[tokio::test]
+    async fn replacing_a_share_drops_the_presignatures_of_the_old_key() {
+        let old = keygen(2, 2).await;
+        let new = keygen(2, 2).await;
+        for store in stores("rotate").await {
+            store.put(0, "session-001", &old[0]).await.unwrap();
+            store.put(0, "session-002", &old[0]).await.unwrap();
+            for session in ["session-001", "session-002"] {
+                let presignatures = presign(&old, &[0, 1], 2, 0).await.remove(0);
+                store
+                    .put_presignatures(0, session, presignatures)
+                    .await
+                    .unwrap();
+            }
+
+            store.put(0, "session-001", &new[0]).await.unwrap();
+
+            let rotated = store
+                .presignature_stock(0, "session-001", &[0, 1])
+                .await
+                .unwrap();
+            assert_eq!(rotated.available, 0);
+            let untouched = store
+                .presignature_stock(0, "session-002", &[0, 1])
+                .await
+                .unwrap();
+            assert_eq!(untouched.available, 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn committing_a_refresh_drops_presignatures_and_bumps_the_version() {
+        let shares = keygen(2, 2).await;
+        for store in stores("refresh").await {
+            store.put(0, "session-001", &shares[0]).await.unwrap();
+            let presignatures = presign(&shares, &[0, 1], 2, 0).await.remove(0);
+            store
+                .put_presignatures(0, "session-001", presignatures)
+                .await
+                .unwrap();
+
+            store
+                .stage_share(0, "session-001", 1, &shares[0])
+                .await
+                .unwrap();
+            assert!(store.commit_share(0, "session-001", 2).await.is_err());
+            assert_eq!(
+                store.share_version(0, "session-001").await.unwrap(),
+                Some(0)
+            );
+
+            store.commit_share(0, "session-001", 1).await.unwrap();
+            assert_eq!(
+                store.share_version(0, "session-001").await.unwrap(),
+                Some(1)
+            );
+            assert_eq!(store.staged_version(0, "session-001").await.unwrap(), None);
+            assert_eq!(
+                store
+                    .presignature_stock(0, "session-001", &[0, 1])
+                    .await
+                    .unwrap()
+                    .available,
+                0
+            );
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use super::{
+    KeyShare, KeyShareStore, LedgerEntry, PresignStock, PresignStore, RefreshStore, RequestLedger,
+    RequestStatus, ShareCodec, presignature_aad, share_aad, signers_key,
+};
+use crate::presign::Presignature;
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
+
+/// Shared database store, suited to nodes that already run against Postgres.
+pub struct PostgresStore {
+    codec: ShareCodec,
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    /// Connects to the database at `url` and runs migrations.
+    pub(super) async fn connect(url: &str, codec: ShareCodec) -> Result<Self> {
+        let pool = PgPoolOptions::new().max_connections(5).connect(url).await?;
+
+        sqlx::migrate!("./migrations/postgres").run(&pool).await?;
+
+        Ok(Self { codec, pool })
+    }
+}
+
+#[async_trait]
+impl KeyShareStore for PostgresStore {
+    async fn put(&self, node_id: u64, session: &str, share: &KeyShare) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO key_shares (node_id, session_id, share) VALUES ($1, $2, $3)
+             ON CONFLICT (node_id, session_id)
+             DO UPDATE SET share = EXCLUDED.share, version = 0, updated_at = now()",
+        )
+        .bind(node_id as i64)
+        .bind(session)
+        .bind(self.codec.encode(node_id, session, share)?)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM staged_key_shares WHERE node_id = $1 AND session_id = $2")
+            .bind(node_id as i64)
+            .bind(session)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM presignatures WHERE node_id = $1 AND session_id = $2")
+            .bind(node_id as i64)
+            .bind(session)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, node_id: u64, session: &str) -> Result<Option<KeyShare>> {
+        let row: Option<(Vec<u8>,)> =
+            sqlx::query_as("SELECT share FROM key_shares WHERE node_id = $1 AND session_id = $2")
+                .bind(node_id as i64)
+                .bind(session)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        row.map(|(bytes,)| self.codec.decode(node_id, session, &bytes))
+            .transpose()
+    }
+
+    async fn rewrap(&self) -> Result<usize> {
+        let rows: Vec<(i64, String, Vec<u8>)> =
+            sqlx::query_as("SELECT node_id, session_id, share FROM key_shares")
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut rewritten = 0;
+        for (node_id, session, record) in rows {
+            let Some(resealed) = self
+                .codec
+                .rewrap(&share_aad(node_id as u64, &session), &record)?
+            else {
+                continue;
+            };
+
+            // Compare-and-swap so a concurrent write is never clobbered by a stale record
+            let result = sqlx::query(
+                "UPDATE key_shares SET share = $1, updated_at = now()
+                 WHERE node_id = $2 AND session_id = $3 AND share = $4",
+            )
+            .bind(resealed)
+            .bind(node_id)
+            .bind(&session)
+            .bind(&record)
+            .execute(&self.pool)
+            .await?;
+            rewritten += result.rows_affected() as usize;
+        }
+
+        let rows: Vec<(i64, String, Vec<u8>)> =
+            sqlx::query_as("SELECT node_id, session_id, share FROM staged_key_shares")
+                .fetch_all(&self.pool)
+                .await?;
+
+        for (node_id, session, record) in rows {
+            let Some(resealed) = self
+                .codec
+                .rewrap(&share_aad(node_id as u64, &session), &record)?
+            else {
+                continue;
+            };
+
+            let result = sqlx::query(
+                "UPDATE staged_key_shares SET share = $1
+                 WHERE node_id = $2 AND session_id = $3 AND share = $4",
+            )
+            .bind(resealed)
+            .bind(node_id)
+            .bind(&session)
+            .bind(&record)
+            .execute(&self.pool)
+            .await?;
+            rewritten += result.rows_affected() as usize;
+        }
+
+        let rows: Vec<(i64, String, String, i64, Vec<u8>)> = sqlx::query_as(
+            "SELECT node_id, session_id, signers, presignature_id, record FROM presignatures",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        for (node_id, session, signers, id, record) in rows {
+            let aad = presignature_aad(node_id as u64, &session, &signers, id as u64);
+            let Some(resealed) = self.codec.rewrap(&aad, &record)? else {
+                continue;
+            };
+
+            // A presignature taken in the meantime is gone, and the update simply misses it
+            let result = sqlx::query(
+                "UPDATE presignatures SET record = $1
+                 WHERE node_id = $2 AND session_id = $3 AND signers = $4
+                     AND presignature_id = $5 AND record = $6",
+            )
+            .bind(resealed)
+            .bind(node_id)
+            .bind(&session)
+            .bind(&signers)
+            .bind(id)
+            .bind(&record)
+            .execute(&self.pool)
+            .await?;
+            rewritten += result.rows_affected() as usize;
+        }
+
+        Ok(rewritten)
+    }
+}
+
+#[async_trait]
+impl RefreshStore for PostgresStore {
+    async fn share_version(&self, node_id: u64, session: &str) -> Result<Option<u64>> {
+        let row: Option<(i64,)> =
+            sqlx::query_as("SELECT version FROM key_shares WHERE node_id = $1 AND session_id = $2")
+                .bind(node_id as i64)
+                .bind(session)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.map(|(version,)| version as u64))
+    }
+
+    async fn stage_share(
+        &self,
+        node_id: u64,
+        session: &str,
+        version: u64,
+        share: &KeyShare,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO staged_key_shares (node_id, session_id, version, share)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (node_id, session_id)
+             DO UPDATE SET version = EXCLUDED.version, share = EXCLUDED.share, created_at = now()",
+        )
+        .bind(node_id as i64)
+        .bind(session)
+        .bind(version as i64)
+        .bind(self.codec.encode(node_id, session, share)?)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn staged_version(&self, node_id: u64, session: &str) -> Result<Option<u64>> {
+        let row: Option<(i64,)> = sqlx::query_as(
+            "SELECT version FROM staged_key_shares WHERE node_id = $1 AND session_id = $2",
+        )
+        .bind(node_id as i64)
+        .bind(session)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(version,)| version as u64))
+    }
+
+    async fn commit_share(&self, node_id: u64, session: &str, version: u64) -> Result<()> {
+        // ✅ One transaction, so a crash leaves either the old share or the new one current.
+        // Postgres keeps the old row as a dead tuple until the table is vacuumed, and has no
+        // equivalent of SQLite's `secure_delete`: the old share stays readable from the data
+        // files until `VACUUM FULL key_shares, staged_key_shares` rewrites them.
+        let mut tx = self.pool.begin().await?;
+        let moved = sqlx::query(
+            "UPDATE key_shares SET share = staged.share, version = staged.version,
+                 updated_at = now()
+             FROM staged_key_shares AS staged
+             WHERE key_shares.node_id = staged.node_id
+                 AND key_shares.session_id = staged.session_id
+                 AND staged.node_id = $1 AND staged.session_id = $2 AND staged.version = $3",
+        )
+        .bind(node_id as i64)
+        .bind(session)
+        .bind(version as i64)
+        .execute(&mut *tx)
+        .await?;
+        if moved.rows_affected() != 1 {
+            bail!(
+                "no share of version {} is staged for session {}",
+                version,
+                session
+            );
+        }
+
+        sqlx::query("DELETE FROM staged_key_shares WHERE node_id = $1 AND session_id = $2")
+            .bind(node_id as i64)
+            .bind(session)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM presignatures WHERE node_id = $1 AND session_id = $2")
+            .bind(node_id as i64)
+            .bind(session)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RequestLedger for PostgresStore {
+    async fn get_request(&self, node_id: u64, request_id: &str) -> Result<Option<LedgerEntry>> {
+        let row: Option<(String, String, String, Option<String>)> = sqlx::query_as(
+            "SELECT session_id, request, status, result FROM request_ledger
+             WHERE node_id = $1 AND request_id = $2",
+        )
+        .bind(node_id as i64)
+        .bind(request_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|(session, request, status, result)| {
+            Ok(LedgerEntry {
+                request_id: request_id.to_string(),
+                session,
+                request,
+                status: RequestStatus::parse(&status)?,
+                result,
+            })
+        })
+        .transpose()
+    }
+
+    async fn put_request(&self, node_id: u64, entry: &LedgerEntry) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO request_ledger (node_id, request_id, session_id, request, status, result)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (node_id, request_id)
+             DO UPDATE SET session_id = EXCLUDED.session_id, request = EXCLUDED.request,
+                 status = EXCLUDED.status, result = EXCLUDED.result, updated_at = now()",
+        )
+        .bind(node_id as i64)
+        .bind(&entry.request_id)
+        .bind(&entry.session)
+        .bind(&entry.request)
+        .bind(entry.status.as_str())
+        .bind(&entry.result)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PresignStore for PostgresStore {
+    async fn put_presignatures(
+        &self,
+        node_id: u64,
+        session: &str,
+        presignatures: Vec<Presignature>,
+    ) -> Result<()> {
+        // All or none, so a failed write leaves no partial batch that other signers lack
+        let mut tx = self.pool.begin().await?;
+        for presignature in &presignatures {
+            sqlx::query(
+                "INSERT INTO presignatures (node_id, session_id, signers, presignature_id, record)
+                 VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(node_id as i64)
+            .bind(session)
+            .bind(signers_key(&presignature.signers))
+            .bind(presignature.id as i64)
+            .bind(
+                self.codec
+                    .encode_presignature(node_id, session, presignature)?,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn take_presignature(
+        &self,
+        node_id: u64,
+        session: &str,
+        signers: &[u16],
+    ) -> Result<Option<Presignature>> {
+        // ✅ Read and deleted in one statement: once returned, no other session can get it
+        let row: Option<(i64, Vec<u8>)> = sqlx::query_as(
+            "DELETE FROM presignatures
+             WHERE (node_id, session_id, signers, presignature_id) IN (
+                 SELECT node_id, session_id, signers, presignature_id FROM presignatures
+                 WHERE node_id = $1 AND session_id = $2 AND signers = $3
+                 ORDER BY presignature_id LIMIT 1 FOR UPDATE SKIP LOCKED)
+             RETURNING presignature_id, record",
+        )
+        .bind(node_id as i64)
+        .bind(session)
+        .bind(signers_key(signers))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|(id, record)| {
+            self.codec
+                .decode_presignature(node_id, session, signers, id as u64, &record)
+        })
+        .transpose()
+    }
+
+    async fn discard_presignatures(
+        &self,
+        node_id: u64,
+        session: &str,
+        signers: &[u16],
+        up_to: u64,
+    ) -> Result<usize> {
+        let result = sqlx::query(
+            "DELETE FROM presignatures
+             WHERE node_id = $1 AND session_id = $2 AND signers = $3 AND presignature_id <= $4",
+        )
+        .bind(node_id as i64)
+        .bind(session)
+        .bind(signers_key(signers))
+        .bind(up_to.min(i64::MAX as u64) as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() as usize)
+    }
+
+    async fn presignature_stock(
+        &self,
+        node_id: u64,
+        session: &str,
+        signers: &[u16],
+    ) -> Result<PresignStock> {
+        let (available, highest): (i64, Option<i64>) = sqlx::query_as(
+            "SELECT COUNT(*), MAX(presignature_id) FROM presignatures
+             WHERE node_id = $1 AND session_id = $2 AND signers = $3",
+        )
+        .bind(node_id as i64)
+        .bind(session)
+        .bind(signers_key(signers))
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(PresignStock {
+            available: available as usize,
+            next_id: highest.map_or(0, |id| id as u64 + 1),
+        })
+    }
+}
@@ -0,0 +1,53 @@
+use crate::presign::Presignature;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// Persistent stock of presignatures, keyed by `(node_id, session, signers)` and numbered by
+/// id. Taking a presignature deletes it in the same write that reads it, so none is ever
+/// handed out twice, not even across a crash.
+#[async_trait]
+pub trait PresignStore: Send + Sync {
+    /// Adds presignatures for a node's session; each carries its signer set and id.
+    async fn put_presignatures(
+        &self,
+        node_id: u64,
+        session: &str,
+        presignatures: Vec<Presignature>,
+    ) -> Result<()>;
+
+    /// Deletes the presignature with the lowest id for a signer set and returns it, or `None`
+    /// when there is none left.
+    async fn take_presignature(
+        &self,
+        node_id: u64,
+        session: &str,
+        signers: &[u16],
+    ) -> Result<Option<Presignature>>;
+
+    /// Deletes every presignature for a signer set with an id up to `up_to`, returning how
+    /// many there were.
+    async fn discard_presignatures(
+        &self,
+        node_id: u64,
+        session: &str,
+        signers: &[u16],
+        up_to: u64,
+    ) -> Result<usize>;
+
+    /// How many presignatures are left for a signer set, and the id the next ones start at.
+    async fn presignature_stock(
+        &self,
+        node_id: u64,
+        session: &str,
+        signers: &[u16],
+    ) -> Result<PresignStock>;
+}
+
+/// Presignatures a node holds for one session and signer set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PresignStock {
+    pub available: usize,
+    /// One above the highest id held, `0` when there is none
+    pub next_id: u64,
+}
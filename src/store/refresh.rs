@@ -0,0 +1,34 @@
+use super::KeyShare;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+/// Versions of a node's key shares, for refreshing them in place. A refresh stages the next
+/// version of a share next to the current one and commits it once every party has staged
+/// theirs, so a node that stops half way still holds a share that signs with the others.
+#[async_trait]
+pub trait RefreshStore: Send + Sync {
+    /// Version of the current share for a node and session: `0` from keygen, one more after
+    /// every refresh.
+    async fn share_version(&self, node_id: u64, session: &str) -> Result<Option<u64>>;
+
+    /// Stores `share` as the staged `version` of a node's share, replacing any share staged
+    /// before. The current share is left as it is.
+    async fn stage_share(
+        &self,
+        node_id: u64,
+        session: &str,
+        version: u64,
+        share: &KeyShare,
+    ) -> Result<()>;
+
+    /// Version of the share staged for a node and session, if any.
+    async fn staged_version(&self, node_id: u64, session: &str) -> Result<Option<u64>>;
+
+    /// Makes the staged share of `version` the current one in a single write, overwriting the
+    /// old share and deleting the session's presignatures, whose nonces were stored next to
+    /// it. Fails if no share of that version is staged. Whether the old share's bytes are gone
+    /// from disk depends on the backend: SQLite zeroes them, Postgres keeps them in dead tuples
+    /// until the table is vacuumed.
+    async fn commit_share(&self, node_id: u64, session: &str, version: u64) -> Result<()>;
+}
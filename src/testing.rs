@@ -0,0 +1,134 @@
+use crate::keygen::{KeygenConfig, generate_private_share};
+use crate::presign::{Presignature, run_presign_phase};
+use crate::progress::Progress;
+use crate::sign::run_signing_phase;
+use crate::store::KeyShare;
+
+use anyhow::Result;
+use solana_pubkey::Pubkey;
+use solana_signature::Signature;
+use std::collections::BTreeMap;
+use tokio::io::DuplexStream;
+
+/// One in-memory connection between every pair of `parties`: for each party, its links to
+/// the others keyed by their index.
+pub fn mesh(parties: &[u16]) -> BTreeMap<u16, Vec<(u16, DuplexStream)>> {
+    let mut links: BTreeMap<u16, Vec<(u16, DuplexStream)>> =
+        parties.iter().map(|&party| (party, Vec::new())).collect();
+    for (position, &a) in parties.iter().enumerate() {
+        for &b in &parties[position + 1..] {
+            let (a_end, b_end) = tokio::io::duplex(1 << 16);
+            links.get_mut(&a).unwrap().push((b, a_end));
+            links.get_mut(&b).unwrap().push((a, b_end));
+        }
+    }
+    links
+}
+
+/// Runs a `threshold`-of-`n` keygen in process and returns every party's share, by index.
+pub async fn keygen(n: u16, threshold: u16) -> Vec<KeyShare> {
+    let config = KeygenConfig::new(n, threshold).unwrap();
+    let parties: Vec<u16> = (0..n).collect();
+    let runs: Vec<_> = mesh(&parties)
+        .into_iter()
+        .map(|(party, peers)| {
+            tokio::spawn(async move {
+                generate_private_share(
+                    peers,
+                    u64::from(party),
+                    config,
+                    b"test-session",
+                    &Progress::default(),
+                )
+                .await
+            })
+        })
+        .collect();
+
+    let mut shares = Vec::new();
+    for run in runs {
+        shares.push(run.await.unwrap().unwrap());
+    }
+    shares
+}
+
+/// Runs one signing session among `signers`, each signing the message at its position, and
+/// returns every signer's result in the order of `signers`.
+pub async fn sign_each(
+    shares: &[KeyShare],
+    signers: &[u16],
+    messages: &[&[u8]],
+) -> Vec<Result<Signature>> {
+    let mut links = mesh(signers);
+    let runs: Vec<_> = signers
+        .iter()
+        .zip(messages)
+        .map(|(&party, message)| {
+            let peers = links.remove(&party).unwrap();
+            let share = shares[usize::from(party)].clone();
+            let signers = signers.to_vec();
+            let message = message.to_vec();
+            tokio::spawn(async move {
+                let progress = Progress::default();
+                run_signing_phase(party.into(), share, &signers, peers, message, &progress).await
+            })
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    for run in runs {
+        results.push(run.await.unwrap());
+    }
+    results
+}
+
+/// The key's address, as a Solana pubkey.
+pub fn public_key(share: &KeyShare) -> Pubkey {
+    Pubkey::try_from(share.shared_public_key().to_bytes(true).as_ref()).unwrap()
+}
+
+/// Signs `message` in process with the first `threshold` parties of `shares`.
+pub async fn sign(shares: &[KeyShare], message: &[u8]) -> Signature {
+    let signers: Vec<u16> = (0..shares[0].min_signers()).collect();
+    let messages = vec![message; signers.len()];
+    let mut results = sign_each(shares, &signers, &messages).await;
+    results.swap_remove(0).unwrap()
+}
+
+/// Generates `count` presignatures, numbered from `first_id`, for the signer set `signers`;
+/// returns each signer's, in the order of `signers`.
+pub async fn presign(
+    shares: &[KeyShare],
+    signers: &[u16],
+    count: usize,
+    first_id: u64,
+) -> Vec<Vec<Presignature>> {
+    let mut links = mesh(signers);
+    let runs: Vec<_> = signers
+        .iter()
+        .map(|&party| {
+            let peers = links.remove(&party).unwrap();
+            let share = shares[usize::from(party)].clone();
+            let signers = signers.to_vec();
+            tokio::spawn(async move {
+                let progress = Progress::default();
+                run_presign_phase(
+                    party.into(),
+                    &share,
+                    &signers,
+                    peers,
+                    count,
+                    first_id,
+                    &progress,
+                )
+                .await
+            })
+        })
+        .collect();
+
+    let mut presignatures = Vec::new();
+    for run in runs {
+        presignatures.push(run.await.unwrap().unwrap());
+    }
+    presignatures
+}
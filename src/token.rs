@@ -0,0 +1,313 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use solana_hash::Hash;
+use solana_instruction::Instruction;
+use solana_message::Message;
+use solana_program::instruction::AccountMeta;
+use solana_pubkey::{Pubkey, pubkey};
+use spl_associated_token_account_interface::address::get_associated_token_address_with_program_id;
+use spl_associated_token_account_interface::instruction::create_associated_token_account_idempotent;
+use spl_token_interface::instruction::TokenInstruction;
+
+use crate::sign;
+
+/// Token-2022 program, which keeps the instruction layout of the original token program.
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+/// Token program that owns a mint.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenProgram {
+    /// The original SPL Token program
+    #[default]
+    Token,
+    #[serde(rename = "token-2022")]
+    Token2022,
+}
+
+impl TokenProgram {
+    pub fn id(self) -> Pubkey {
+        match self {
+            TokenProgram::Token => spl_token_interface::id(),
+            TokenProgram::Token2022 => TOKEN_2022_PROGRAM_ID,
+        }
+    }
+}
+
+/// A `TransferChecked` of `amount` base units of `mint` from the owner's associated token
+/// account to the recipient's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenTransfer {
+    /// Wallet that owns the source account, signs and pays fees; the MPC-controlled key
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    /// Wallet that receives the tokens; its associated token account is credited
+    pub recipient: Pubkey,
+    /// Amount in the mint's base units
+    pub amount: u64,
+    /// The mint's decimals; the program rejects the transfer if they do not match
+    pub decimals: u8,
+    pub program: TokenProgram,
+    /// Create the recipient's associated token account first if it does not exist
+    pub create_recipient_account: bool,
+    /// Memo attached after the transfer, signed by the owner
+    pub memo: Option<String>,
+}
+
+/// Associated token account of `wallet` for `mint`.
+pub fn associated_token_address(wallet: &Pubkey, mint: &Pubkey, program: TokenProgram) -> Pubkey {
+    get_associated_token_address_with_program_id(wallet, mint, &program.id())
+}
+
+/// `TransferChecked` instruction for either token program, signed by `authority` alone.
+///
+/// # Arguments
+/// * `program` - Token program that owns the mint
+/// * `source` - Token account debited
+/// * `mint` - Mint of both accounts
+/// * `destination` - Token account credited
+/// * `authority` - Owner of `source`
+/// * `amount` - Amount in the mint's base units
+/// * `decimals` - The mint's decimals
+pub fn transfer_checked_instruction(
+    program: TokenProgram,
+    source: &Pubkey,
+    mint: &Pubkey,
+    destination: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+    decimals: u8,
+) -> Instruction {
+    // Packed directly: the interface's own builder only accepts the original program id
+    let data = TokenInstruction::TransferChecked { amount, decimals }.pack();
+
+    Instruction {
+        program_id: program.id(),
+        accounts: vec![
+            AccountMeta::new(*source, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        data,
+    }
+}
+
+/// Instruction creating `wallet`'s associated token account for `mint`, paid by `payer`.
+/// It is a no-op when the account already exists, so it is safe to include unconditionally.
+pub fn create_token_account_instruction(
+    payer: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    program: TokenProgram,
+) -> Instruction {
+    create_associated_token_account_idempotent(payer, wallet, mint, &program.id())
+}
+
+/// Builds the message for `transfer`, paid for by its owner.
+///
+/// # Arguments
+/// * `transfer` - What to transfer, and to whom
+/// * `recent_blockhash` - Blockhash the message is valid under
+pub fn token_transfer_message(transfer: &TokenTransfer, recent_blockhash: Hash) -> Message {
+    let instructions = token_transfer_instructions(transfer);
+    Message::new_with_blockhash(&instructions, Some(&transfer.owner), &recent_blockhash)
+}
+
+/// Instructions of `transfer`, for composing them into other messages such as a v0 message
+/// built with `sign::v0_message`.
+pub fn token_transfer_instructions(transfer: &TokenTransfer) -> Vec<Instruction> {
+    let TokenTransfer {
+        owner,
+        mint,
+        recipient,
+        amount,
+        decimals,
+        program,
+        create_recipient_account,
+        ref memo,
+    } = *transfer;
+
+    let source = associated_token_address(&owner, &mint, program);
+    let destination = associated_token_address(&recipient, &mint, program);
+
+    let mut instructions = Vec::with_capacity(3);
+    if create_recipient_account {
+        instructions.push(create_token_account_instruction(
+            &owner, &recipient, &mint, program,
+        ));
+    }
+    instructions.push(transfer_checked_instruction(
+        program,
+        &source,
+        &mint,
+        &destination,
+        &owner,
+        amount,
+        decimals,
+    ));
+    if let Some(memo) = memo {
+        instructions.push(sign::memo_instruction(memo, &owner));
+    }
+    instructions
+}
+
+/// Builds a message that creates `wallet`'s associated token account for `mint`, paid for by
+/// `payer`.
+///
+/// # Arguments
+/// * `payer` - Fee and rent payer; the MPC-controlled key
+/// * `wallet` - Owner of the new account
+/// * `mint` - Mint the account holds
+/// * `program` - Token program that owns the mint
+/// * `recent_blockhash` - Blockhash the message is valid under
+pub fn create_token_account_message(
+    payer: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    program: TokenProgram,
+    recent_blockhash: Hash,
+) -> Message {
+    let instruction = create_token_account_instruction(payer, wallet, mint, program);
+    Message::new_with_blockhash(&[instruction], Some(payer), &recent_blockhash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_message::compiled_instruction::CompiledInstruction;
+
+    fn transfer(program: TokenProgram) -> TokenTransfer {
+        TokenTransfer {
+            owner: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            recipient: Pubkey::new_unique(),
+            amount: 1_500_000,
+            decimals: 6,
+            program,
+            create_recipient_account: false,
+            memo: None,
+        }
+    }
+
+    fn program_of<'a>(message: &'a Message, instruction: &CompiledInstruction) -> &'a Pubkey {
+        &message.account_keys[usize::from(instruction.program_id_index)]
+    }
+
+    fn accounts_of(message: &Message, instruction: &CompiledInstruction) -> Vec<Pubkey> {
+        instruction
+            .accounts
+            .iter()
+            .map(|&index| message.account_keys[usize::from(index)])
+            .collect()
+    }
+
+    #[test]
+    fn transfer_checked_moves_between_associated_accounts_paid_by_the_owner() {
+        for program in [TokenProgram::Token, TokenProgram::Token2022] {
+            let transfer = transfer(program);
+            let message = token_transfer_message(&transfer, Hash::new_unique());
+
+            assert_eq!(message.header.num_required_signatures, 1);
+            assert_eq!(message.account_keys[0], transfer.owner);
+            assert_eq!(message.instructions.len(), 1);
+
+            let instruction = &message.instructions[0];
+            assert_eq!(program_of(&message, instruction), &program.id());
+            assert_eq!(
+                TokenInstruction::unpack(&instruction.data).unwrap(),
+                TokenInstruction::TransferChecked {
+                    amount: 1_500_000,
+                    decimals: 6
+                }
+            );
+            assert_eq!(
+                accounts_of(&message, instruction),
+                vec![
+                    associated_token_address(&transfer.owner, &transfer.mint, program),
+                    transfer.mint,
+                    associated_token_address(&transfer.recipient, &transfer.mint, program),
+                    transfer.owner,
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn associated_accounts_differ_between_token_programs() {
+        let wallet = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+
+        assert_ne!(
+            associated_token_address(&wallet, &mint, TokenProgram::Token),
+            associated_token_address(&wallet, &mint, TokenProgram::Token2022)
+        );
+    }
+
+    #[test]
+    fn recipient_account_is_created_before_and_memo_added_after_the_transfer() {
+        let transfer = TokenTransfer {
+            create_recipient_account: true,
+            memo: Some("invoice 42".to_string()),
+            ..transfer(TokenProgram::Token2022)
+        };
+
+        let instructions = token_transfer_instructions(&transfer);
+
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(
+            instructions[0],
+            create_token_account_instruction(
+                &transfer.owner,
+                &transfer.recipient,
+                &transfer.mint,
+                TokenProgram::Token2022
+            )
+        );
+        assert_eq!(instructions[1].program_id, TOKEN_2022_PROGRAM_ID);
+        assert_eq!(instructions[2].program_id, sign::MEMO_PROGRAM_ID);
+        assert_eq!(instructions[2].data, b"invoice 42");
+
+        // Still a single signer: the owner pays for the new account too
+        let message = token_transfer_message(&transfer, Hash::new_unique());
+        assert_eq!(message.header.num_required_signatures, 1);
+        assert_eq!(message.account_keys[0], transfer.owner);
+    }
+
+    #[test]
+    fn token_account_creation_is_paid_by_the_payer() {
+        let payer = Pubkey::new_unique();
+        let wallet = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+
+        let message = create_token_account_message(
+            &payer,
+            &wallet,
+            &mint,
+            TokenProgram::Token,
+            Hash::new_unique(),
+        );
+
+        assert_eq!(message.header.num_required_signatures, 1);
+        assert_eq!(message.account_keys[0], payer);
+        let accounts = accounts_of(&message, &message.instructions[0]);
+        assert_eq!(
+            accounts[1],
+            associated_token_address(&wallet, &mint, TokenProgram::Token)
+        );
+        assert_eq!(accounts[2], wallet);
+        assert_eq!(accounts[3], mint);
+    }
+
+    #[test]
+    fn token_programs_are_named_in_kebab_case() {
+        assert_eq!(
+            serde_json::to_string(&TokenProgram::Token2022).unwrap(),
+            "\"token-2022\""
+        );
+        assert_eq!(
+            serde_json::from_str::<TokenProgram>("\"token\"").unwrap(),
+            TokenProgram::Token
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use anyhow::{Result, ensure};
+use solana_commitment_config::CommitmentConfig;
+use solana_message::VersionedMessage;
+use solana_signature::Signature;
+use solana_transaction::versioned::VersionedTransaction;
+use solana_transaction_error::TransactionError;
+use std::fmt;
+use std::future::Future;
+use tokio::time::{Duration, sleep};
+use tracing::{info, warn};
+
+use crate::rpc::SolanaRpc;
+
+/// How [`submit_transaction`] simulates, sends and confirms a transaction.
+#[derive(Debug, Clone)]
+pub struct SubmitConfig {
+    /// Commitment the transaction must reach; blockhashes and simulation use it too
+    pub commitment: CommitmentConfig,
+    /// Times the message is re-signed under a fresh blockhash after the previous one expired
+    pub max_blockhash_refreshes: usize,
+    /// How often the signature status is polled, and the transaction re-sent, until it lands
+    pub poll_interval: Duration,
+    /// Simulate before sending; a failed simulation aborts before anything is sent
+    pub simulate: bool,
+}
+
+impl Default for SubmitConfig {
+    fn default() -> Self {
+        Self {
+            commitment: CommitmentConfig::confirmed(),
+            max_blockhash_refreshes: 3,
+            poll_interval: Duration::from_secs(2),
+            simulate: true,
+        }
+    }
+}
+
+/// Why [`submit_transaction`] gave up on a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubmitError {
+    /// Simulation rejected the transaction; nothing was sent
+    SimulationFailed { error: String, logs: Vec<String> },
+    /// The transaction landed but failed on chain
+    TransactionFailed {
+        signature: Signature,
+        error: TransactionError,
+    },
+    /// Every blockhash expired before the transaction was confirmed
+    BlockhashExpired { attempts: usize },
+}
+
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmitError::SimulationFailed { error, logs } => {
+                write!(f, "simulation failed: {}", error)?;
+                if !logs.is_empty() {
+                    write!(f, "\n{}", logs.join("\n"))?;
+                }
+                Ok(())
+            }
+            SubmitError::TransactionFailed { signature, error } => {
+                write!(f, "transaction {} failed: {}", signature, error)
+            }
+            SubmitError::BlockhashExpired { attempts } => write!(
+                f,
+                "blockhash expired before confirmation on all {} attempts",
+                attempts
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SubmitError {}
+
+/// Signs `message` with the threshold key, then simulates, sends and confirms it.
+///
+/// Each attempt sets a fresh blockhash and calls `sign` with the serialized message. The
+/// transaction is re-sent every `poll_interval` until it lands; if its blockhash expires first,
+/// the message is signed again under a new one, up to `max_blockhash_refreshes` times.
+/// Returns the signature of the confirmed transaction, which is also its id.
+///
+/// # Arguments
+/// * `rpc` - RPC of the cluster to submit to, e.g. `Cluster::client()` or `MockRpc`
+/// * `message` - Legacy or v0 message whose only required signer is the MPC-controlled fee
+///   payer; wrap a legacy `Message` in `VersionedMessage::Legacy`
+/// * `sign` - Runs a signing session over the given bytes, e.g. via `run_signing_phase`
+/// * `config` - Commitment, retry and simulation settings
+pub async fn submit_transaction<F, Fut>(
+    rpc: &dyn SolanaRpc,
+    mut message: VersionedMessage,
+    mut sign: F,
+    config: &SubmitConfig,
+) -> Result<Signature>
+where
+    F: FnMut(Vec<u8>) -> Fut,
+    Fut: Future<Output = Result<Signature>>,
+{
+    // ✅ A threshold key yields one signature; anything else could never be fully signed
+    let required_signatures = message.header().num_required_signatures;
+    ensure!(
+        required_signatures == 1,
+        "message requires {} signatures, threshold signing provides 1",
+        required_signatures
+    );
+
+    let attempts = config.max_blockhash_refreshes + 1;
+    for attempt in 1..=attempts {
+        let (blockhash, last_valid_block_height) = rpc.latest_blockhash(config.commitment).await?;
+        message.set_recent_blockhash(blockhash);
+
+        let signature = sign(message.serialize()).await?;
+        let transaction = VersionedTransaction {
+            signatures: vec![signature],
+            message: message.clone(),
+        };
+
+        if config.simulate {
+            simulate(rpc, &transaction, config).await?;
+        }
+
+        info!(
+            "[TX] Sending {} (attempt {}/{}, valid until block {})",
+            signature, attempt, attempts, last_valid_block_height
+        );
+        if confirm(rpc, &transaction, last_valid_block_height, config).await? {
+            info!("[TX] Confirmed {}", signature);
+            return Ok(signature);
+        }
+        warn!(
+            "[TX] Blockhash of {} expired before it landed; signing again",
+            signature
+        );
+    }
+
+    Err(SubmitError::BlockhashExpired { attempts }.into())
+}
+
+/// Fails with [`SubmitError::SimulationFailed`] if the cluster would reject `transaction`.
+async fn simulate(
+    rpc: &dyn SolanaRpc,
+    transaction: &VersionedTransaction,
+    config: &SubmitConfig,
+) -> Result<()> {
+    let simulation = rpc.simulate(transaction, config.commitment).await?;
+    match simulation.err {
+        None => Ok(()),
+        Some(error) => Err(SubmitError::SimulationFailed {
+            error,
+            logs: simulation.logs,
+        }
+        .into()),
+    }
+}
+
+/// Sends `transaction` until it reaches the configured commitment. Returns `false` once its
+/// blockhash has expired without it landing, so it can no longer be processed.
+///
+/// # Arguments
+/// * `rpc` - RPC of the cluster to submit to
+/// * `transaction` - Fully signed transaction
+/// * `last_valid_block_height` - Last block height at which its blockhash is accepted
+/// * `config` - Commitment and polling settings
+async fn confirm(
+    rpc: &dyn SolanaRpc,
+    transaction: &VersionedTransaction,
+    last_valid_block_height: u64,
+    config: &SubmitConfig,
+) -> Result<bool> {
+    let signature = transaction.signatures[0];
+
+    let mut landed = false;
+    loop {
+        // Re-sent here on every poll instead of by the RPC node
+        if !landed && let Err(e) = rpc.send(transaction).await {
+            warn!("[TX] Failed to send {}: {}", signature, e);
+        }
+        sleep(config.poll_interval).await;
+
+        if let Some(status) = rpc.signature_status(&signature).await? {
+            if let Some(error) = status.err {
+                return Err(SubmitError::TransactionFailed { signature, error }.into());
+            }
+            if status.satisfies_commitment(config.commitment) {
+                return Ok(true);
+            }
+            // In a block already, so it can no longer expire; wait for the commitment
+            landed = true;
+            continue;
+        }
+        // Not seen, or dropped with a fork; keep sending while the blockhash is valid
+        landed = false;
+
+        let block_height = rpc.block_height(config.commitment).await?;
+        if block_height > last_valid_block_height {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::MockRpc;
+    use crate::sign::transfer_message;
+    use crate::testing;
+    use solana_hash::Hash;
+    use solana_message::Message;
+    use std::sync::Mutex;
+
+    const FROM: &str = "4Nd1mBQtrMJVYVfKf2PJy9NZUZdTAsp7D4xWLs4gDB4T";
+    const TO: &str = "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin";
+
+    fn config() -> SubmitConfig {
+        SubmitConfig {
+            poll_interval: Duration::ZERO,
+            max_blockhash_refreshes: 2,
+            ..Default::default()
+        }
+    }
+
+    fn message() -> VersionedMessage {
+        VersionedMessage::Legacy(transfer_message(FROM, TO, 1_000).unwrap())
+    }
+
+    /// Stands in for threshold signing: a distinct signature per call, with every signed
+    /// message kept for inspection.
+    struct Signer(Mutex<Vec<Vec<u8>>>);
+
+    impl Signer {
+        fn new() -> Self {
+            Self(Mutex::new(Vec::new()))
+        }
+
+        async fn sign(&self, message: Vec<u8>) -> Result<Signature> {
+            let mut signed = self.0.lock().unwrap();
+            signed.push(message);
+            Ok(Signature::from([signed.len() as u8; 64]))
+        }
+
+        fn blockhashes(&self) -> Vec<Hash> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|bytes| *parse(bytes).recent_blockhash())
+                .collect()
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> VersionedMessage {
+        VersionedMessage::Legacy(bincode::deserialize::<Message>(bytes).unwrap())
+    }
+
+    fn submit_error(error: anyhow::Error) -> SubmitError {
+        error.downcast().unwrap()
+    }
+
+    #[tokio::test]
+    async fn threshold_signed_transfer_is_confirmed() {
+        let shares = testing::keygen(2, 2).await;
+        let from = testing::public_key(&shares[0]);
+        let message = transfer_message(&from.to_string(), TO, 1_000).unwrap();
+
+        let rpc = MockRpc::new();
+        let sign = |bytes: Vec<u8>| {
+            let shares = shares.clone();
+            async move { Ok(testing::sign(&shares, &bytes).await) }
+        };
+
+        let signature =
+            submit_transaction(&rpc, VersionedMessage::Legacy(message), sign, &config())
+                .await
+                .unwrap();
+
+        let sent = rpc.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].signatures, vec![signature]);
+        assert!(signature.verify(from.as_ref(), &sent[0].message.serialize()));
+    }
+
+    #[tokio::test]
+    async fn failed_simulation_sends_nothing() {
+        let rpc = MockRpc::new().simulation_error("InsufficientFundsForFee");
+        let signer = Signer::new();
+
+        let error = submit_transaction(&rpc, message(), |m| signer.sign(m), &config())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            submit_error(error),
+            SubmitError::SimulationFailed {
+                error: "InsufficientFundsForFee".to_string(),
+                logs: Vec::new(),
+            }
+        );
+        assert!(rpc.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dropped_send_is_sent_again_under_the_same_signature() {
+        let rpc = MockRpc::new().drop_sends(2);
+        let signer = Signer::new();
+
+        let signature = submit_transaction(&rpc, message(), |m| signer.sign(m), &config())
+            .await
+            .unwrap();
+
+        let sent = rpc.sent();
+        assert_eq!(sent.len(), 3);
+        assert!(sent.iter().all(|t| t.signatures == vec![signature]));
+        assert_eq!(signer.blockhashes().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn expired_blockhash_is_replaced_and_the_message_signed_again() {
+        // The first send is dropped and its blockhash expires before the next poll
+        let rpc = MockRpc::new().blockhash_lifetime(2).drop_sends(1);
+        let signer = Signer::new();
+
+        let signature = submit_transaction(&rpc, message(), |m| signer.sign(m), &config())
+            .await
+            .unwrap();
+
+        let blockhashes = signer.blockhashes();
+        assert_eq!(blockhashes.len(), 2);
+        assert_ne!(blockhashes[0], blockhashes[1]);
+        let sent = rpc.sent();
+        assert_eq!(sent.last().unwrap().signatures, vec![signature]);
+        assert_eq!(
+            sent.last().unwrap().message.recent_blockhash(),
+            &blockhashes[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_once_every_blockhash_expired() {
+        let rpc = MockRpc::new().blockhash_lifetime(2).drop_sends(usize::MAX);
+        let signer = Signer::new();
+
+        let error = submit_transaction(&rpc, message(), |m| signer.sign(m), &config())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            submit_error(error),
+            SubmitError::BlockhashExpired { attempts: 3 }
+        );
+        assert_eq!(signer.blockhashes().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn transaction_failing_on_chain_is_reported_with_its_signature() {
+        let rpc = MockRpc::new().transaction_error(TransactionError::InsufficientFundsForFee);
+        let signer = Signer::new();
+
+        let error = submit_transaction(&rpc, message(), |m| signer.sign(m), &config())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            submit_error(error),
+            SubmitError::TransactionFailed {
+                signature: Signature::from([1; 64]),
+                error: TransactionError::InsufficientFundsForFee,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn messages_needing_more_than_one_signature_are_refused() {
+        let mut message = transfer_message(FROM, TO, 1_000).unwrap();
+        message.header.num_required_signatures = 2;
+        let rpc = MockRpc::new();
+        let signer = Signer::new();
+
+        let result = submit_transaction(
+            &rpc,
+            VersionedMessage::Legacy(message),
+            |m| signer.sign(m),
+            &config(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(signer.blockhashes().is_empty());
+        assert!(rpc.sent().is_empty());
+    }
+}
@@ -0,0 +1,604 @@
+pub mod peers;
+pub mod session;
+pub mod tls;
+
+use crate::progress::{Progress, ProgressEvent};
+
+use bincode;
+use bytes::{Bytes, BytesMut};
+use futures::stream::{self, BoxStream, SelectAll};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use round_based::{Incoming, MessageDestination, Outgoing, ProtocolMessage};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    collections::BTreeMap,
+    io,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    sync::atomic::{AtomicU16, Ordering},
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::task::JoinHandle;
+use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+use tracing::warn;
+
+/// Any duplex byte stream a peer link can run over (plain TCP or TLS).
+pub trait PeerStream: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T> PeerStream for T where T: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+/// Type-erased peer link, so plain and TLS connections can share one code path.
+pub type BoxedStream = Box<dyn PeerStream>;
+
+#[derive(Serialize, Deserialize, Debug)]
+enum MsgKind {
+    Broadcast,
+    P2P,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct WireMessage<M> {
+    kind: MsgKind,
+    recipient: Option<u16>,
+    msg: M,
+}
+
+/// Splits one duplex connection per peer into the incoming and outgoing halves
+/// expected by `round_based::MpcParty::connected`.
+///
+/// # Arguments
+/// * `id` - This party's index
+/// * `peers` - One connection per remote party, keyed by that party's index
+/// * `progress` - Receives peer disconnects and round starts
+pub fn split_peers<M, S>(
+    id: u64,
+    peers: Vec<(u16, S)>,
+    progress: &Progress,
+) -> (TcpIncoming<M>, TcpOutgoing<M>)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (readers, writers): (Vec<_>, Vec<_>) = peers
+        .into_iter()
+        .map(|(peer, stream)| {
+            let (reader, writer) = tokio::io::split(stream);
+            ((peer, reader), (peer, writer))
+        })
+        .unzip();
+
+    (
+        TcpIncoming::new(id, readers, progress),
+        TcpOutgoing::new(writers, progress),
+    )
+}
+
+/// ======================
+/// INCOMING TRANSPORT
+/// ======================
+pub struct TcpIncoming<M> {
+    id: u16,
+    /// Index passed to `new` when it is not a party index; every poll fails with it
+    invalid_id: Option<u64>,
+    next_msg_id: u64,
+    frames: SelectAll<BoxStream<'static, (u16, io::Result<BytesMut>)>>,
+    _phantom: PhantomData<M>,
+}
+
+impl<M> TcpIncoming<M> {
+    /// Builds the incoming transport from one reader per peer.
+    /// Every frame is tagged with the index of the peer whose connection it arrived on,
+    /// and a connection that ends is reported to `progress`. If `id` is not a party index,
+    /// the stream yields an error instead of any message.
+    pub fn new<R>(id: u64, peers: Vec<(u16, R)>, progress: &Progress) -> Self
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        let mut frames = SelectAll::new();
+        for (peer, reader) in peers {
+            let progress = progress.clone();
+            let closed = stream::once(async move {
+                progress.emit(ProgressEvent::PeerDisconnected { party: peer });
+            })
+            .filter_map(|()| futures::future::ready(None));
+
+            let framed = FramedRead::new(reader, LengthDelimitedCodec::new())
+                .map(move |frame| (peer, frame))
+                .chain(closed);
+            frames.push(framed.boxed());
+        }
+
+        let (id, invalid_id) = match u16::try_from(id) {
+            Ok(index) => (index, None),
+            Err(_) => (u16::MAX, Some(id)),
+        };
+
+        Self {
+            id,
+            invalid_id,
+            next_msg_id: 0,
+            frames,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<M> Stream for TcpIncoming<M>
+where
+    M: DeserializeOwned + Send + Unpin + 'static,
+{
+    type Item = Result<Incoming<M>, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(id) = this.invalid_id {
+            return Poll::Ready(Some(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("party index {} does not fit in u16", id),
+            ))));
+        }
+
+        match Pin::new(&mut this.frames).poll_next(cx) {
+            Poll::Ready(Some((sender, Ok(bytes)))) => {
+                match bincode::deserialize::<WireMessage<M>>(bytes.as_ref()) {
+                    Ok(wire_msg) => {
+                        let msg_type = match wire_msg.kind {
+                            MsgKind::Broadcast => round_based::MessageType::Broadcast,
+                            MsgKind::P2P => round_based::MessageType::P2P,
+                        };
+
+                        if msg_type == round_based::MessageType::P2P
+                            && wire_msg.recipient != Some(this.id)
+                        {
+                            return Poll::Ready(Some(Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "p2p message from party {} addressed to {:?}, expected {}",
+                                    sender, wire_msg.recipient, this.id
+                                ),
+                            ))));
+                        }
+
+                        let incoming = Incoming {
+                            id: this.next_msg_id,
+                            sender,
+                            msg_type,
+                            msg: wire_msg.msg,
+                        };
+                        this.next_msg_id += 1;
+
+                        Poll::Ready(Some(Ok(incoming)))
+                    }
+                    Err(e) => Poll::Ready(Some(Err(io::Error::other(format!(
+                        "deserialize error from party {}: {}",
+                        sender, e
+                    ))))),
+                }
+            }
+            Poll::Ready(Some((_, Err(e)))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// ======================
+/// OUTGOING TRANSPORT
+/// ======================
+pub struct TcpOutgoing<M> {
+    peers: BTreeMap<u16, UnboundedSender<Bytes>>,
+    /// Highest round sent so far, shared by clones
+    round: Arc<AtomicU16>,
+    progress: Progress,
+    _phantom: PhantomData<M>,
+}
+
+impl<M> Clone for TcpOutgoing<M> {
+    fn clone(&self) -> Self {
+        Self {
+            peers: self.peers.clone(),
+            round: self.round.clone(),
+            progress: self.progress.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<M> TcpOutgoing<M> {
+    /// Builds the outgoing transport from one writer per peer.
+    /// Each writer is drained by its own task so a slow peer does not stall the others.
+    /// The first message of every round is reported to `progress`.
+    pub fn new<W>(peers: Vec<(u16, W)>, progress: &Progress) -> Self
+    where
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let peers = peers
+            .into_iter()
+            .map(|(peer, writer)| {
+                let (tx, rx) = unbounded_channel();
+                let framed_writer = FramedWrite::new(writer, LengthDelimitedCodec::new());
+
+                tokio::spawn(async move {
+                    run_sender(peer, framed_writer, rx).await;
+                });
+
+                (peer, tx)
+            })
+            .collect();
+
+        Self {
+            peers,
+            round: Arc::new(AtomicU16::new(0)),
+            progress: progress.clone(),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn send_to(&self, peer: u16, data: Bytes) -> Result<(), io::Error> {
+        let tx = self.peers.get(&peer).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no connection to party {}", peer),
+            )
+        })?;
+
+        tx.send(data).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                format!("connection to party {} is closed", peer),
+            )
+        })
+    }
+
+    /// Frames `item` and queues it for its recipient, or for every peer if broadcast.
+    fn send_message(&self, item: Outgoing<M>) -> Result<(), io::Error>
+    where
+        M: ProtocolMessage + Serialize,
+    {
+        let (kind, recipient) = match &item.recipient {
+            MessageDestination::AllParties => (MsgKind::Broadcast, None),
+            MessageDestination::OneParty(peer_id) => (MsgKind::P2P, Some(*peer_id)),
+        };
+
+        // round-based numbers rounds from 0
+        let round = item.msg.round() + 1;
+        if self.round.fetch_max(round, Ordering::Relaxed) < round {
+            self.progress.emit(ProgressEvent::RoundStarted { round });
+        }
+
+        let wire_msg = WireMessage {
+            kind,
+            recipient,
+            msg: item.msg,
+        };
+
+        let data = bincode::serialize(&wire_msg).map_err(|e| io::Error::other(e.to_string()))?;
+        let data = Bytes::from(data);
+
+        match recipient {
+            Some(peer) => self.send_to(peer, data),
+            None => self
+                .peers
+                .keys()
+                .try_for_each(|peer| self.send_to(*peer, data.clone())),
+        }
+    }
+}
+
+async fn run_sender<W>(
+    peer: u16,
+    mut framed: FramedWrite<W, LengthDelimitedCodec>,
+    mut rx: UnboundedReceiver<Bytes>,
+) where
+    W: AsyncWrite + Unpin,
+{
+    while let Some(msg) = rx.recv().await {
+        if let Err(e) = framed.send(msg).await {
+            warn!("Failed to send frame to party {}: {:?}", peer, e);
+            let _ = framed.flush().await;
+            break;
+        }
+    }
+}
+
+impl<M> Sink<Outgoing<M>> for TcpOutgoing<M>
+where
+    M: ProtocolMessage + Serialize + Send + 'static,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Outgoing<M>) -> Result<(), Self::Error> {
+        self.send_message(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// ======================
+/// BATCHED TRANSPORT
+/// ======================
+#[derive(Serialize, Deserialize, Debug)]
+struct Batched<M> {
+    /// Position of the protocol instance in its batch
+    index: u32,
+    msg: M,
+}
+
+impl<M: ProtocolMessage> ProtocolMessage for Batched<M> {
+    fn round(&self) -> u16 {
+        self.msg.round()
+    }
+}
+
+/// Splits one duplex connection per peer into `count` incoming/outgoing pairs, one per
+/// protocol instance of a batch. Messages are tagged with their instance's position, so all
+/// instances run at once over the same connections.
+///
+/// # Arguments
+/// * `id` - This party's index
+/// * `peers` - One connection per remote party, keyed by that party's index
+/// * `count` - Number of protocol instances in the batch
+/// * `progress` - Receives peer disconnects and round starts, shared by every instance
+pub fn split_peers_batched<M, S>(
+    id: u64,
+    peers: Vec<(u16, S)>,
+    count: usize,
+    progress: &Progress,
+) -> Vec<(BatchIncoming<M>, BatchOutgoing<M>)>
+where
+    M: DeserializeOwned + Send + Unpin + 'static,
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (incoming, outgoing) = split_peers::<Batched<M>, S>(id, peers, progress);
+    let (senders, receivers): (Vec<_>, Vec<_>) = (0..count).map(|_| unbounded_channel()).unzip();
+    let router = Arc::new(Router(tokio::spawn(route(incoming, senders))));
+
+    receivers
+        .into_iter()
+        .enumerate()
+        .map(|(index, rx)| {
+            let incoming = BatchIncoming {
+                rx,
+                _router: router.clone(),
+            };
+            let outgoing = BatchOutgoing {
+                index: index as u32,
+                inner: outgoing.clone(),
+            };
+            (incoming, outgoing)
+        })
+        .collect()
+}
+
+/// Hands every incoming message to the instance it is tagged with. A transport error ends
+/// every instance, since they share the connection it happened on.
+async fn route<M>(
+    mut incoming: TcpIncoming<Batched<M>>,
+    instances: Vec<UnboundedSender<Result<Incoming<M>, io::Error>>>,
+) where
+    M: DeserializeOwned + Send + Unpin + 'static,
+{
+    while let Some(item) = incoming.next().await {
+        let error = match item {
+            Ok(Incoming {
+                id,
+                sender,
+                msg_type,
+                msg: Batched { index, msg },
+            }) => match instances.get(index as usize) {
+                Some(instance) => {
+                    let _ = instance.send(Ok(Incoming {
+                        id,
+                        sender,
+                        msg_type,
+                        msg,
+                    }));
+                    continue;
+                }
+                None => io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "party {} sent a message for batch entry {} of {}",
+                        sender,
+                        index,
+                        instances.len()
+                    ),
+                ),
+            },
+            Err(e) => e,
+        };
+        for instance in &instances {
+            let _ = instance.send(Err(io::Error::new(error.kind(), error.to_string())));
+        }
+    }
+}
+
+/// Stops routing once every instance of the batch is gone.
+struct Router(JoinHandle<()>);
+
+impl Drop for Router {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Incoming messages of one protocol instance of a batch.
+pub struct BatchIncoming<M> {
+    rx: UnboundedReceiver<Result<Incoming<M>, io::Error>>,
+    _router: Arc<Router>,
+}
+
+impl<M> Stream for BatchIncoming<M> {
+    type Item = Result<Incoming<M>, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().rx.poll_recv(cx)
+    }
+}
+
+/// Outgoing messages of one protocol instance of a batch, tagged with its position.
+pub struct BatchOutgoing<M> {
+    index: u32,
+    inner: TcpOutgoing<Batched<M>>,
+}
+
+impl<M> Sink<Outgoing<M>> for BatchOutgoing<M>
+where
+    M: ProtocolMessage + Serialize + Send + 'static,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Outgoing<M>) -> Result<(), Self::Error> {
+        self.inner.send_message(Outgoing {
+            recipient: item.recipient,
+            msg: Batched {
+                index: self.index,
+                msg: item.msg,
+            },
+        })
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use round_based::MessageType;
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Note(String);
+
+    impl ProtocolMessage for Note {
+        fn round(&self) -> u16 {
+            0
+        }
+    }
+
+    async fn socket_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dialed = TcpStream::connect(listener.local_addr().unwrap());
+        let (dialed, accepted) = tokio::join!(dialed, listener.accept());
+        (dialed.unwrap(), accepted.unwrap().0)
+    }
+
+    /// One loopback connection between every pair of `n` parties, split into each party's
+    /// transport halves, by party index.
+    async fn mesh(n: u16) -> Vec<(TcpIncoming<Note>, TcpOutgoing<Note>)> {
+        let mut links: Vec<Vec<(u16, TcpStream)>> = (0..n).map(|_| Vec::new()).collect();
+        for a in 0..n {
+            for b in a + 1..n {
+                let (a_end, b_end) = socket_pair().await;
+                links[usize::from(a)].push((b, a_end));
+                links[usize::from(b)].push((a, b_end));
+            }
+        }
+        links
+            .into_iter()
+            .enumerate()
+            .map(|(party, peers)| split_peers(party as u64, peers, &Progress::default()))
+            .collect()
+    }
+
+    fn note(text: &str) -> Note {
+        Note(text.to_string())
+    }
+
+    #[tokio::test]
+    async fn broadcasts_reach_every_peer_tagged_with_their_sender() {
+        let mut parties = mesh(3).await;
+
+        parties[0]
+            .1
+            .send(Outgoing::broadcast(note("hello")))
+            .await
+            .unwrap();
+
+        for (incoming, _) in &mut parties[1..] {
+            let received = incoming.next().await.unwrap().unwrap();
+            assert_eq!(received.sender, 0);
+            assert_eq!(received.msg_type, MessageType::Broadcast);
+            assert_eq!(received.msg, note("hello"));
+        }
+    }
+
+    #[tokio::test]
+    async fn p2p_messages_reach_only_their_recipient() {
+        let mut parties = mesh(3).await;
+
+        parties[1]
+            .1
+            .send(Outgoing::p2p(2, note("for 2")))
+            .await
+            .unwrap();
+        parties[1]
+            .1
+            .send(Outgoing::broadcast(note("for all")))
+            .await
+            .unwrap();
+
+        let first = parties[2].0.next().await.unwrap().unwrap();
+        assert_eq!(first.sender, 1);
+        assert_eq!(first.msg_type, MessageType::P2P);
+        assert_eq!(first.msg, note("for 2"));
+        let second = parties[2].0.next().await.unwrap().unwrap();
+        assert_eq!((second.id, second.msg), (first.id + 1, note("for all")));
+
+        // Party 0 only sees the broadcast sent after the p2p message
+        let received = parties[0].0.next().await.unwrap().unwrap();
+        assert_eq!(received.sender, 1);
+        assert_eq!(received.msg, note("for all"));
+    }
+
+    #[tokio::test]
+    async fn p2p_messages_addressed_to_another_party_are_rejected() {
+        let (dialed, accepted) = socket_pair().await;
+        let (reader, _) = tokio::io::split(accepted);
+        let mut incoming = TcpIncoming::<Note>::new(1, vec![(0, reader)], &Progress::default());
+
+        // Party 0's link to party 1, mislabeled as its link to party 2
+        let (_, writer) = tokio::io::split(dialed);
+        let mut outgoing = TcpOutgoing::new(vec![(2, writer)], &Progress::default());
+        outgoing
+            .send(Outgoing::p2p(2, note("for 2")))
+            .await
+            .unwrap();
+
+        let error = incoming.next().await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn indices_beyond_u16_are_rejected() {
+        let (_, accepted) = socket_pair().await;
+        let (reader, _) = tokio::io::split(accepted);
+        let mut incoming =
+            TcpIncoming::<Note>::new(1 << 16, vec![(0, reader)], &Progress::default());
+
+        let error = incoming.next().await.unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
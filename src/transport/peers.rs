@@ -0,0 +1,57 @@
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use std::collections::BTreeMap;
+
+/// MPC listener address of every party, keyed by party index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerTable {
+    addrs: BTreeMap<u16, String>,
+}
+
+impl PeerTable {
+    /// Parses a comma-separated list of `<party>=<host:port>` entries,
+    /// e.g. `0=10.0.0.1:7001,1=10.0.0.2:7001,2=10.0.0.3:7001`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut addrs = BTreeMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (party, addr) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("peer entry {:?} is not <party>=<host:port>", entry))?;
+            let party: u16 = party
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid party index in peer entry {:?}", entry))?;
+            let addr = addr.trim();
+            ensure!(!addr.is_empty(), "peer entry {:?} has no address", entry);
+
+            if addrs.insert(party, addr.to_string()).is_some() {
+                bail!("party {} is listed more than once", party);
+            }
+        }
+        Ok(Self { addrs })
+    }
+
+    /// Checks that every party of an `n`-party deployment has an address.
+    pub fn validate(&self, n: u16) -> Result<()> {
+        let missing: Vec<u16> = (0..n).filter(|p| !self.addrs.contains_key(p)).collect();
+        ensure!(missing.is_empty(), "no address for parties {:?}", missing);
+
+        if let Some(extra) = self.addrs.keys().find(|&&p| p >= n) {
+            bail!("party {} is out of range for {} parties", extra, n);
+        }
+        Ok(())
+    }
+
+    /// Listener address of `party`.
+    pub fn addr(&self, party: u16) -> Result<&str> {
+        self.addrs
+            .get(&party)
+            .map(String::as_str)
+            .ok_or_else(|| anyhow!("no address for party {}", party))
+    }
+}
+
+/// Decides who opens the link between two parties: the higher index dials and the lower listens,
+/// so each pair agrees without coordination.
+pub fn dials(own: u16, peer: u16) -> bool {
+    own > peer
+}
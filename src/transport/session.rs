@@ -0,0 +1,567 @@
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::time::{Duration, Instant, sleep, timeout, timeout_at};
+use tracing::{debug, info, warn};
+
+use super::BoxedStream;
+use super::peers::{self, PeerTable};
+use super::tls::{self, NodeTls};
+use crate::progress::{Progress, ProgressEvent};
+
+/// Version of the handshake and the framing that follows it. Version 2 has signers exchange
+/// their share versions before the first signing round.
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// Upper bound on an encoded handshake, so a bogus length prefix cannot force a large allocation.
+const MAX_HANDSHAKE_LEN: u32 = 4096;
+
+/// How long an accepted connection may take to present its handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long connections for a session that nobody registered are kept around.
+const UNCLAIMED_TTL: Duration = Duration::from_secs(30);
+
+/// Most connections held for sessions that nobody registered yet, across all of them, so
+/// handshakes from an unauthenticated source cannot pile up sockets until they expire.
+const MAX_UNCLAIMED_LINKS: usize = 256;
+
+/// Pause between attempts to dial a peer that is not accepting connections yet.
+const DIAL_RETRY: Duration = Duration::from_millis(250);
+
+/// Protocol a connection is opened for.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtocolKind {
+    Keygen,
+    Sign,
+    Presign,
+    Refresh,
+}
+
+/// First frame on every MPC connection, identifying what the dialer wants to run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub version: u16,
+    pub kind: ProtocolKind,
+    /// Key session the protocol runs for
+    pub session: String,
+    /// Control-plane request id, so concurrent requests on one session get separate links
+    pub request_id: String,
+    /// Party index of the dialer
+    pub party: u16,
+}
+
+impl Handshake {
+    pub fn new(kind: ProtocolKind, session: &str, request_id: &str, party: u16) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            kind,
+            session: session.to_string(),
+            request_id: request_id.to_string(),
+            party,
+        }
+    }
+
+    fn key(&self) -> SessionKey {
+        (self.kind, self.session.clone(), self.request_id.clone())
+    }
+}
+
+/// Writes `handshake` as a length-prefixed frame.
+pub async fn write_handshake<S>(stream: &mut S, handshake: &Handshake) -> Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let data = bincode::serialize(handshake)?;
+    stream.write_u32(data.len() as u32).await?;
+    stream.write_all(&data).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Reads exactly one handshake frame, leaving any following bytes in the stream.
+pub async fn read_handshake<S>(stream: &mut S) -> Result<Handshake>
+where
+    S: AsyncRead + Unpin,
+{
+    let len = stream.read_u32().await?;
+    ensure!(
+        len <= MAX_HANDSHAKE_LEN,
+        "handshake frame of {} bytes exceeds the {} byte limit",
+        len,
+        MAX_HANDSHAKE_LEN
+    );
+
+    let mut data = vec![0u8; len as usize];
+    stream.read_exact(&mut data).await?;
+    let handshake: Handshake = bincode::deserialize(&data).context("malformed handshake")?;
+
+    ensure!(
+        handshake.version == PROTOCOL_VERSION,
+        "unsupported protocol version {} (expected {})",
+        handshake.version,
+        PROTOCOL_VERSION
+    );
+    Ok(handshake)
+}
+
+/// Dials `addr`, upgrades to TLS when configured and sends the handshake.
+///
+/// # Arguments
+/// * `addr` - Listener address of the peer
+/// * `tls` - Mutual TLS configuration, if enabled
+/// * `peer_id` - Party index of the node being dialed
+/// * `handshake` - Session this connection belongs to, with this node as `party`
+pub async fn connect_session(
+    addr: impl ToSocketAddrs,
+    tls: Option<&NodeTls>,
+    peer_id: u16,
+    handshake: &Handshake,
+) -> Result<BoxedStream> {
+    let socket = TcpStream::connect(addr).await?;
+    let mut stream = tls::connect_peer(tls, socket, peer_id).await?;
+    write_handshake(&mut stream, handshake).await?;
+    Ok(stream)
+}
+
+/// Opens one link to every other party in `parties` for the session named by `handshake`.
+/// Lower-index parties are dialed and higher-index parties are awaited on `pending`,
+/// following [`peers::dials`].
+///
+/// # Arguments
+/// * `pending` - The session, registered on this node's listener before any peer is dialed
+/// * `tls` - Mutual TLS configuration, if enabled
+/// * `peers` - Listener address of every party
+/// * `handshake` - Session to open, with this node as `party`
+/// * `parties` - Every party taking part in the protocol, this node included
+/// * `wait` - How long to keep dialing and waiting before giving up
+/// * `progress` - Receives each link as it opens
+pub async fn open_links(
+    pending: &mut PendingSession,
+    tls: Option<&NodeTls>,
+    peers: &PeerTable,
+    handshake: &Handshake,
+    parties: &[u16],
+    wait: Duration,
+    progress: &Progress,
+) -> Result<Vec<(u16, BoxedStream)>> {
+    let own = handshake.party;
+    let deadline = Instant::now() + wait;
+    let (to_dial, to_accept): (Vec<u16>, Vec<u16>) = parties
+        .iter()
+        .copied()
+        .filter(|&p| p != own)
+        .partition(|&p| peers::dials(own, p));
+
+    let dials = to_dial.iter().map(|&peer| async move {
+        let addr = peers.addr(peer)?;
+        let stream = dial_until(addr, tls, peer, handshake, deadline).await?;
+        progress.emit(ProgressEvent::PeerConnected { party: peer });
+        Ok::<_, anyhow::Error>((peer, stream))
+    });
+    let mut links = futures::future::try_join_all(dials).await?;
+
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    links.extend(pending.wait_for(&to_accept, remaining, progress).await?);
+    Ok(links)
+}
+
+/// Dials `addr` until it accepts or `deadline` passes, since the peer may still be starting up.
+async fn dial_until(
+    addr: &str,
+    tls: Option<&NodeTls>,
+    peer_id: u16,
+    handshake: &Handshake,
+    deadline: Instant,
+) -> Result<BoxedStream> {
+    loop {
+        match timeout_at(deadline, connect_session(addr, tls, peer_id, handshake)).await {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) if Instant::now() + DIAL_RETRY < deadline => {
+                debug!(
+                    "Dialing party {} at {} failed, retrying: {:#}",
+                    peer_id, addr, e
+                );
+                sleep(DIAL_RETRY).await;
+            }
+            Ok(Err(e)) => return Err(e.context(format!("could not reach party {}", peer_id))),
+            Err(_) => bail!("timed out dialing party {} at {}", peer_id, addr),
+        }
+    }
+}
+
+type SessionKey = (ProtocolKind, String, String);
+type Arrival = (u16, BoxedStream);
+
+/// Connections for one session, collected until the session is registered.
+struct Slot {
+    sender: UnboundedSender<Arrival>,
+    receiver: Option<UnboundedReceiver<Arrival>>,
+    opened: Instant,
+    /// Parties that connected before the session was registered
+    early: BTreeSet<u16>,
+}
+
+impl Slot {
+    fn new() -> Self {
+        let (sender, receiver) = unbounded_channel();
+        Self {
+            sender,
+            receiver: Some(receiver),
+            opened: Instant::now(),
+            early: BTreeSet::new(),
+        }
+    }
+
+    fn is_claimed(&self) -> bool {
+        self.receiver.is_none()
+    }
+}
+
+type Slots = Arc<Mutex<HashMap<SessionKey, Slot>>>;
+
+/// Accepts MPC connections on one address and hands each to the session named in its handshake,
+/// so concurrent sessions never pick up each other's peers.
+pub struct SessionListener {
+    slots: Slots,
+}
+
+impl SessionListener {
+    /// Binds `addr` and starts accepting connections in the background.
+    pub async fn bind(addr: impl ToSocketAddrs, tls: Option<NodeTls>) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Session listener active on {}", listener.local_addr()?);
+
+        let slots: Slots = Arc::new(Mutex::new(HashMap::new()));
+        tokio::spawn(accept_loop(listener, tls, slots.clone()));
+
+        Ok(Self { slots })
+    }
+
+    /// Claims the connections for a session, including any that arrived before this call.
+    pub fn register(
+        &self,
+        kind: ProtocolKind,
+        session: &str,
+        request_id: &str,
+    ) -> Result<PendingSession> {
+        let key = (kind, session.to_string(), request_id.to_string());
+        let mut slots = self.slots.lock().unwrap();
+        let slot = slots.entry(key.clone()).or_insert_with(Slot::new);
+        let receiver = slot.receiver.take().ok_or_else(|| {
+            anyhow!(
+                "{:?} session {} (request {:?}) is already in progress",
+                kind,
+                session,
+                request_id
+            )
+        })?;
+
+        Ok(PendingSession {
+            key,
+            receiver,
+            slots: self.slots.clone(),
+        })
+    }
+}
+
+/// A registered session waiting for its peers to connect.
+/// Dropping it releases the session so later connections for it are rejected.
+pub struct PendingSession {
+    key: SessionKey,
+    receiver: UnboundedReceiver<Arrival>,
+    slots: Slots,
+}
+
+impl PendingSession {
+    /// Waits until every party in `peers` has connected, returning one link per peer and
+    /// reporting each to `progress`. Connections from parties outside `peers`, or repeats,
+    /// are dropped.
+    pub async fn wait_for(
+        &mut self,
+        peers: &[u16],
+        wait: Duration,
+        progress: &Progress,
+    ) -> Result<Vec<Arrival>> {
+        let mut missing: BTreeSet<u16> = peers.iter().copied().collect();
+        let mut links = Vec::with_capacity(missing.len());
+        let deadline = Instant::now() + wait;
+
+        while !missing.is_empty() {
+            let (party, stream) = match timeout_at(deadline, self.receiver.recv()).await {
+                Ok(Some(arrival)) => arrival,
+                Ok(None) => bail!("session listener stopped"),
+                Err(_) => bail!(
+                    "timed out waiting for parties {:?} in session {}",
+                    missing,
+                    self.key.1
+                ),
+            };
+
+            if missing.remove(&party) {
+                progress.emit(ProgressEvent::PeerConnected { party });
+                links.push((party, stream));
+            } else {
+                warn!(
+                    "Dropping unexpected connection from party {} for session {}",
+                    party, self.key.1
+                );
+            }
+        }
+
+        Ok(links)
+    }
+}
+
+impl Drop for PendingSession {
+    fn drop(&mut self) {
+        self.slots.lock().unwrap().remove(&self.key);
+    }
+}
+
+async fn accept_loop(listener: TcpListener, tls: Option<NodeTls>, slots: Slots) {
+    loop {
+        let (socket, addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Session listener accept error: {:?}", e);
+                continue;
+            }
+        };
+
+        // Handshakes run off the accept loop so one slow peer cannot block the rest
+        let tls = tls.clone();
+        let slots = slots.clone();
+        tokio::spawn(async move {
+            match timeout(HANDSHAKE_TIMEOUT, accept_connection(tls.as_ref(), socket)).await {
+                Ok(Ok((handshake, stream))) => dispatch(&slots, handshake, stream),
+                Ok(Err(e)) => warn!("Rejected connection from {}: {:#}", addr, e),
+                Err(_) => warn!("Handshake from {} timed out", addr),
+            }
+        });
+    }
+}
+
+/// Runs TLS (if configured) and reads the handshake, checking the claimed party against the
+/// client certificate.
+async fn accept_connection(
+    tls: Option<&NodeTls>,
+    socket: TcpStream,
+) -> Result<(Handshake, BoxedStream)> {
+    match tls {
+        Some(tls) => {
+            let mut stream = tls.accept_any(socket).await?;
+            let handshake = read_handshake(&mut stream).await?;
+            tls::verify_client_identity(&stream, handshake.party)?;
+            Ok((handshake, Box::new(stream)))
+        }
+        None => {
+            let mut stream = socket;
+            let handshake = read_handshake(&mut stream).await?;
+            Ok((handshake, Box::new(stream)))
+        }
+    }
+}
+
+/// Hands a connection to its session. Connections for a session that is not registered yet
+/// are held for it, one per party and at most [`MAX_UNCLAIMED_LINKS`] in all.
+fn dispatch(slots: &Slots, handshake: Handshake, stream: BoxedStream) {
+    let mut slots = slots.lock().unwrap();
+    slots.retain(|_, slot| slot.is_claimed() || slot.opened.elapsed() < UNCLAIMED_TTL);
+
+    let key = handshake.key();
+    let claimed = slots.get(&key).is_some_and(Slot::is_claimed);
+    if !claimed {
+        let unclaimed: usize = slots
+            .values()
+            .filter(|slot| !slot.is_claimed())
+            .map(|slot| slot.early.len())
+            .sum();
+        if unclaimed >= MAX_UNCLAIMED_LINKS {
+            warn!(
+                "Dropping connection from party {} for unregistered {:?} session {}: {} connections already wait for a session",
+                handshake.party, handshake.kind, handshake.session, unclaimed
+            );
+            return;
+        }
+    }
+
+    let slot = slots.entry(key).or_insert_with(Slot::new);
+    if !claimed && !slot.early.insert(handshake.party) {
+        warn!(
+            "Dropping repeated connection from party {} for unregistered {:?} session {}",
+            handshake.party, handshake.kind, handshake.session
+        );
+        return;
+    }
+
+    debug!(
+        "Routing party {} to {:?} session {}",
+        handshake.party, handshake.kind, handshake.session
+    );
+    let _ = slot.sender.send((handshake.party, stream));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener() -> SessionListener {
+        SessionListener {
+            slots: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn link() -> BoxedStream {
+        Box::new(tokio::io::duplex(64).0)
+    }
+
+    fn handshake(session: &str, request_id: &str, party: u16) -> Handshake {
+        Handshake::new(ProtocolKind::Sign, session, request_id, party)
+    }
+
+    async fn parties(pending: &mut PendingSession, peers: &[u16]) -> Vec<u16> {
+        let links = pending
+            .wait_for(peers, Duration::from_millis(100), &Progress::default())
+            .await
+            .unwrap();
+        links.into_iter().map(|(party, _)| party).collect()
+    }
+
+    #[tokio::test]
+    async fn handshake_is_read_back_without_the_bytes_after_it() {
+        let (mut dialer, mut acceptor) = tokio::io::duplex(1024);
+        let sent = handshake("session-001", "7", 2);
+        write_handshake(&mut dialer, &sent).await.unwrap();
+        dialer.write_all(b"frame").await.unwrap();
+
+        assert_eq!(read_handshake(&mut acceptor).await.unwrap(), sent);
+        let mut rest = [0u8; 5];
+        acceptor.read_exact(&mut rest).await.unwrap();
+        assert_eq!(&rest, b"frame");
+    }
+
+    #[tokio::test]
+    async fn handshake_rejects_oversized_frames_and_other_versions() {
+        let (mut dialer, mut acceptor) = tokio::io::duplex(1024);
+        dialer.write_u32(MAX_HANDSHAKE_LEN + 1).await.unwrap();
+        assert!(read_handshake(&mut acceptor).await.is_err());
+
+        let (mut dialer, mut acceptor) = tokio::io::duplex(1024);
+        let mut old = handshake("session-001", "7", 2);
+        old.version = PROTOCOL_VERSION + 1;
+        write_handshake(&mut dialer, &old).await.unwrap();
+        assert!(read_handshake(&mut acceptor).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn connect_session_is_routed_by_its_handshake() {
+        let socket = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let sent = handshake("session-001", "7", 1);
+
+        let (dialed, accepted) = tokio::join!(connect_session(addr, None, 0, &sent), async {
+            accept_connection(None, socket.accept().await.unwrap().0).await
+        });
+        dialed.unwrap();
+        let (received, _) = accepted.unwrap();
+        assert_eq!(received, sent);
+    }
+
+    #[tokio::test]
+    async fn connections_that_arrive_early_are_handed_over_on_register() {
+        let listener = listener();
+        dispatch(&listener.slots, handshake("session-001", "7", 1), link());
+        dispatch(&listener.slots, handshake("session-001", "7", 2), link());
+
+        let mut pending = listener
+            .register(ProtocolKind::Sign, "session-001", "7")
+            .unwrap();
+        assert_eq!(parties(&mut pending, &[1, 2]).await, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn sessions_and_requests_do_not_share_connections() {
+        let listener = listener();
+        dispatch(&listener.slots, handshake("session-001", "7", 1), link());
+        dispatch(&listener.slots, handshake("session-001", "8", 2), link());
+        dispatch(&listener.slots, handshake("session-002", "7", 3), link());
+
+        let mut pending = listener
+            .register(ProtocolKind::Sign, "session-001", "7")
+            .unwrap();
+        assert_eq!(parties(&mut pending, &[1]).await, vec![1]);
+        let mut other = listener
+            .register(ProtocolKind::Sign, "session-001", "8")
+            .unwrap();
+        assert_eq!(parties(&mut other, &[2]).await, vec![2]);
+        assert!(
+            listener
+                .register(ProtocolKind::Keygen, "session-002", "7")
+                .unwrap()
+                .wait_for(&[3], Duration::from_millis(50), &Progress::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn a_session_is_registered_once() {
+        let listener = listener();
+        let pending = listener
+            .register(ProtocolKind::Sign, "session-001", "7")
+            .unwrap();
+        assert!(
+            listener
+                .register(ProtocolKind::Sign, "session-001", "7")
+                .is_err()
+        );
+
+        // Dropping the registration releases the session
+        drop(pending);
+        assert!(
+            listener
+                .register(ProtocolKind::Sign, "session-001", "7")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn unregistered_sessions_hold_a_bounded_number_of_connections() {
+        let listener = listener();
+        let registered = listener
+            .register(ProtocolKind::Sign, "session-001", "registered")
+            .unwrap();
+        for request in 0..MAX_UNCLAIMED_LINKS + 10 {
+            dispatch(
+                &listener.slots,
+                handshake("session-001", &request.to_string(), 1),
+                link(),
+            );
+        }
+        // A repeated party is not held twice either
+        dispatch(&listener.slots, handshake("session-001", "0", 1), link());
+
+        let slots = listener.slots.lock().unwrap();
+        let held: usize = slots
+            .values()
+            .filter(|slot| !slot.is_claimed())
+            .map(|slot| slot.early.len())
+            .sum();
+        assert_eq!(held, MAX_UNCLAIMED_LINKS);
+        drop(slots);
+
+        // Registered sessions still get their connections
+        dispatch(
+            &listener.slots,
+            handshake("session-001", "registered", 1),
+            link(),
+        );
+        let mut registered = registered;
+        assert!(registered.receiver.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,286 @@
+use anyhow::{Context, Result, anyhow, bail};
+use rustls::crypto::ring;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector, client, server};
+
+use super::BoxedStream;
+
+/// Name every node certificate must carry as a DNS subject alternative name,
+/// binding the certificate to the node's party index.
+pub fn node_name(id: u16) -> String {
+    format!("node-{}", id)
+}
+
+/// Mutual TLS configuration for the MPC links: this node's certificate and key,
+/// and the CA that issued every peer's certificate.
+#[derive(Clone)]
+pub struct NodeTls {
+    acceptor: TlsAcceptor,
+    connector: TlsConnector,
+}
+
+impl NodeTls {
+    /// Builds the configuration from PEM-encoded material.
+    ///
+    /// # Arguments
+    /// * `cert_chain_pem` - This node's certificate (and any intermediates), SAN `node-<NODE_ID>`
+    /// * `key_pem` - Private key for the certificate
+    /// * `ca_pem` - CA certificate(s) trusted to issue node certificates
+    pub fn from_pem(cert_chain_pem: &[u8], key_pem: &[u8], ca_pem: &[u8]) -> Result<Self> {
+        let cert_chain = rustls_pemfile::certs(&mut &*cert_chain_pem)
+            .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+            .context("invalid node certificate PEM")?;
+        if cert_chain.is_empty() {
+            bail!("no certificate found in node certificate PEM");
+        }
+
+        let key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut &*key_pem)
+            .context("invalid node key PEM")?
+            .ok_or_else(|| anyhow!("no private key found in node key PEM"))?;
+
+        let mut roots = RootCertStore::empty();
+        for ca in rustls_pemfile::certs(&mut &*ca_pem) {
+            roots.add(ca.context("invalid CA certificate PEM")?)?;
+        }
+        if roots.is_empty() {
+            bail!("no CA certificate found in CA PEM");
+        }
+        let roots = Arc::new(roots);
+
+        let provider = Arc::new(ring::default_provider());
+
+        let client_verifier =
+            WebPkiClientVerifier::builder_with_provider(roots.clone(), provider.clone()).build()?;
+        let server_config = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .with_client_cert_verifier(client_verifier)
+            .with_single_cert(cert_chain.clone(), key.clone_key())?;
+
+        let client_config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_client_auth_cert(cert_chain, key)?;
+
+        Ok(Self {
+            acceptor: TlsAcceptor::from(Arc::new(server_config)),
+            connector: TlsConnector::from(Arc::new(client_config)),
+        })
+    }
+
+    /// Loads PEM files from disk, see [`NodeTls::from_pem`].
+    pub fn load(
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+        ca_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let read = |path: &Path| {
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+        };
+        Self::from_pem(
+            &read(cert_path.as_ref())?,
+            &read(key_path.as_ref())?,
+            &read(ca_path.as_ref())?,
+        )
+    }
+
+    /// Opens a TLS session to `peer_id`, rejecting any server certificate not issued to that node.
+    pub async fn connect(
+        &self,
+        socket: TcpStream,
+        peer_id: u16,
+    ) -> Result<client::TlsStream<TcpStream>> {
+        let server_name = ServerName::try_from(node_name(peer_id))?;
+        self.connector
+            .connect(server_name, socket)
+            .await
+            .with_context(|| format!("TLS handshake with node {} failed", peer_id))
+    }
+
+    /// Accepts a TLS session from `peer_id`, rejecting any client certificate not issued to that node.
+    pub async fn accept(
+        &self,
+        socket: TcpStream,
+        peer_id: u16,
+    ) -> Result<server::TlsStream<TcpStream>> {
+        let stream = self.accept_any(socket).await?;
+        verify_client_identity(&stream, peer_id)?;
+        Ok(stream)
+    }
+
+    /// Accepts a TLS session from any node holding a certificate issued by the CA.
+    /// Callers must check the node identity with [`verify_client_identity`] once it is known.
+    pub async fn accept_any(&self, socket: TcpStream) -> Result<server::TlsStream<TcpStream>> {
+        self.acceptor
+            .accept(socket)
+            .await
+            .context("TLS handshake with incoming peer failed")
+    }
+}
+
+/// Checks that the client certificate on an accepted session was issued to `peer_id`.
+pub fn verify_client_identity(stream: &server::TlsStream<TcpStream>, peer_id: u16) -> Result<()> {
+    // The chain was already verified against the CA; check it belongs to the expected node
+    let leaf = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .ok_or_else(|| anyhow!("node {} presented no client certificate", peer_id))?;
+    verify_node_identity(leaf, peer_id)
+}
+
+/// Checks that `cert` carries the `node-<id>` subject alternative name.
+pub fn verify_node_identity(cert: &CertificateDer<'_>, id: u16) -> Result<()> {
+    let cert = webpki::EndEntityCert::try_from(cert)
+        .map_err(|e| anyhow!("invalid peer certificate: {:?}", e))?;
+    let name = ServerName::try_from(node_name(id))?;
+    cert.verify_is_valid_for_subject_name(&name)
+        .map_err(|_| anyhow!("peer certificate is not issued to {}", node_name(id)))
+}
+
+/// Dials a peer connection, upgrading it to mutual TLS when `tls` is configured.
+pub async fn connect_peer(
+    tls: Option<&NodeTls>,
+    socket: TcpStream,
+    peer_id: u16,
+) -> Result<BoxedStream> {
+    Ok(match tls {
+        Some(tls) => Box::new(tls.connect(socket, peer_id).await?),
+        None => Box::new(socket),
+    })
+}
+
+/// Loads mutual TLS when all three paths are set, and plain TCP when none are.
+pub fn load_optional(
+    cert_path: Option<&str>,
+    key_path: Option<&str>,
+    ca_path: Option<&str>,
+) -> Result<Option<NodeTls>> {
+    match (cert_path, key_path, ca_path) {
+        (Some(cert), Some(key), Some(ca)) => NodeTls::load(cert, key, ca).map(Some),
+        (None, None, None) => Ok(None),
+        _ => bail!("TLS_CERT, TLS_KEY and TLS_CA must be set together"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, IsCa, KeyPair};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn ca() -> CertifiedIssuer<'static, KeyPair> {
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap()
+    }
+
+    /// A certificate for `name` issued by `ca`, with its key, both PEM-encoded.
+    fn issue(ca: &CertifiedIssuer<'static, KeyPair>, name: &str) -> (String, String) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec![name.to_string()])
+            .unwrap()
+            .signed_by(&key, ca)
+            .unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+
+    fn node(ca: &CertifiedIssuer<'static, KeyPair>, name: &str) -> NodeTls {
+        let (cert, key) = issue(ca, name);
+        NodeTls::from_pem(cert.as_bytes(), key.as_bytes(), ca.pem().as_bytes()).unwrap()
+    }
+
+    async fn socket_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dialed = TcpStream::connect(listener.local_addr().unwrap());
+        let (dialed, accepted) = tokio::join!(dialed, listener.accept());
+        (dialed.unwrap(), accepted.unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn mutual_tls_round_trip() {
+        let ca = ca();
+        let (node0, node1) = (node(&ca, &node_name(0)), node(&ca, &node_name(1)));
+        let (dialed, accepted) = socket_pair().await;
+
+        let (client, server) = tokio::join!(node1.connect(dialed, 0), node0.accept(accepted, 1));
+        let (mut client, mut server) = (client.unwrap(), server.unwrap());
+
+        client.write_all(b"round 1").await.unwrap();
+        client.flush().await.unwrap();
+        let mut received = [0u8; 7];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"round 1");
+    }
+
+    #[tokio::test]
+    async fn accept_rejects_another_nodes_certificate() {
+        let ca = ca();
+        let (node0, node2) = (node(&ca, &node_name(0)), node(&ca, &node_name(2)));
+        let (dialed, accepted) = socket_pair().await;
+
+        // Node 2 dials in while node 0 expects node 1
+        let (_, server) = tokio::join!(node2.connect(dialed, 0), node0.accept(accepted, 1));
+        assert!(server.is_err());
+    }
+
+    #[tokio::test]
+    async fn accept_rejects_client_without_certificate() {
+        let ca = ca();
+        let node0 = node(&ca, &node_name(0));
+        let (dialed, accepted) = socket_pair().await;
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let anonymous = TlsConnector::from(Arc::new(config));
+
+        let (client, server) = tokio::join!(
+            async {
+                let mut stream = anonymous
+                    .connect(ServerName::try_from(node_name(0)).unwrap(), dialed)
+                    .await?;
+                // TLS 1.3 reports the missing certificate on the first read
+                stream.read_u8().await
+            },
+            node0.accept(accepted, 1)
+        );
+        assert!(server.is_err());
+        assert!(client.is_err());
+    }
+
+    #[test]
+    fn node_identity_follows_the_subject_alternative_name() {
+        let ca = ca();
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec![node_name(7)])
+            .unwrap()
+            .signed_by(&key, &ca)
+            .unwrap();
+
+        assert!(verify_node_identity(cert.der(), 7).is_ok());
+        assert!(verify_node_identity(cert.der(), 1).is_err());
+    }
+
+    #[test]
+    fn node_identity_rejects_other_names() {
+        let ca = ca();
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["node-1.example".to_string()])
+            .unwrap()
+            .signed_by(&key, &ca)
+            .unwrap();
+
+        assert!(verify_node_identity(cert.der(), 1).is_err());
+    }
+}